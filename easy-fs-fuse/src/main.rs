@@ -47,6 +47,15 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("extra")
+                .short("e")
+                .long("extra")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Dir of files copied as is, e.g. scripts or static Linux binaries"),
+        )
         .get_matches();
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
@@ -82,6 +91,19 @@ fn easy_fs_pack() -> std::io::Result<()> {
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
     }
+    for extra_path in matches.values_of("extra").into_iter().flatten() {
+        for dir_entry in read_dir(extra_path)? {
+            let path = dir_entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let mut all_data: Vec<u8> = Vec::new();
+            File::open(&path)?.read_to_end(&mut all_data)?;
+            let inode = root_inode.create(name.as_str()).unwrap();
+            inode.write_at(0, all_data.as_slice());
+        }
+    }
     // list apps
     for app in root_inode.ls() {
         println!("{}", app);
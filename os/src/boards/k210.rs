@@ -21,6 +21,15 @@ pub const MMIO: &[(usize, usize)] = &[
 
 pub type BlockDeviceImpl = crate::drivers::block::SDCardWrapper;
 
+/// K210 has 8MiB of SRAM and two harts, no device tree is consulted.
+pub fn memory_end() -> usize {
+    0x8080_0000 - crate::config::KTHREAD_AREA_SIZE
+}
+
+pub fn cpu_num() -> usize {
+    2
+}
+
 pub fn device_init() {
     unimplemented!();
 }
@@ -1,16 +1,64 @@
 pub const CLOCK_FREQ: usize = 12500000;
 
-pub const MMIO: &[(usize, usize)] = &[
-    (0x1000_0000, 0x1000),
-    (0x1000_1000, 0x1000),
-    (0xC00_0000, 0x40_0000),
-];
-
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
-pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
+pub type CharDeviceImpl = crate::drivers::chardev::NS16550a;
+
+use crate::config::{KTHREAD_AREA_SIZE, MAX_CPU_NUM};
+use crate::fdt::{MmioDevice, MACHINE_INFO};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const VIRTIO_MMIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_MMIO_DEVICE_ID: usize = 0x8;
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+lazy_static! {
+    /// Register windows of every device found in the device tree.
+    pub static ref MMIO: Vec<(usize, usize)> = {
+        let mut mmio = Vec::new();
+        for device in MACHINE_INFO.plic.iter().chain(MACHINE_INFO.uart.iter()) {
+            mmio.push((device.base, device.size));
+        }
+        for device in MACHINE_INFO.virtio.iter() {
+            mmio.push((device.base, device.size));
+        }
+        mmio
+    };
+    /// The virtio-mmio transport that has a block device behind it.
+    /// Probing reads device registers, so kernel space must be active.
+    static ref VIRTIO_BLK: MmioDevice = *MACHINE_INFO
+        .virtio
+        .iter()
+        .filter(|device| read(device.base) == VIRTIO_MMIO_MAGIC)
+        .find(|device| read(device.base + VIRTIO_MMIO_DEVICE_ID) == VIRTIO_DEVICE_BLOCK)
+        .expect("no virtio block device found");
+}
+
+/// End of the physical memory handed to the frame allocator.
+/// The top KTHREAD_AREA_SIZE bytes are kept for kernel thread stacks.
+pub fn memory_end() -> usize {
+    MACHINE_INFO.memory_base + MACHINE_INFO.memory_size - KTHREAD_AREA_SIZE
+}
 
-pub const VIRT_PLIC: usize = 0xC00_0000;
-pub const VIRT_UART: usize = 0x1000_0000;
+pub fn cpu_num() -> usize {
+    MACHINE_INFO.cpu_num.min(MAX_CPU_NUM)
+}
+
+fn uart() -> &'static MmioDevice {
+    MACHINE_INFO.uart.as_ref().expect("no ns16550a uart found")
+}
+
+fn plic_base() -> usize {
+    MACHINE_INFO.plic.as_ref().expect("no plic found").base
+}
+
+pub fn uart_base() -> usize {
+    uart().base
+}
+
+pub fn virtio_blk_base() -> usize {
+    VIRTIO_BLK.base
+}
 
 use crate::drivers::block::BLOCK_DEVICE;
 use crate::drivers::chardev::{CharDevice, UART};
@@ -51,10 +99,12 @@ pub fn irq_handler() {
     
     // which device interrupted?
     if let Some(irq) = plic_claim() {
-        match irq {
-            1 => BLOCK_DEVICE.handle_irq(),
-            10 => UART.handle_irq(),
-            _ => panic!("unsupported IRQ {}", irq),
+        if irq == VIRTIO_BLK.irq {
+            BLOCK_DEVICE.handle_irq();
+        } else if irq == uart().irq {
+            UART.handle_irq();
+        } else {
+            panic!("unsupported IRQ {}", irq);
         }
         // Tell the PLIC we've served the IRQ
         plic_complete(irq);
@@ -63,16 +113,6 @@ pub fn irq_handler() {
 
 
 use core::ptr;
-// qemu puts platform-level interrupt controller (PLIC) here.
-pub const PLIC_BASE: usize = 0x0c000000;
-
-/// qemu puts UART registers here in physical memory.
-pub const UART0:usize = 0x10000000;
-pub const UART0_IRQ: u32 = 10;
-
-/// virtio mmio interface
-pub const VIRTIO0:usize = 0x10001000;
-pub const VIRTIO0_IRQ: u32 = 1;
 
 
 pub fn device_init(){
@@ -81,8 +121,8 @@ pub fn device_init(){
 }
 
 pub fn plic_init() {
-    write(PLIC_BASE + (UART0_IRQ * 4) as usize, 1);
-    write(PLIC_BASE + (VIRTIO0_IRQ * 4) as usize, 1);
+    write(plic_base() + (uart().irq * 4) as usize, 1);
+    write(plic_base() + (VIRTIO_BLK.irq * 4) as usize, 1);
 }
 
 
@@ -92,8 +132,9 @@ pub fn plic_init_hart() {
     // println!("hart {} plic senable {:#x?}", hart_id, plic_senable(hart_id));
     // println!("hart {} plic spriority {:#x?}", hart_id, plic_spriority(hart_id));
 
-    // Set UART's enable bit for this hart's S-mode. 
-    write(plic_senable(hart_id), (1 << UART0_IRQ) | (1 << VIRTIO0_IRQ));
+    // Set UART's and virtio-blk's enable bits for this hart's S-mode. 
+    plic_enable(hart_id, uart().irq);
+    plic_enable(hart_id, VIRTIO_BLK.irq);
 
     // Set this hart's S-mode pirority threshold to 0. 
     write(plic_spriority(hart_id), 0);
@@ -104,15 +145,20 @@ pub fn plic_init_hart() {
 }
 
 fn plic_senable(hart_id: usize) -> usize {
-    PLIC_BASE + 0x2080 + hart_id * 0x100
+    plic_base() + 0x2080 + hart_id * 0x100
+}
+
+fn plic_enable(hart_id: usize, irq: u32) {
+    let addr = plic_senable(hart_id) + (irq / 32) as usize * 4;
+    write(addr, read(addr) | (1 << (irq % 32)));
 }
 
 fn plic_spriority(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201000 + hart_id * 0x2000
+    plic_base() + 0x201000 + hart_id * 0x2000
 }
 
 fn plic_sclaim(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201004 + hart_id * 0x2000
+    plic_base() + 0x201004 + hart_id * 0x2000
 }
 
 /// Ask the PLIC what interrupt we should serve. 
@@ -3,15 +3,31 @@
 pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
-pub const MEMORY_END: usize = 0x81000000;
+/// Physical memory at the top of RAM reserved for kthread trap contexts and stacks.
+pub const KTHREAD_AREA_SIZE: usize = 0x40_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
+/// Where position independent executables are loaded, plus a random offset.
+pub const ELF_DYN_BASE: usize = 0x4000_0000;
+/// Base of the user stacks, one slot per thread above it.
+pub const USER_STACK_BASE: usize = 0x8_0000_0000;
+/// Threads per process, bounds the stack and trap context slots.
+pub const MAX_THREADS: usize = 256;
+/// Lowest address used for shared memory and other kernel-chosen user mappings.
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+
+/// End of user space, the lower half of Sv39. `VirtAddr` keeps 39 bits, so
+/// higher user addresses would wrap onto the trap contexts and trampoline.
+pub const USER_SPACE_END: usize = 1 << 38;
+
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 
-pub use crate::board::{CLOCK_FREQ, MMIO};
+pub use crate::board::{cpu_num, memory_end, CLOCK_FREQ, MMIO};
 
-pub const CPU_NUM: usize = 4;
+/// Upper bound of harts the kernel can drive; boot stacks are sized for it,
+/// see the `global_asm!` of entry.asm in main.rs.
+pub const MAX_CPU_NUM: usize = 8;
 
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
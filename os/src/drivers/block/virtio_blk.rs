@@ -5,6 +5,7 @@ use crate::mm::{
 };
 use crate::sync::{Condvar, UPIntrFreeCell};
 use crate::task::schedule;
+use crate::board::virtio_blk_base;
 use crate::DEV_NON_BLOCKING_ACCESS;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
@@ -12,9 +13,6 @@ use lazy_static::*;
 use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
 use lock::Mutex;
 
-#[allow(unused)]
-const VIRTIO0: usize = 0x10001000;
-
 pub struct VirtIOBlock {
     virtio_blk: UPIntrFreeCell<VirtIOBlk<'static>>,
     condvars: BTreeMap<u16, Condvar>,
@@ -50,7 +48,7 @@ impl BlockDevice for VirtIOBlock {
 impl VirtIOBlock {
     pub fn new() -> Self {
         let virtio_blk = unsafe {
-            UPIntrFreeCell::new(VirtIOBlk::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap())
+            UPIntrFreeCell::new(VirtIOBlk::new(&mut *(virtio_blk_base() as *mut VirtIOHeader)).unwrap())
         };
         let mut condvars = BTreeMap::new();
         let channels = virtio_blk.exclusive_access().virt_queue_size();
@@ -126,15 +126,15 @@ struct NS16550aInner {
     read_buffer: VecDeque<u8>,
 }
 
-pub struct NS16550a<const BASE_ADDR: usize> {
+pub struct NS16550a {
     inner: Mutex<NS16550aInner>,
     condvar: Condvar,
 }
 
-impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
-    pub fn new() -> Self {
+impl NS16550a {
+    pub fn new(base_addr: usize) -> Self {
         let mut inner = NS16550aInner {
-            ns16550a: NS16550aRaw::new(BASE_ADDR),
+            ns16550a: NS16550aRaw::new(base_addr),
             read_buffer: VecDeque::new(),
         };
         inner.ns16550a.init();
@@ -145,7 +145,7 @@ impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
     }
 }
 
-impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
+impl CharDevice for NS16550a {
     fn read(&self) -> u8 {
         loop {
             let mut inner = self.inner.lock();
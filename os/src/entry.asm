@@ -2,6 +2,10 @@
     .globl _start
 _start:
     # a0: hart id
+    # no boot stack or processor slot past MAX_CPU_NUM harts, park the hart
+    # before it touches any memory
+    li t0, {max_cpu_num}
+    bgeu a0, t0, park
     mv tp, a0
     la sp, boot_stack
     # li t1, 4096 * 16 # t1 = 4096 * 16 64KB
@@ -9,10 +13,14 @@ _start:
     slli t0, t0, 16 # 64K * (hartid + 1)
     add sp, sp, t0  # sp = sp + t0
     call rust_main
+park:
+    wfi
+    j park
 
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16 * 4
+    # 64KB for each of MAX_CPU_NUM harts
+    .space 4096 * 16 * {max_cpu_num}
     .globl boot_stack_top
 boot_stack_top:
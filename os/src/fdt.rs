@@ -0,0 +1,259 @@
+//! A minimal flattened device tree (FDT) parser.
+//!
+//! Only what the kernel needs at boot is extracted: the physical memory
+//! range, the number of harts, the PLIC, NS16550a UART and virtio-mmio
+//! devices together with their interrupt numbers, and the boot arguments
+//! and random seed from `/chosen`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+#[derive(Copy, Clone, Debug)]
+pub struct MmioDevice {
+    pub base: usize,
+    pub size: usize,
+    pub irq: u32,
+}
+
+#[derive(Debug)]
+pub struct MachineInfo {
+    pub memory_base: usize,
+    pub memory_size: usize,
+    pub cpu_num: usize,
+    pub plic: Option<MmioDevice>,
+    pub uart: Option<MmioDevice>,
+    pub virtio: Vec<MmioDevice>,
+    /// `/chosen/bootargs`, empty if absent
+    pub bootargs: String,
+    /// `/chosen/rng-seed` folded into 64 bits, 0 if absent
+    pub rng_seed: u64,
+}
+
+static DEVICE_TREE_PADDR: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    pub static ref MACHINE_INFO: MachineInfo =
+        MachineInfo::parse(DEVICE_TREE_PADDR.load(Ordering::Acquire));
+}
+
+/// Parse the device tree handed over by SBI.
+///
+/// Must run on the boot hart after the heap is ready and before the frame
+/// allocator or kernel space are set up, since both depend on the result.
+pub fn init(device_tree_paddr: usize) {
+    DEVICE_TREE_PADDR.store(device_tree_paddr, Ordering::Release);
+    lazy_static::initialize(&MACHINE_INFO);
+    let info = &*MACHINE_INFO;
+    println!(
+        "[kernel] fdt@{:#x}: memory [{:#x}, {:#x}), {} cpus, {} virtio-mmio slots",
+        device_tree_paddr,
+        info.memory_base,
+        info.memory_base + info.memory_size,
+        info.cpu_num,
+        info.virtio.len()
+    );
+}
+
+struct Blob {
+    base: usize,
+}
+
+impl Blob {
+    fn be32(&self, offset: usize) -> u32 {
+        unsafe { u32::from_be(((self.base + offset) as *const u32).read()) }
+    }
+    fn bytes(&self, offset: usize, len: usize) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts((self.base + offset) as *const u8, len) }
+    }
+    /// Null-terminated string starting at `offset`, without the terminator.
+    fn cstr(&self, offset: usize) -> &'static [u8] {
+        let len = (0usize..)
+            .find(|i| unsafe { ((self.base + offset + *i) as *const u8).read() } == 0)
+            .unwrap();
+        self.bytes(offset, len)
+    }
+}
+
+struct NodeState {
+    name: &'static [u8],
+    /// cells used by the `reg` of child nodes
+    address_cells: usize,
+    size_cells: usize,
+    reg: &'static [u8],
+    irq: Option<u32>,
+    compatible: &'static [u8],
+    device_type: &'static [u8],
+    disabled: bool,
+    bootargs: &'static [u8],
+    rng_seed: &'static [u8],
+}
+
+impl NodeState {
+    fn new(name: &'static [u8]) -> Self {
+        Self {
+            name,
+            address_cells: 2,
+            size_cells: 1,
+            reg: &[],
+            irq: None,
+            compatible: &[],
+            device_type: &[],
+            disabled: false,
+            bootargs: &[],
+            rng_seed: &[],
+        }
+    }
+    fn is_compatible(&self, name: &[u8]) -> bool {
+        self.compatible.split(|b| *b == 0).any(|c| c == name)
+    }
+    /// First (address, size) pair of `reg`, decoded with the parent's cells.
+    fn first_reg(&self, address_cells: usize, size_cells: usize) -> Option<(usize, usize)> {
+        if self.reg.len() < (address_cells + size_cells) * 4 {
+            return None;
+        }
+        let (addr, size) = self.reg.split_at(address_cells * 4);
+        Some((read_cells(addr, address_cells), read_cells(size, size_cells)))
+    }
+}
+
+fn be32_of(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_cells(bytes: &[u8], cells: usize) -> usize {
+    bytes[..cells * 4]
+        .chunks(4)
+        .fold(0usize, |acc, cell| (acc << 32) | be32_of(cell) as usize)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+impl MachineInfo {
+    fn parse(device_tree_paddr: usize) -> Self {
+        let blob = Blob {
+            base: device_tree_paddr,
+        };
+        assert_eq!(
+            blob.be32(0),
+            FDT_MAGIC,
+            "invalid device tree at {:#x}",
+            device_tree_paddr
+        );
+        let struct_offset = blob.be32(8) as usize;
+        let strings_offset = blob.be32(12) as usize;
+
+        let mut info = Self {
+            memory_base: 0,
+            memory_size: 0,
+            cpu_num: 0,
+            plic: None,
+            uart: None,
+            virtio: Vec::new(),
+            bootargs: String::new(),
+            rng_seed: 0,
+        };
+        let mut stack: Vec<NodeState> = Vec::new();
+        let mut offset = struct_offset;
+        loop {
+            let token = blob.be32(offset);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = blob.cstr(offset);
+                    offset = align4(offset + name.len() + 1);
+                    stack.push(NodeState::new(name));
+                }
+                FDT_END_NODE => {
+                    let node = stack.pop().unwrap();
+                    let (address_cells, size_cells) = stack
+                        .last()
+                        .map(|parent| (parent.address_cells, parent.size_cells))
+                        .unwrap_or((2, 1));
+                    if stack.len() == 1 && node.name == b"chosen" {
+                        info.add_chosen(&node);
+                    }
+                    info.add_node(&node, address_cells, size_cells);
+                }
+                FDT_PROP => {
+                    let len = blob.be32(offset) as usize;
+                    let name_offset = blob.be32(offset + 4) as usize;
+                    let value = blob.bytes(offset + 8, len);
+                    offset = align4(offset + 8 + len);
+                    let node = stack.last_mut().unwrap();
+                    match blob.cstr(strings_offset + name_offset) {
+                        b"#address-cells" => node.address_cells = be32_of(value) as usize,
+                        b"#size-cells" => node.size_cells = be32_of(value) as usize,
+                        b"reg" => node.reg = value,
+                        b"interrupts" => node.irq = Some(be32_of(value)),
+                        b"compatible" => node.compatible = value,
+                        b"device_type" => node.device_type = value,
+                        b"status" => node.disabled = value.starts_with(b"disabled"),
+                        b"bootargs" => node.bootargs = value,
+                        b"rng-seed" => node.rng_seed = value,
+                        _ => {}
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => panic!("invalid device tree token {:#x} at {:#x}", token, offset - 4),
+            }
+        }
+        assert!(info.memory_size != 0, "no memory node in device tree");
+        assert!(info.cpu_num != 0, "no cpu node in device tree");
+        info
+    }
+
+    /// Copy what is needed, the blob may be overwritten after boot.
+    fn add_chosen(&mut self, node: &NodeState) {
+        let bootargs = node.bootargs.split(|b| *b == 0).next().unwrap_or(&[]);
+        self.bootargs = String::from_utf8_lossy(bootargs).into_owned();
+        self.rng_seed = node
+            .rng_seed
+            .iter()
+            .fold(0u64, |acc, b| acc.rotate_left(8) ^ *b as u64);
+    }
+
+    fn add_node(&mut self, node: &NodeState, address_cells: usize, size_cells: usize) {
+        if node.disabled {
+            return;
+        }
+        let reg = node.first_reg(address_cells, size_cells);
+        if node.device_type.starts_with(b"memory") {
+            if let Some((base, size)) = reg {
+                if self.memory_size == 0 {
+                    self.memory_base = base;
+                    self.memory_size = size;
+                }
+            }
+        } else if node.device_type.starts_with(b"cpu") {
+            self.cpu_num += 1;
+        } else if let Some((base, size)) = reg {
+            let device = MmioDevice {
+                base,
+                size,
+                irq: node.irq.unwrap_or(0),
+            };
+            if node.is_compatible(b"riscv,plic0") || node.is_compatible(b"sifive,plic-1.0.0") {
+                self.plic = Some(device);
+            } else if node.is_compatible(b"ns16550a") {
+                if self.uart.is_none() {
+                    self.uart = Some(device);
+                }
+            } else if node.is_compatible(b"virtio,mmio") {
+                self.virtio.push(device);
+            }
+        }
+    }
+}
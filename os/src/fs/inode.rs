@@ -42,6 +42,13 @@ impl OSInode {
         }
         v
     }
+    /// Kernel side counterpart of `File::write`, false if the disk is full.
+    pub fn write_all(&self, data: &[u8]) -> bool {
+        let mut inner = self.inner.lock();
+        let write_size = inner.inode.write_at(inner.offset, data);
+        inner.offset += write_size;
+        write_size == data.len()
+    }
 }
 
 lazy_static! {
@@ -1,16 +1,31 @@
 mod inode;
+mod mqueue;
 mod pipe;
 mod stdio;
 
 use crate::mm::UserBuffer;
+use crate::sync::SyncObject;
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Terminals answer the termios ioctls.
+    fn is_tty(&self) -> bool {
+        false
+    }
+    /// Handles of sync objects give theirs to `sync_import`.
+    fn sync_object(&self) -> Option<SyncObject> {
+        None
+    }
+    /// Message queue fds answer the mq_* calls.
+    fn mqueue(&self) -> Option<&MqDescriptor> {
+        None
+    }
 }
 
 pub use inode::{list_apps, open_file, OSInode, OpenFlags};
+pub use mqueue::{mq_open, mq_unlink, MqDescriptor, MqError, MqFlags};
 pub use pipe::{make_pipe, Pipe};
 pub use stdio::{Stdin, Stdout};
@@ -0,0 +1,316 @@
+//! POSIX-style message queues. Messages keep their boundaries and carry a
+//! priority, a receiver gets the oldest message of the highest priority.
+//! A queue is opened by name from any process and used through an fd; the
+//! name stays taken until it is unlinked, the queue lives on until the last
+//! fd referring to it is closed.
+
+use super::File;
+use crate::mm::UserBuffer;
+use crate::sync::NAME_MAX;
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use crate::timer::{block_current_with_timeout, get_time_ms, remove_waiter, TimedWait};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+use lock::{Mutex, MutexGuard};
+
+/// Priorities go from 0 to `MQ_PRIO_MAX - 1`, higher ones are received first.
+pub const MQ_PRIO_MAX: usize = 32;
+pub const MQ_MAXMSG_MAX: usize = 64;
+pub const MQ_MSGSIZE_MAX: usize = 4096;
+/// the size of a queue created without one
+pub const MQ_MAXMSG_DEFAULT: usize = 10;
+pub const MQ_MSGSIZE_DEFAULT: usize = 256;
+
+bitflags! {
+    pub struct MqFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        /// fail if the name is already in use
+        const EXCL = 1 << 7;
+        /// create the queue if the name is unused
+        const CREATE = 1 << 9;
+        /// fail with `WouldBlock` instead of waiting
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MqError {
+    /// a bad name, size, priority or access mode
+    Invalid,
+    /// no queue has the name, and CREATE was not given
+    NotFound,
+    /// CREATE and EXCL were given, but the name is taken
+    Exists,
+    /// the message is larger than the queue allows, or the buffer for
+    /// receiving smaller
+    MsgSize,
+    /// the queue is full or empty, and the fd does not block
+    WouldBlock,
+    TimedOut,
+}
+
+struct Message {
+    priority: usize,
+    data: Vec<u8>,
+}
+
+pub struct MessageQueue {
+    max_msgs: usize,
+    msg_size: usize,
+    inner: Mutex<MessageQueueInner>,
+}
+
+pub struct MessageQueueInner {
+    /// by priority, the highest first, and by age within one priority
+    messages: VecDeque<Message>,
+    senders: VecDeque<Arc<TaskControlBlock>>,
+    receivers: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl MessageQueue {
+    fn new(max_msgs: usize, msg_size: usize) -> Self {
+        Self {
+            max_msgs,
+            msg_size,
+            inner: unsafe {
+                Mutex::new(MessageQueueInner {
+                    messages: VecDeque::new(),
+                    senders: VecDeque::new(),
+                    receivers: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Queue the current task as a sender or receiver and block it until it
+    /// is woken or `deadline` passes. False once it has, with the task off
+    /// the queue again.
+    fn wait(
+        self: &Arc<Self>,
+        mut inner: MutexGuard<MessageQueueInner>,
+        sending: bool,
+        deadline: Option<usize>,
+    ) -> bool {
+        let timeout_ms = match deadline {
+            Some(deadline) => match deadline.checked_sub(get_time_ms()) {
+                Some(timeout_ms) if timeout_ms > 0 => Some(timeout_ms),
+                _ => return false,
+            },
+            None => None,
+        };
+        let task = current_task().unwrap();
+        if sending {
+            inner.senders.push_back(task);
+        } else {
+            inner.receivers.push_back(task);
+        }
+        drop(inner);
+        match timeout_ms {
+            Some(timeout_ms) => block_current_with_timeout(self.clone(), timeout_ms),
+            None => {
+                block_current_and_run_next();
+                true
+            }
+        }
+    }
+
+    /// A woken task checks again, whoever was faster may have taken the
+    /// message or the free slot it was woken for.
+    fn send(
+        self: &Arc<Self>,
+        data: Vec<u8>,
+        priority: usize,
+        nonblocking: bool,
+        deadline: Option<usize>,
+    ) -> Result<(), MqError> {
+        if data.len() > self.msg_size {
+            return Err(MqError::MsgSize);
+        }
+        if priority >= MQ_PRIO_MAX {
+            return Err(MqError::Invalid);
+        }
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.messages.len() < self.max_msgs {
+                let idx = inner
+                    .messages
+                    .iter()
+                    .position(|message| message.priority < priority)
+                    .unwrap_or(inner.messages.len());
+                inner.messages.insert(idx, Message { priority, data });
+                if let Some(task) = inner.receivers.pop_front() {
+                    add_task(task);
+                }
+                return Ok(());
+            }
+            if nonblocking {
+                return Err(MqError::WouldBlock);
+            }
+            if !self.wait(inner, true, deadline) {
+                return Err(MqError::TimedOut);
+            }
+        }
+    }
+
+    /// Returns the message and its priority.
+    fn receive(
+        self: &Arc<Self>,
+        buf_len: usize,
+        nonblocking: bool,
+        deadline: Option<usize>,
+    ) -> Result<(Vec<u8>, usize), MqError> {
+        if buf_len < self.msg_size {
+            return Err(MqError::MsgSize);
+        }
+        loop {
+            let mut inner = self.inner.lock();
+            if let Some(message) = inner.messages.pop_front() {
+                if let Some(task) = inner.senders.pop_front() {
+                    add_task(task);
+                }
+                return Ok((message.data, message.priority));
+            }
+            if nonblocking {
+                return Err(MqError::WouldBlock);
+            }
+            if !self.wait(inner, false, deadline) {
+                return Err(MqError::TimedOut);
+            }
+        }
+    }
+}
+
+impl TimedWait for MessageQueue {
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = self.inner.lock();
+        remove_waiter(&mut inner.senders, task) || remove_waiter(&mut inner.receivers, task)
+    }
+}
+
+/// What an fd returned by `mq_open` refers to: the queue, and how this
+/// opening may use it.
+pub struct MqDescriptor {
+    queue: Arc<MessageQueue>,
+    readable: bool,
+    writable: bool,
+    nonblocking: bool,
+}
+
+impl MqDescriptor {
+    /// Wait for room for at most `timeout_ms` milliseconds unless that is 0.
+    pub fn send(&self, data: Vec<u8>, priority: usize, timeout_ms: usize) -> Result<(), MqError> {
+        let deadline = (timeout_ms != 0).then(|| get_time_ms() + timeout_ms);
+        self.queue.send(data, priority, self.nonblocking, deadline)
+    }
+
+    /// Wait for a message like `send` waits for room. `buf_len` must be at
+    /// least the queue's message size.
+    pub fn receive(&self, buf_len: usize, timeout_ms: usize) -> Result<(Vec<u8>, usize), MqError> {
+        let deadline = (timeout_ms != 0).then(|| get_time_ms() + timeout_ms);
+        self.queue.receive(buf_len, self.nonblocking, deadline)
+    }
+}
+
+/// `read` and `write` receive and send a message of priority 0. A failure
+/// returns `usize::MAX`, -1 to the caller, 0 bytes would read as end of file.
+impl File for MqDescriptor {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        match self.receive(buf.len(), 0) {
+            Ok((data, _)) => {
+                for (byte, &src) in buf.into_iter().zip(data.iter()) {
+                    unsafe {
+                        *byte = src;
+                    }
+                }
+                data.len()
+            }
+            Err(_) => usize::MAX,
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let data: Vec<u8> = buf.into_iter().map(|byte| unsafe { *byte }).collect();
+        let len = data.len();
+        match self.send(data, 0, 0) {
+            Ok(()) => len,
+            Err(_) => usize::MAX,
+        }
+    }
+    fn mqueue(&self) -> Option<&MqDescriptor> {
+        Some(self)
+    }
+}
+
+lazy_static! {
+    static ref MQ_REGISTRY: Mutex<BTreeMap<String, Arc<MessageQueue>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Open the queue called `name`, creating one for `max_msgs` messages of
+/// up to `msg_size` bytes if allowed, 0 asks for the default. The sizes
+/// are ignored when the queue exists already.
+pub fn mq_open(
+    name: &str,
+    flags: MqFlags,
+    max_msgs: usize,
+    msg_size: usize,
+) -> Result<Arc<MqDescriptor>, MqError> {
+    if name.is_empty() || name.len() > NAME_MAX || flags.contains(MqFlags::WRONLY | MqFlags::RDWR) {
+        return Err(MqError::Invalid);
+    }
+    let mut registry = MQ_REGISTRY.lock();
+    let queue = match registry.get(name) {
+        Some(_) if flags.contains(MqFlags::CREATE | MqFlags::EXCL) => return Err(MqError::Exists),
+        Some(queue) => Arc::clone(queue),
+        None => {
+            if !flags.contains(MqFlags::CREATE) {
+                return Err(MqError::NotFound);
+            }
+            let max_msgs = if max_msgs == 0 {
+                MQ_MAXMSG_DEFAULT
+            } else {
+                max_msgs
+            };
+            let msg_size = if msg_size == 0 {
+                MQ_MSGSIZE_DEFAULT
+            } else {
+                msg_size
+            };
+            if max_msgs > MQ_MAXMSG_MAX || msg_size > MQ_MSGSIZE_MAX {
+                return Err(MqError::Invalid);
+            }
+            let queue = Arc::new(MessageQueue::new(max_msgs, msg_size));
+            registry.insert(String::from(name), Arc::clone(&queue));
+            queue
+        }
+    };
+    Ok(Arc::new(MqDescriptor {
+        queue,
+        readable: !flags.contains(MqFlags::WRONLY),
+        writable: flags.intersects(MqFlags::WRONLY | MqFlags::RDWR),
+        nonblocking: flags.contains(MqFlags::NONBLOCK),
+    }))
+}
+
+/// Free the name, fds open on the queue keep working.
+pub fn mq_unlink(name: &str) -> Result<(), MqError> {
+    if name.is_empty() || name.len() > NAME_MAX {
+        return Err(MqError::Invalid);
+    }
+    MQ_REGISTRY
+        .lock()
+        .remove(name)
+        .map(|_| ())
+        .ok_or(MqError::NotFound)
+}
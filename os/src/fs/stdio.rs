@@ -12,8 +12,11 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
+    /// One character at a time, whatever the size of the buffer.
     fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
+        if user_buf.len() == 0 {
+            return 0;
+        }
         // println!("before UART.read() in Stdin::read()");
         let ch = UART.read();
         unsafe {
@@ -24,6 +27,9 @@ impl File for Stdin {
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn is_tty(&self) -> bool {
+        true
+    }
 }
 
 impl File for Stdout {
@@ -42,4 +48,7 @@ impl File for Stdout {
         }
         user_buf.len()
     }
+    fn is_tty(&self) -> bool {
+        true
+    }
 }
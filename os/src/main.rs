@@ -2,6 +2,7 @@
 #![no_main]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
+#![feature(asm_const)]
 
 extern crate alloc;
 
@@ -23,9 +24,11 @@ pub mod console;
 
 mod config;
 mod drivers;
+mod fdt;
 mod fs;
 mod lang_items;
 mod mm;
+mod random;
 mod sbi;
 mod sync;
 mod syscall;
@@ -34,7 +37,11 @@ mod timer;
 mod trap;
 
 
-core::arch::global_asm!(include_str!("entry.asm"));
+// parks harts and sizes the boot stacks by MAX_CPU_NUM
+core::arch::global_asm!(
+    include_str!("entry.asm"),
+    max_cpu_num = const config::MAX_CPU_NUM,
+);
 
 fn clear_bss() {
     extern "C" {
@@ -72,6 +79,9 @@ pub fn rust_main(hart_id: usize, device_tree_paddr: usize) -> ! {
 
     if hart_id == 0 {
         clear_bss();
+        mm::init_heap();
+        fdt::init(device_tree_paddr);
+        random::init();
         mm::init();
         thread_local_init();
         board::device_init();
@@ -83,7 +93,8 @@ pub fn rust_main(hart_id: usize, device_tree_paddr: usize) -> ! {
     
         BOOTED_CPU_NUM.fetch_add(1, Ordering::Release);
         AP_CAN_INIT.store(true, Ordering::Relaxed);
-    }else{
+    } else {
+        // entry.asm parks harts from MAX_CPU_NUM on
         init_other_cpu();
     }
     
@@ -138,7 +149,7 @@ pub fn thread_local_init() {
 }
 
 fn wait_all_cpu_started() {
-    while BOOTED_CPU_NUM.load(Ordering::Acquire) < crate::config::CPU_NUM {
+    while BOOTED_CPU_NUM.load(Ordering::Acquire) < crate::config::cpu_num() {
         spin_loop();
     }
 }
\ No newline at end of file
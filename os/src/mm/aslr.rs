@@ -0,0 +1,64 @@
+//! Address space layout randomization.
+//!
+//! Every new image gets a random stack, heap and mmap base, position
+//! independent ones a random load address too; fork and threads keep the
+//! layout of their parent. Boot with `norandmaps` in the
+//! kernel command line for the fixed layout.
+
+use crate::config::{ELF_DYN_BASE, MMAP_BASE, PAGE_SIZE, USER_STACK_BASE};
+use crate::fdt::MACHINE_INFO;
+use crate::random::rand_below;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Span of each randomized base, in pages.
+const STACK_RANDOM_PAGES: usize = 1 << 18;
+const HEAP_RANDOM_PAGES: usize = 1 << 13;
+const MMAP_RANDOM_PAGES: usize = 1 << 20;
+const DYN_RANDOM_PAGES: usize = 1 << 16;
+
+static ASLR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn init() {
+    if MACHINE_INFO
+        .bootargs
+        .split_whitespace()
+        .any(|arg| arg == "norandmaps")
+    {
+        ASLR_ENABLED.store(false, Ordering::Relaxed);
+        println!("[kernel] address space randomization disabled");
+    }
+}
+
+fn random_offset(pages: usize) -> usize {
+    if ASLR_ENABLED.load(Ordering::Relaxed) {
+        rand_below(pages) * PAGE_SIZE
+    } else {
+        0
+    }
+}
+
+/// Load address of a position independent executable.
+pub fn load_bias() -> usize {
+    ELF_DYN_BASE + random_offset(DYN_RANDOM_PAGES)
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AddressLayout {
+    /// user stacks are stacked upwards from here
+    pub stack_base: usize,
+    /// start of the heap, right above the image when not randomized
+    pub heap_base: usize,
+    /// lowest address of kernel-chosen mappings
+    pub mmap_base: usize,
+}
+
+impl AddressLayout {
+    /// `image_end` is the page aligned end of the loaded ELF.
+    pub fn new(image_end: usize) -> Self {
+        Self {
+            stack_base: USER_STACK_BASE + random_offset(STACK_RANDOM_PAGES),
+            heap_base: image_end + random_offset(HEAP_RANDOM_PAGES),
+            mmap_base: MMAP_BASE + random_offset(MMAP_RANDOM_PAGES),
+        }
+    }
+}
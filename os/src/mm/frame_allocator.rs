@@ -1,5 +1,5 @@
 use super::{PhysAddr, PhysPageNum};
-use crate::config::MEMORY_END;
+use crate::config::memory_end;
 use crate::sync::UPSafeCell;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
@@ -51,6 +51,9 @@ impl StackFrameAllocator {
         self.end = r.0;
         println!("last {} Physical Frames.", self.end - self.current);
     }
+    fn free(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
@@ -95,7 +98,7 @@ pub fn init_frame_allocator() {
     
     FRAME_ALLOCATOR.exclusive_access().init(
         PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END + 0x400000).floor(),
+        PhysAddr::from(memory_end()).floor(),
     );
 }
 
@@ -110,6 +113,11 @@ pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
+/// How many frames `frame_alloc` could still hand out.
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free()
+}
+
 #[allow(unused)]
 pub fn frame_allocator_test() {
     let mut v: Vec<FrameTracker> = Vec::new();
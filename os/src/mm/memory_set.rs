@@ -1,19 +1,29 @@
-use super::{frame_alloc, FrameTracker};
+use super::aslr::{self, AddressLayout};
+use super::{frame_alloc, FrameTracker, SharedMemory};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, USER_STACK_SIZE, TRAP_CONTEXT};
+use crate::config::{
+    memory_end, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_SPACE_END, USER_STACK_BASE,
+    USER_STACK_SIZE,
+};
 use crate::sync::UPSafeCell;
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
+use core::mem::size_of;
+use xmas_elf::header;
+use xmas_elf::program::{ProgramHeader64, Type};
+use xmas_elf::ElfFile;
 use lazy_static::*;
+use lock::Mutex;
 use riscv::register::satp;
 
 use crate::task::{
-    ustack_bottom_from_pid,
-    trap_cx_bottom_from_pid,
+    ustack_bottom_from_tid,
+    trap_cx_bottom_from_tid,
 };
 
 extern "C" {
@@ -38,9 +48,70 @@ pub fn kernel_token() -> usize {
     KERNEL_SPACE.exclusive_access().token()
 }
 
+/// The pages of [start, start + len) if `start` is page aligned and the
+/// range lies in user space, to check addresses from syscalls with.
+pub fn user_page_range(start: usize, len: usize) -> Option<(VirtPageNum, VirtPageNum)> {
+    let end = start.checked_add(len)?;
+    if start % PAGE_SIZE != 0 || end > USER_SPACE_END {
+        return None;
+    }
+    Some((VirtAddr::from(start).floor(), VirtAddr::from(end).ceil()))
+}
+
+/// Why a user mapping could not be established.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MapError {
+    /// the address space would grow beyond its `RLimit`, or has no thread
+    /// slot left
+    LimitExceeded,
+    /// no physical frame is left
+    OutOfMemory,
+    /// the range overlaps an existing mapping
+    Overlap,
+    /// the file is not a well-formed RISC-V executable
+    InvalidElf,
+}
+
+/// Soft and hard limit in bytes, laid out like the Linux `struct rlimit`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct RLimit {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+impl RLimit {
+    pub const INFINITY: Self = Self {
+        rlim_cur: RLIM_INFINITY,
+        rlim_max: RLIM_INFINITY,
+    };
+}
+
+/// The address space of a process, shared by its threads.
+pub type SharedMemorySet = Arc<Mutex<MemorySet>>;
+
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// bounds the resident pages of the user areas, of all threads together
+    mem_limit: RLimit,
+    layout: AddressLayout,
+    /// end of the heap, which starts at `layout.heap_base`
+    brk: usize,
+}
+
+/// Where the kernel loaded an image, for the auxiliary vector.
+#[derive(Clone, Debug)]
+pub struct ElfInfo {
+    pub entry: usize,
+    /// address of the program headers in the loaded image
+    pub phdr: usize,
+    pub phent: usize,
+    pub phnum: usize,
+    /// the dynamic loader named by PT_INTERP
+    pub interp: Option<String>,
 }
 
 impl MemorySet {
@@ -48,11 +119,39 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            mem_limit: RLimit::INFINITY,
+            layout: AddressLayout::default(),
+            brk: 0,
         }
     }
+    fn try_new_bare() -> Result<Self, MapError> {
+        Ok(Self {
+            page_table: PageTable::try_new().ok_or(MapError::OutOfMemory)?,
+            areas: Vec::new(),
+            mem_limit: RLimit::INFINITY,
+            layout: AddressLayout::default(),
+            brk: 0,
+        })
+    }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// Pages backed by frames, shared ones included; without demand paging
+    /// this is both the resident and the mapped size of the address space.
+    pub fn resident_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| area.map_type != MapType::Identical)
+            .map(|area| area.data_frames.len())
+            .sum()
+    }
+    pub fn mem_limit(&self) -> RLimit {
+        self.mem_limit
+    }
+    /// A limit below the current usage only prevents further growth.
+    pub fn set_mem_limit(&mut self, limit: RLimit) {
+        self.mem_limit = limit;
+    }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
         &mut self,
@@ -88,12 +187,193 @@ impl MemorySet {
             self.areas.remove(idx);
         }
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Unmap the stack and trap context of a joined thread so that its tid
+    /// can be handed out again.
+    pub fn remove_thread_areas(&mut self, tid: usize) {
+        let user_stack_bottom = ustack_bottom_from_tid(self.layout.stack_base, tid);
+        self.remove_area_with_start_vpn(VirtAddr::from(user_stack_bottom).floor());
+        self.remove_area_with_start_vpn(VirtAddr::from(trap_cx_bottom_from_tid(tid)).floor());
+    }
+    /// [start, end) and permission of the areas user code can reach.
+    pub fn user_areas(&self) -> Vec<(VirtPageNum, VirtPageNum, MapPermission)> {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end(), area.map_perm))
+            .collect()
+    }
+    /// Return true if no area intersects [start_vpn, end_vpn).
+    pub fn is_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        !self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+        })
+    }
+    /// Map zeroed pages to [start_vpn, end_vpn), which must be free.
+    pub fn map_anonymous(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        perm: MapPermission,
+    ) -> Result<(), MapError> {
+        if !self.is_free(start_vpn, end_vpn) {
+            return Err(MapError::Overlap);
+        }
+        self.try_push(
+            MapArea::new(
+                start_vpn.into(),
+                end_vpn.into(),
+                MapType::Framed,
+                perm | MapPermission::U,
+            ),
+            None,
+        )
+    }
+    /// Unmap the user pages in [start_vpn, end_vpn), splitting the areas
+    /// crossing the boundaries. Holes are skipped, kernel-only areas such as
+    /// trap contexts make the whole call fail.
+    pub fn unmap_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        if start_vpn >= end_vpn {
+            return false;
+        }
+        if self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end_vpn
+                && start_vpn < area.vpn_range.get_end()
+                && !area.map_perm.contains(MapPermission::U)
+        }) {
+            return false;
+        }
+        for vpn in [start_vpn, end_vpn] {
+            let mut splitted = Vec::new();
+            for area in self.areas.iter_mut() {
+                if area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end() {
+                    splitted.push(area.split_off(vpn));
+                }
+            }
+            self.areas.append(&mut splitted);
+        }
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            if start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn {
+                area.unmap(page_table);
+                false
+            } else {
+                true
+            }
+        });
+        true
+    }
+    pub fn heap_base(&self) -> usize {
+        self.layout.heap_base
+    }
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+    /// Grow or shrink the heap, `new_brk` must not be below the heap base.
+    pub fn set_brk(&mut self, new_brk: usize) -> Result<(), MapError> {
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if new_end > old_end {
+            self.map_anonymous(old_end, new_end, MapPermission::R | MapPermission::W)?;
+        } else if new_end < old_end {
+            self.unmap_range(new_end, old_end);
+        }
+        self.brk = new_brk;
+        Ok(())
+    }
+    /// Copy `data` to `va`, the pages must be mapped. Unlike the
+    /// `translated_*` helpers this works before the space is activated.
+    pub fn write_bytes(&self, va: usize, data: &[u8]) {
+        let mut written = 0;
+        while written < data.len() {
+            let va = VirtAddr::from(va + written);
+            let offset = va.page_offset();
+            let len = (PAGE_SIZE - offset).min(data.len() - written);
+            let ppn = self.translate(va.floor()).unwrap().ppn();
+            ppn.get_bytes_array()[offset..offset + len]
+                .copy_from_slice(&data[written..written + len]);
+            written += len;
+        }
+    }
+    /// Find `pages` free pages at or above the mmap base, first fit.
+    /// None if user space ends before.
+    pub fn find_free_area(&self, pages: usize) -> Option<VirtPageNum> {
+        let mut start = VirtAddr::from(self.layout.mmap_base).floor();
+        let last = VirtAddr::from(USER_SPACE_END).floor();
+        loop {
+            if last.0 - start.0.min(last.0) < pages {
+                return None;
+            }
+            let end = VirtPageNum(start.0 + pages);
+            match self
+                .areas
+                .iter()
+                .find(|area| area.vpn_range.get_start() < end && start < area.vpn_range.get_end())
+            {
+                Some(area) => start = area.vpn_range.get_end(),
+                None => return Some(start),
+            }
+        }
+    }
+    /// Map the whole shared memory object into a free range, return its start.
+    pub fn map_shared(
+        &mut self,
+        shm: &Arc<SharedMemory>,
+        permission: MapPermission,
+    ) -> Result<VirtAddr, MapError> {
+        let start_vpn = self
+            .find_free_area(shm.pages())
+            .ok_or(MapError::LimitExceeded)?;
+        self.try_push(MapArea::new_shared(start_vpn, shm, permission), None)?;
+        Ok(start_vpn.into())
+    }
+    /// Unmap the shared mapping starting at `start_vpn` together with the
+    /// pieces `mprotect` split off it. Return false if none starts there.
+    pub fn unmap_shared(&mut self, start_vpn: VirtPageNum) -> bool {
+        let shm = match self.areas.iter().find(|area| {
+            area.map_type == MapType::Shared && area.vpn_range.get_start() == start_vpn
+        }) {
+            Some(area) => Arc::clone(area.shm.as_ref().unwrap()),
+            None => return false,
+        };
+        let end_vpn = VirtPageNum(start_vpn.0 + shm.pages());
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            let piece = area
+                .shm
+                .as_ref()
+                .map_or(false, |other| Arc::ptr_eq(other, &shm))
+                && start_vpn <= area.vpn_range.get_start()
+                && area.vpn_range.get_end() <= end_vpn;
+            if piece {
+                area.unmap(page_table);
+            }
+            !piece
+        });
+        true
+    }
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) {
+        self.try_push(map_area, data).unwrap();
+    }
+    /// Like `push`, but charge user areas against `mem_limit` and leave
+    /// nothing mapped if the area does not fit.
+    fn try_push(
+        &mut self,
+        mut map_area: MapArea,
+        data: Option<&[u8]>,
+    ) -> Result<(), MapError> {
+        if map_area.map_type != MapType::Identical {
+            let pages = map_area.vpn_range.get_end().0 - map_area.vpn_range.get_start().0;
+            let limit = self.mem_limit.rlim_cur / PAGE_SIZE;
+            if self.resident_pages() + pages > limit {
+                return Err(MapError::LimitExceeded);
+            }
+        }
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        Ok(())
     }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
@@ -160,14 +440,14 @@ impl MemorySet {
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
-                MEMORY_END.into(),
+                memory_end().into(),
                 MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
             None,
         );
         println!("mapping memory-mapped registers");
-        for pair in MMIO {
+        for pair in MMIO.iter() {
             memory_set.push(
                 MapArea::new(
                     (*pair).0.into(),
@@ -180,62 +460,46 @@ impl MemorySet {
         }
         memory_set
     }
-    /// Include sections in elf and trampoline,
-    /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8], pid: usize) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// Include sections in elf and trampoline, the stack and trap context
+    /// of the main thread (tid 0), also returns user_sp_base and `ElfInfo`.
+    /// Position independent executables are loaded at a random bias, a
+    /// PT_INTERP is only reported, see `load_interp`.
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, ElfInfo), MapError> {
+        let elf = parse_elf(elf_data)?;
+        let mut memory_set = Self::try_new_bare()?;
         // map trampoline
         memory_set.map_trampoline();
+        let bias = match elf.header.pt2.type_().as_type() {
+            header::Type::SharedObject => aslr::load_bias(),
+            _ => 0,
+        };
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
-        // let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                // max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
-            }
-        }        
+        let (elf_info, image_end, exec_stack) =
+            memory_set.load_segments(&elf, bias, USER_STACK_BASE)?;
+        memory_set.layout = AddressLayout::new(image_end);
+        memory_set.brk = memory_set.layout.heap_base;
         // map user stack with U flags
-        let user_stack_bottom = ustack_bottom_from_pid(pid);
+        let user_stack_bottom = ustack_bottom_from_tid(memory_set.layout.stack_base, 0);
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let mut stack_perm = MapPermission::R | MapPermission::W | MapPermission::U;
+        if exec_stack {
+            stack_perm |= MapPermission::X;
+        }
         //map user stack
-        // println!("mapping user stack bottom {:#x?}  top:{:#x?}", user_stack_bottom, user_stack_top);
-        memory_set.push(
+        memory_set.try_push(
             MapArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
                 MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
+                stack_perm,
             ),
             None,
-        );
+        )?;
 
 
-        let trap_cx_bottom = trap_cx_bottom_from_pid(pid);
+        let trap_cx_bottom = trap_cx_bottom_from_tid(0);
         let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
-        memory_set.push(
+        memory_set.try_push(
             MapArea::new(
                 trap_cx_bottom.into(),
                 trap_cx_top.into(),
@@ -243,31 +507,126 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-
-
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid).into();
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(trap_cx_bottom_va).into())
-            .unwrap()
-            .ppn();
+        )?;
 
-        // println!("new tcb/exec trap_cx_ppn: {:#x?}", trap_cx_ppn);
-
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        Ok((memory_set, user_stack_top, elf_info))
     }
-    pub fn from_existed_user(user_space: &MemorySet, pid:usize) -> (Self, usize) {
-        let mut memory_set = Self::new_bare();
+    /// Map a dynamic loader into a free range above the mmap base,
+    /// return its load bias and entry point.
+    pub fn load_interp(&mut self, elf_data: &[u8]) -> Result<(usize, usize), MapError> {
+        let elf = parse_elf(elf_data)?;
+        if elf.header.pt2.type_().as_type() != header::Type::SharedObject {
+            return Err(MapError::InvalidElf);
+        }
+        let (start, end) = load_span(&elf)?;
+        let start_vpn = VirtAddr::from(start).floor();
+        let pages = VirtAddr::from(end).ceil().0 - start_vpn.0;
+        let free_vpn = self.find_free_area(pages).ok_or(MapError::LimitExceeded)?;
+        let bias = VirtAddr::from(free_vpn).0 - VirtAddr::from(start_vpn).0;
+        let (elf_info, _, _) = self.load_segments(&elf, bias, USER_SPACE_END)?;
+        // a loader that needs a loader itself is not supported
+        if elf_info.interp.is_some() {
+            return Err(MapError::InvalidElf);
+        }
+        Ok((bias, elf_info.entry))
+    }
+    /// Map the PT_LOAD segments of a checked ELF shifted by `bias`, all
+    /// below `limit`. Returns the `ElfInfo`, the end of the image and
+    /// whether PT_GNU_STACK asks for an executable stack.
+    fn load_segments(
+        &mut self,
+        elf: &ElfFile,
+        bias: usize,
+        limit: usize,
+    ) -> Result<(ElfInfo, usize, bool), MapError> {
+        let elf_header = elf.header;
+        let ph_offset = elf_header.pt2.ph_offset() as usize;
+        let (_, span_end) = load_span(elf)?;
+        if bias.checked_add(span_end).map_or(true, |end| end > limit) {
+            return Err(MapError::InvalidElf);
+        }
+        let mut image_end = 0;
+        let mut phdr = 0;
+        let mut interp = None;
+        let mut exec_stack = false;
+        for ph in elf.program_iter() {
+            let offset = ph.offset() as usize;
+            let file_size = ph.file_size() as usize;
+            match ph.get_type() {
+                Ok(Type::Phdr) => phdr = ph.virtual_addr() as usize + bias,
+                Ok(Type::Interp) => {
+                    let path = &elf.input[offset..offset + file_size];
+                    let path = path.strip_suffix(&[0]).unwrap_or(path);
+                    let path = core::str::from_utf8(path).map_err(|_| MapError::InvalidElf)?;
+                    interp = Some(String::from(path));
+                }
+                Ok(Type::OsSpecific(PT_GNU_STACK)) => exec_stack = ph.flags().is_execute(),
+                // libc sets up the static TLS block itself, from AT_PHDR
+                Ok(Type::Tls) => {
+                    let align = ph.align() as usize;
+                    if file_size > ph.mem_size() as usize
+                        || (align != 0 && !align.is_power_of_two())
+                    {
+                        return Err(MapError::InvalidElf);
+                    }
+                }
+                Ok(Type::Load) if ph.mem_size() > 0 => {
+                    // the segment holding the file header also holds the program headers
+                    if phdr == 0 && offset <= ph_offset && ph_offset < offset + file_size {
+                        phdr = ph.virtual_addr() as usize + bias + ph_offset - offset;
+                    }
+                    let start = ph.virtual_addr() as usize + bias;
+                    let start_va: VirtAddr = start.into();
+                    let end_va: VirtAddr = (start + ph.mem_size() as usize).into();
+                    if !self.is_free(start_va.floor(), end_va.ceil()) {
+                        return Err(MapError::InvalidElf);
+                    }
+                    let mut map_perm = MapPermission::U;
+                    let ph_flags = ph.flags();
+                    if ph_flags.is_read() {
+                        map_perm |= MapPermission::R;
+                    }
+                    if ph_flags.is_write() {
+                        map_perm |= MapPermission::W;
+                    }
+                    if ph_flags.is_execute() {
+                        map_perm |= MapPermission::X;
+                    }
+                    let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                    image_end = image_end.max(VirtAddr::from(map_area.vpn_range.get_end()).0);
+                    self.try_push(map_area, None)?;
+                    // segments need not start at a page boundary
+                    self.write_bytes(start_va.0, &elf.input[offset..offset + file_size]);
+                }
+                _ => {}
+            }
+        }
+        let elf_info = ElfInfo {
+            entry: elf_header.pt2.entry_point() as usize + bias,
+            phdr,
+            phent: elf_header.pt2.ph_entry_size() as usize,
+            phnum: elf_header.pt2.ph_count() as usize,
+            interp,
+        };
+        Ok((elf_info, image_end, exec_stack))
+    }
+    /// Copy of the address space for fork, the child continues as `tid`.
+    pub fn from_existed_user(user_space: &MemorySet, tid: usize) -> Result<(Self, usize), MapError> {
+        let mut memory_set = Self::try_new_bare()?;
+        memory_set.mem_limit = user_space.mem_limit;
+        memory_set.layout = user_space.layout;
+        memory_set.brk = user_space.brk;
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
+            if area.map_type == MapType::Shared {
+                // shared memory is mapped to the same frames, not copied
+                memory_set.try_push(area.clone(), None)?;
+                continue;
+            }
             let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
+            memory_set.try_push(new_area, None)?;
             // copy data from another space
             for vpn in area.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
@@ -278,64 +637,22 @@ impl MemorySet {
             }
         }
 
-        // map user stack with U flags
-        let user_stack_bottom = ustack_bottom_from_pid(pid);
-        //map user stack
+        // the forking thread's stack and trap context were copied above
+        let user_stack_bottom = ustack_bottom_from_tid(memory_set.layout.stack_base, tid);
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
 
-        // println!("mapping user stack bottom {:#x?}  top:{:#x?}", user_stack_bottom, user_stack_top);
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
-        );
-
-        let trap_cx_bottom = trap_cx_bottom_from_pid(pid);
-        let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
-
-        memory_set.push(
-            MapArea::new(
-                trap_cx_bottom.into(),
-                trap_cx_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W ,
-            ),
-            None,
-        );
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid).into();
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(trap_cx_bottom_va).into())
-            .unwrap()
-            .ppn();
-
-        // println!("fork trap_cx_ppn: {:#x?}", trap_cx_ppn);
-
-        (
+        Ok((
             memory_set,
             user_stack_top,
-        )
+        ))
     }
 
-    pub fn from_existed(user_space: &MemorySet, pid:usize) -> (Self, usize) {
-    
-        let copy_areas = user_space.areas.clone();
-
-        let mut memory_set = Self{
-            page_table: PageTable::from_token(user_space.token()),
-            areas: copy_areas,
-        };
-
-        // map user stack with U flags
-        let user_stack_bottom = ustack_bottom_from_pid(pid);
+    /// Map the user stack and trap context of a new thread `tid`, return
+    /// the top of its user stack.
+    pub fn map_thread(&mut self, tid: usize) -> Result<usize, MapError> {
+        let user_stack_bottom = ustack_bottom_from_tid(self.layout.stack_base, tid);
         let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        
-        //map user stack
-        // println!("mapping user stack bottom {:#x?}  top:{:#x?}", user_stack_bottom, user_stack_top);
-        memory_set.push(
+        self.try_push(
             MapArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
@@ -343,30 +660,72 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
 
-        let trap_cx_bottom = trap_cx_bottom_from_pid(pid);
+        let trap_cx_bottom = trap_cx_bottom_from_tid(tid);
         let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
-        memory_set.push(
+        if let Err(err) = self.try_push(
             MapArea::new(
                 trap_cx_bottom.into(),
                 trap_cx_top.into(),
                 MapType::Framed,
-                MapPermission::R | MapPermission::W ,
+                MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-
-        // let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid).into();
-        // let trap_cx_ppn = memory_set
-        //     .translate(VirtAddr::from(trap_cx_bottom_va).into())
-        //     .unwrap()
-        //     .ppn();
-        // println!("new user thread trap_cx_ppn: {:#x?}", trap_cx_ppn);
-        (
-            memory_set,
-            user_stack_top,
-        )
+        ) {
+            // do not leave the stack behind
+            self.remove_area_with_start_vpn(VirtAddr::from(user_stack_bottom).floor());
+            return Err(err);
+        }
+        Ok(user_stack_top)
+    }
+    /// Change the permission of the pages in [start_vpn, end_vpn), `perm`
+    /// without R/W/X is PROT_NONE. Areas crossing the range boundaries are
+    /// split first. Return false if any page in the range is not covered by
+    /// a user area.
+    pub fn mprotect(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        perm: MapPermission,
+    ) -> bool {
+        if start_vpn >= end_vpn {
+            return false;
+        }
+        let mut covered = 0;
+        for area in self.areas.iter() {
+            let l = area.vpn_range.get_start().max(start_vpn);
+            let r = area.vpn_range.get_end().min(end_vpn);
+            if l < r {
+                if !area.map_perm.contains(MapPermission::U) {
+                    return false;
+                }
+                covered += r.0 - l.0;
+            }
+        }
+        if covered != end_vpn.0 - start_vpn.0 {
+            return false;
+        }
+        for vpn in [start_vpn, end_vpn] {
+            let mut splitted = Vec::new();
+            for area in self.areas.iter_mut() {
+                if area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end() {
+                    splitted.push(area.split_off(vpn));
+                }
+            }
+            self.areas.append(&mut splitted);
+        }
+        let perm = perm | MapPermission::U;
+        let pte_flags = leaf_flags(perm) | PTEFlags::V;
+        for area in self.areas.iter_mut().filter(|area| {
+            start_vpn <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end_vpn
+        }) {
+            area.map_perm = perm;
+            for vpn in area.vpn_range {
+                self.page_table.set_flags(vpn, pte_flags);
+            }
+        }
+        true
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -388,6 +747,9 @@ impl MemorySet {
         Self {
             page_table: PageTable::from_token(kernel_token()),
             areas: areas,
+            mem_limit: RLimit::INFINITY,
+            layout: AddressLayout::default(),
+            brk: 0,
         }
     }
 }
@@ -395,9 +757,11 @@ impl MemorySet {
 #[derive(Clone)]
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// keeps a shared memory object alive while it is mapped
+    shm: Option<Arc<SharedMemory>>,
 }
 
 impl MapArea {
@@ -414,6 +778,26 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            shm: None,
+        }
+    }
+    pub fn new_shared(
+        start_vpn: VirtPageNum,
+        shm: &Arc<SharedMemory>,
+        map_perm: MapPermission,
+    ) -> Self {
+        let data_frames = shm
+            .frames()
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| (VirtPageNum(start_vpn.0 + i), Arc::clone(frame)))
+            .collect();
+        Self {
+            vpn_range: VPNRange::new(start_vpn, VirtPageNum(start_vpn.0 + shm.pages())),
+            data_frames,
+            map_type: MapType::Shared,
+            map_perm,
+            shm: Some(Arc::clone(shm)),
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -422,33 +806,65 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            shm: None,
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// Split this area at `vpn`: keep [start, vpn) and return [vpn, end).
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> MapArea {
+        let end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        Self {
+            vpn_range: VPNRange::new(vpn, end),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            shm: self.shm.clone(),
+        }
+    }
+    pub fn map_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), MapError> {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(MapError::OutOfMemory)?;
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
+            }
+            MapType::Shared => {
+                ppn = self.data_frames.get(&vpn).unwrap().ppn;
             }
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        if !page_table.try_map(vpn, ppn, leaf_flags(self.map_perm)) {
+            if self.map_type == MapType::Framed {
+                self.data_frames.remove(&vpn);
+            }
+            return Err(MapError::OutOfMemory);
+        }
+        Ok(())
     }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
+        if self.map_type != MapType::Identical {
             self.data_frames.remove(&vpn);
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// On failure the pages mapped so far are unmapped again.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), MapError> {
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if let Err(err) = self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
         }
+        Ok(())
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
@@ -479,10 +895,93 @@ impl MapArea {
     }
 }
 
+/// The leaf pte flags for `map_perm`, without V. RISC-V reserves W without
+/// R, so W comes with R. A user page without R/W/X (PROT_NONE) stays a
+/// valid leaf, but one only the kernel may read: with no R/W/X at all the
+/// hardware would take it for a pointer to the next level.
+fn leaf_flags(map_perm: MapPermission) -> PTEFlags {
+    let mut flags = PTEFlags::from_bits(map_perm.bits).unwrap();
+    if flags.contains(PTEFlags::W) {
+        flags |= PTEFlags::R;
+    }
+    if !flags.intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X) {
+        flags = PTEFlags::R;
+    }
+    flags
+}
+
+/// e_machine of RISC-V.
+const EM_RISCV: u16 = 243;
+const PT_GNU_STACK: u32 = 0x6474_e551;
+
+/// Reject anything but a well-formed 64 bit little endian RISC-V
+/// executable or shared object, so that loading it cannot panic.
+fn parse_elf(elf_data: &[u8]) -> Result<ElfFile, MapError> {
+    let elf = ElfFile::new(elf_data).map_err(|_| MapError::InvalidElf)?;
+    let elf_header = elf.header;
+    // read e_machine directly, older xmas-elf has no name for RISC-V
+    let machine = u16::from_le_bytes([elf_data[18], elf_data[19]]);
+    if elf_header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46]
+        || elf_header.pt1.class() != header::Class::SixtyFour
+        || elf_header.pt1.data() != header::Data::LittleEndian
+        || machine != EM_RISCV
+    {
+        return Err(MapError::InvalidElf);
+    }
+    match elf_header.pt2.type_().as_type() {
+        header::Type::Executable | header::Type::SharedObject => {}
+        _ => return Err(MapError::InvalidElf),
+    }
+    // the program headers and every segment must lie within the file
+    let ph_entry_size = elf_header.pt2.ph_entry_size() as usize;
+    let ph_end = (elf_header.pt2.ph_count() as usize)
+        .checked_mul(ph_entry_size)
+        .and_then(|size| size.checked_add(elf_header.pt2.ph_offset() as usize));
+    if ph_entry_size != size_of::<ProgramHeader64>()
+        || ph_end.map_or(true, |end| end > elf_data.len())
+    {
+        return Err(MapError::InvalidElf);
+    }
+    for ph in elf.program_iter() {
+        let file_end = ph.offset().checked_add(ph.file_size());
+        if ph.get_type().is_err() || file_end.map_or(true, |end| end > elf_data.len() as u64) {
+            return Err(MapError::InvalidElf);
+        }
+    }
+    Ok(elf)
+}
+
+/// [lowest, highest) address of the PT_LOAD segments, unbiased.
+/// Segments must be sorted, not overlap and fit into memory.
+fn load_span(elf: &ElfFile) -> Result<(usize, usize), MapError> {
+    let mut start = usize::MAX;
+    let mut end = 0;
+    for ph in elf.program_iter() {
+        if ph.get_type() != Ok(Type::Load) || ph.mem_size() == 0 {
+            continue;
+        }
+        let seg_start = ph.virtual_addr() as usize;
+        let seg_end = seg_start
+            .checked_add(ph.mem_size() as usize)
+            .ok_or(MapError::InvalidElf)?;
+        if ph.file_size() > ph.mem_size() || seg_start < end {
+            return Err(MapError::InvalidElf);
+        }
+        start = start.min(seg_start);
+        end = seg_end;
+    }
+    if end == 0 {
+        return Err(MapError::InvalidElf);
+    }
+    Ok((start, end))
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
     Framed,
+    /// frames owned by a shared memory object
+    Shared,
 }
 
 bitflags! {
@@ -1,23 +1,31 @@
 mod address;
+mod aslr;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
 
 use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_dealloc, free_frames, FrameTracker};
+pub use heap_allocator::init_heap;
 pub use memory_set::remap_test;
-pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE};
+pub use memory_set::{
+    kernel_token, user_page_range, ElfInfo, MapError, MapPermission, MemorySet, RLimit,
+    SharedMemorySet, KERNEL_SPACE, RLIM_INFINITY,
+};
+pub use shm::{shm_open, SharedMemory, ShmError, ShmFlags};
 use page_table::PTEFlags;
 pub use page_table::{
     translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
     PageTableEntry, UserBuffer, UserBufferIterator,
 };
 
+/// The heap must already be initialized, see `init_heap`.
 pub fn init() {
-    heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
+    aslr::init();
     KERNEL_SPACE.exclusive_access().activate();
 }
 
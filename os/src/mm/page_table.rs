@@ -50,6 +50,9 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
 }
 
 pub struct PageTable {
@@ -57,14 +60,17 @@ pub struct PageTable {
     frames: Vec<FrameTracker>,
 }
 
-/// Assume that it won't oom when creating/mapping.
+/// `map` assumes that it won't oom, user mappings go through `try_map`.
 impl PageTable {
     pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+        Self::try_new().unwrap()
+    }
+    pub fn try_new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
@@ -84,7 +90,7 @@ impl PageTable {
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -115,12 +121,29 @@ impl PageTable {
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
+    /// Return false if a frame for the page table itself cannot be allocated.
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        match self.find_pte_create(vpn) {
+            Some(pte) => {
+                assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+                *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+                true
+            }
+            None => false,
+        }
+    }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Rewrite the flags of a leaf entry while keeping its ppn.
+    /// The caller decides whether the entry stays valid.
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        *pte = PageTableEntry::new(pte.ppn(), flags);
+    }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).map(|pte| *pte)
     }
@@ -0,0 +1,86 @@
+//! Shared memory objects identified by a user-chosen key.
+//!
+//! An object lives as long as some process holds a handle to it or has it
+//! mapped; after that its frames are released and the key is free again.
+
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+use lock::Mutex;
+
+bitflags! {
+    pub struct ShmFlags: u32 {
+        /// create the object if the key is unused
+        const CREATE = 1 << 0;
+        /// fail if the key is already in use
+        const EXCL = 1 << 1;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ShmError {
+    /// no such object, or it does not match the requested size or flags
+    Invalid,
+    /// no frame is left for a new object
+    OutOfMemory,
+}
+
+pub struct SharedMemory {
+    key: usize,
+    frames: Vec<Arc<FrameTracker>>,
+}
+
+impl SharedMemory {
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn frames(&self) -> &[Arc<FrameTracker>] {
+        &self.frames
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let mut registry = SHM_REGISTRY.lock();
+        // the key may have been reused by a new object in the meantime
+        if let Some(weak) = registry.get(&self.key) {
+            if weak.strong_count() == 0 {
+                registry.remove(&self.key);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref SHM_REGISTRY: Mutex<BTreeMap<usize, Weak<SharedMemory>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Open the object with `key`, creating one of `size` bytes if allowed.
+/// An existing object must be at least `size` bytes large.
+pub fn shm_open(key: usize, size: usize, flags: ShmFlags) -> Result<Arc<SharedMemory>, ShmError> {
+    let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    let mut registry = SHM_REGISTRY.lock();
+    if let Some(shm) = registry.get(&key).and_then(|weak| weak.upgrade()) {
+        // a rejected handle must not be dropped with the registry locked
+        drop(registry);
+        if flags.contains(ShmFlags::EXCL) || pages > shm.pages() {
+            return Err(ShmError::Invalid);
+        }
+        return Ok(shm);
+    }
+    if !flags.contains(ShmFlags::CREATE) || pages == 0 {
+        return Err(ShmError::Invalid);
+    }
+    let mut frames = Vec::new();
+    for _ in 0..pages {
+        frames.push(Arc::new(frame_alloc().ok_or(ShmError::OutOfMemory)?));
+    }
+    let shm = Arc::new(SharedMemory { key, frames });
+    registry.insert(key, Arc::downgrade(&shm));
+    Ok(shm)
+}
@@ -0,0 +1,43 @@
+//! Kernel random numbers.
+//!
+//! A SplitMix64 stream seeded at boot from the device tree's `rng-seed`
+//! and the timer. Every draw also mixes in the current time, so the jitter
+//! of when things happen keeps adding entropy. Good enough to randomize
+//! address space layouts, not for cryptography.
+
+use crate::fdt::MACHINE_INFO;
+use crate::timer::get_time;
+use lazy_static::*;
+use lock::Mutex;
+
+lazy_static! {
+    static ref STATE: Mutex<u64> = Mutex::new(0);
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Must run after `fdt::init`.
+pub fn init() {
+    let mut state = STATE.lock();
+    *state = MACHINE_INFO.rng_seed ^ get_time() as u64;
+    if MACHINE_INFO.rng_seed == 0 {
+        println!("[kernel] no rng-seed in device tree, random numbers are weak");
+    }
+}
+
+pub fn rand_u64() -> u64 {
+    let mut state = STATE.lock();
+    *state ^= (get_time() as u64).rotate_left(32);
+    splitmix64(&mut state)
+}
+
+/// A number in [0, bound), `bound` must not be 0.
+pub fn rand_below(bound: usize) -> usize {
+    (rand_u64() % bound as u64) as usize
+}
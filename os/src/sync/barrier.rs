@@ -0,0 +1,48 @@
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+use lock::Mutex;
+
+/// Blocks tasks until `count` of them wait, then lets them all go. It can
+/// be waited on again right away, for the next round.
+pub struct Barrier {
+    inner: Mutex<BarrierInner>,
+}
+
+pub struct BarrierInner {
+    count: usize,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Barrier {
+    /// `count` is at least 1.
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: unsafe {
+                Mutex::new(BarrierInner {
+                    count,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Returns true in one task of each round, the last to arrive.
+    pub fn wait(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.wait_queue.len() + 1 < inner.count {
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+            false
+        } else {
+            for task in inner.wait_queue.drain(..) {
+                add_task(task);
+            }
+            true
+        }
+    }
+
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.lock().wait_queue.is_empty()
+    }
+}
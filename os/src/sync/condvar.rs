@@ -1,6 +1,7 @@
 use crate::task::{add_task, block_current_task, block_current_and_run_next, current_task, TaskControlBlock, TaskContext};
 use alloc::{collections::VecDeque, sync::Arc};
 use crate::sync::{Mutex, UPSafeCell};
+use crate::timer::{block_current_with_timeout, remove_waiter, TimedWait};
 // use super::Mutex;
 
 pub struct Condvar {
@@ -9,6 +10,31 @@ pub struct Condvar {
 
 pub struct CondvarInner {
     pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// the mutex all queued waiters wait with, if they agree on one
+    pub mutex: Option<Arc<dyn Mutex>>,
+}
+
+impl CondvarInner {
+    fn enqueue(&mut self, mutex: &Arc<dyn Mutex>) {
+        let agree = match &self.mutex {
+            Some(shared) => Arc::as_ptr(shared) as *const u8 == Arc::as_ptr(mutex) as *const u8,
+            None => false,
+        };
+        if self.wait_queue.is_empty() {
+            self.mutex = Some(Arc::clone(mutex));
+        } else if !agree {
+            self.mutex = None;
+        }
+        self.wait_queue.push_back(current_task().unwrap());
+    }
+}
+
+/// After waking up, a waiter that `broadcast` moved onto the mutex already
+/// holds it.
+fn relock(mutex: Arc<dyn Mutex>) {
+    if !mutex.is_owner(&current_task().unwrap()) {
+        mutex.lock();
+    }
 }
 
 impl Condvar {
@@ -17,6 +43,7 @@ impl Condvar {
             inner: unsafe {
                 UPSafeCell::new(CondvarInner {
                     wait_queue: VecDeque::new(),
+                    mutex: None,
                 })
             },
         }
@@ -29,26 +56,75 @@ impl Condvar {
         }
     }
 
+    /// Wake every waiter. Rather than having them all run only to fight
+    /// over the mutex they wait with, they are moved onto its wait queue
+    /// and run one by one, each holding the mutex. Waiters that disagree
+    /// on the mutex, wait with a spin mutex, or have yet to release it in
+    /// `wait_timeout`, are simply woken.
+    pub fn broadcast(&self) {
+        let mut inner = self.inner.exclusive_access();
+        let waiters = core::mem::take(&mut inner.wait_queue);
+        let mutex = inner.mutex.take();
+        drop(inner);
+        for task in waiters {
+            let requeued = match &mutex {
+                Some(mutex) => mutex.clone().requeue(Arc::clone(&task)),
+                None => false,
+            };
+            if !requeued {
+                add_task(task);
+            }
+        }
+    }
+
     pub fn wait(&self, mutex: Arc<dyn Mutex>) {
-        mutex.unlock();
+        mutex.clone().unlock();
         let mut inner = self.inner.exclusive_access();
-        inner.wait_queue.push_back(current_task().unwrap());
+        inner.enqueue(&mutex);
         drop(inner);
         block_current_and_run_next();
-        mutex.lock();
+        relock(mutex);
+    }
+
+    /// Like `wait`, false if nobody signaled within `ms` milliseconds. The
+    /// mutex is held again either way.
+    pub fn wait_timeout(self: &Arc<Self>, mutex: Arc<dyn Mutex>, ms: usize) -> bool {
+        // queued before the mutex is released, a signal in between is not
+        // lost; a broadcast in between wakes instead of requeueing us
+        let mut inner = self.inner.exclusive_access();
+        inner.enqueue(&mutex);
+        drop(inner);
+        mutex.clone().unlock();
+        let signaled = block_current_with_timeout(self.clone(), ms);
+        relock(mutex);
+        signaled
     }
 
     pub fn wait_no_sched(&self) -> *mut TaskContext {
         let mut inner = self.inner.exclusive_access();
+        // no mutex to be moved onto
+        inner.mutex = None;
         inner.wait_queue.push_back(current_task().unwrap());
         block_current_task()
     }
 
     pub fn wait_with_mutex(&self, mutex: Arc<dyn Mutex>) {
-        mutex.lock();
+        mutex.clone().lock();
         let mut inner = self.inner.exclusive_access();
+        // the mutex is held while waiting, nobody may be moved onto it
+        inner.mutex = None;
         inner.wait_queue.push_back(current_task().unwrap());
         block_current_and_run_next();
         mutex.unlock();
     }
+
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.exclusive_access().wait_queue.is_empty()
+    }
+}
+
+impl TimedWait for Condvar {
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_waiter(&mut self.inner.exclusive_access().wait_queue, task)
+    }
 }
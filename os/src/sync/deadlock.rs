@@ -0,0 +1,137 @@
+//! Opt-in deadlock detection for user mutexes and semaphores.
+//!
+//! Each process keeps banker's style bookkeeping for the mutexes and
+//! semaphores it created: the units of each that are available, the units
+//! each thread holds and the resource each blocked thread waits for. With
+//! detection on, a lock or down first checks that its caller could still
+//! finish, assuming threads that are not blocked release what they hold
+//! eventually, and fails with EDEADLK instead of blocking forever.
+//!
+//! Acquisitions are recorded after they happen and releases before, so a
+//! check racing with them on another hart sees more available than there
+//! is and lets its caller block rather than failing it wrongly. A thread
+//! waiting on a semaphore that is only ever upped by threads that do not
+//! hold it looks deadlocked, which is why detection is off by default.
+
+use super::{Mutex as UserMutex, Semaphore};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use lock::Mutex;
+
+/// Objects are named by their address, not by their id: a destroyed id is
+/// handed out again while threads that looked it up may still use the old
+/// object, which keeps its address as long as they do.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Resource {
+    Mutex(usize),
+    Semaphore(usize),
+}
+
+impl Resource {
+    pub fn mutex(mutex: &Arc<dyn UserMutex>) -> Self {
+        Resource::Mutex(Arc::as_ptr(mutex) as *const () as usize)
+    }
+
+    pub fn semaphore(sem: &Arc<Semaphore>) -> Self {
+        Resource::Semaphore(Arc::as_ptr(sem) as usize)
+    }
+}
+
+/// Threads are named by their pid.
+#[derive(Default)]
+pub struct DeadlockDetector {
+    pub enabled: bool,
+    available: BTreeMap<Resource, usize>,
+    allocation: BTreeMap<(usize, Resource), usize>,
+    need: BTreeMap<usize, Resource>,
+}
+
+/// Shared by the threads of a process.
+pub type SharedDeadlockDetector = Arc<Mutex<DeadlockDetector>>;
+
+pub fn new_deadlock_detector() -> SharedDeadlockDetector {
+    Arc::new(Mutex::new(DeadlockDetector::default()))
+}
+
+impl DeadlockDetector {
+    /// `resource` was created with `units` available. Semaphores inherited
+    /// from a parent are never added, calls on them are not checked.
+    pub fn add(&mut self, resource: Resource, units: usize) {
+        self.available.insert(resource, units);
+        self.allocation.retain(|&(_, held), _| held != resource);
+    }
+
+    /// `resource` was destroyed. A thread that got past `request` but did
+    /// not queue yet is forgotten along with what was held.
+    pub fn remove(&mut self, resource: Resource) {
+        self.available.remove(&resource);
+        self.allocation.retain(|&(_, held), _| held != resource);
+        self.need.retain(|_, &mut needed| needed != resource);
+    }
+
+    /// `thread` is about to wait for `resource`. Returns false, with
+    /// nothing recorded, if that would deadlock.
+    pub fn request(&mut self, thread: usize, resource: Resource) -> bool {
+        if !self.available.contains_key(&resource) {
+            return true;
+        }
+        self.need.insert(thread, resource);
+        if self.enabled && !self.can_finish(thread) {
+            self.need.remove(&thread);
+            return false;
+        }
+        true
+    }
+
+    pub fn acquired(&mut self, thread: usize, resource: Resource) {
+        self.need.remove(&thread);
+        if let Some(available) = self.available.get_mut(&resource) {
+            *available = available.saturating_sub(1);
+            *self.allocation.entry((thread, resource)).or_insert(0) += 1;
+        }
+    }
+
+    /// Semaphores may be upped by threads that never downed them.
+    pub fn released(&mut self, thread: usize, resource: Resource) {
+        if let Some(available) = self.available.get_mut(&resource) {
+            *available += 1;
+            if let Some(held) = self.allocation.get_mut(&(thread, resource)) {
+                *held -= 1;
+                if *held == 0 {
+                    self.allocation.remove(&(thread, resource));
+                }
+            }
+        }
+    }
+
+    /// The safety check: let every thread whose need can be met finish
+    /// and return what it holds, until `thread` finishes or nobody can.
+    fn can_finish(&self, thread: usize) -> bool {
+        let mut work = self.available.clone();
+        let mut unfinished: BTreeSet<usize> = self
+            .allocation
+            .keys()
+            .map(|&(holder, _)| holder)
+            .chain(self.need.keys().copied())
+            .collect();
+        while unfinished.contains(&thread) {
+            let next = unfinished.iter().copied().find(|t| match self.need.get(t) {
+                Some(resource) => work[resource] > 0,
+                None => true,
+            });
+            let next = match next {
+                Some(next) => next,
+                None => return false,
+            };
+            unfinished.remove(&next);
+            for (&(_, resource), &units) in self
+                .allocation
+                .range((next, Resource::Mutex(0))..)
+                .take_while(|(&(holder, _), _)| holder == next)
+            {
+                *work.get_mut(&resource).unwrap() += units;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,113 @@
+//! Futexes: user space synchronizes through plain memory words and only
+//! enters the kernel to sleep when it finds a word contended. Waiters are
+//! keyed by the physical address of the word, so threads, forked processes
+//! and shared memory mappings reaching the same word share its waiters.
+
+use crate::mm::{PageTable, PhysAddr, VirtAddr};
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use crate::timer::{block_current_with_timeout, remove_waiter, TimedWait};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+use lock::Mutex;
+
+pub enum FutexError {
+    /// the word is not mapped user memory or not aligned
+    Fault,
+    /// the word no longer holds the expected value
+    Again,
+    TimedOut,
+}
+
+lazy_static! {
+    static ref FUTEXES: Mutex<BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+fn futex_word(token: usize, uaddr: usize) -> Result<PhysAddr, FutexError> {
+    if uaddr % core::mem::size_of::<u32>() != 0 {
+        return Err(FutexError::Fault);
+    }
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(uaddr);
+    match page_table.translate(va.floor()) {
+        Some(pte) if pte.is_valid() && pte.is_user() => {
+            page_table.translate_va(va).ok_or(FutexError::Fault)
+        }
+        _ => Err(FutexError::Fault),
+    }
+}
+
+/// Sleep on the word at `uaddr` of address space `token` if it still
+/// holds `expected`, until woken or `timeout_ms` milliseconds pass.
+pub fn futex_wait(
+    token: usize,
+    uaddr: usize,
+    expected: u32,
+    timeout_ms: Option<usize>,
+) -> Result<(), FutexError> {
+    let pa = futex_word(token, uaddr)?;
+    let mut futexes = FUTEXES.lock();
+    // compared under the lock futex_wake takes, a store and a wake after
+    // the comparison find us queued
+    if pa.get_ref::<AtomicU32>().load(Ordering::SeqCst) != expected {
+        return Err(FutexError::Again);
+    }
+    futexes
+        .entry(pa.0)
+        .or_default()
+        .push_back(current_task().unwrap());
+    drop(futexes);
+    match timeout_ms {
+        None => {
+            block_current_and_run_next();
+            Ok(())
+        }
+        Some(ms) => {
+            if block_current_with_timeout(Arc::new(Futex(pa.0)), ms) {
+                Ok(())
+            } else {
+                Err(FutexError::TimedOut)
+            }
+        }
+    }
+}
+
+/// Wake up to `count` waiters on the word at `uaddr`, returns how many
+/// there were.
+pub fn futex_wake(token: usize, uaddr: usize, count: usize) -> Result<usize, FutexError> {
+    let pa = futex_word(token, uaddr)?;
+    let mut futexes = FUTEXES.lock();
+    let mut woken = 0;
+    if let Some(wait_queue) = futexes.get_mut(&pa.0) {
+        while woken < count {
+            match wait_queue.pop_front() {
+                Some(task) => add_task(task),
+                None => break,
+            }
+            woken += 1;
+        }
+        if wait_queue.is_empty() {
+            futexes.remove(&pa.0);
+        }
+    }
+    Ok(woken)
+}
+
+/// The waiters of one word, for timed waits.
+struct Futex(usize);
+
+impl TimedWait for Futex {
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut futexes = FUTEXES.lock();
+        let cancelled = match futexes.get_mut(&self.0) {
+            Some(wait_queue) => remove_waiter(wait_queue, task),
+            None => false,
+        };
+        if futexes.get(&self.0).map_or(false, |queue| queue.is_empty()) {
+            futexes.remove(&self.0);
+        }
+        cancelled
+    }
+}
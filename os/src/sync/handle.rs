@@ -0,0 +1,49 @@
+//! File descriptors for sync objects. An fd keeps its object alive like a
+//! slot in one of the per-process lists does, and is inherited by `fork`,
+//! so a process can pass a mutex or condvar to its children, which import
+//! it into their own lists. The object goes away with the last fd and the
+//! last list slot referring to it.
+
+use super::{Barrier, Condvar, Mutex, RwLock, Semaphore};
+use crate::fs::File;
+use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+
+#[derive(Clone)]
+pub enum SyncObject {
+    Mutex(Arc<dyn Mutex>),
+    Semaphore(Arc<Semaphore>),
+    Condvar(Arc<Condvar>),
+    RwLock(Arc<RwLock>),
+    Barrier(Arc<Barrier>),
+}
+
+/// Neither readable nor writable, it can only be imported, passed on and
+/// closed.
+pub struct SyncHandle {
+    object: SyncObject,
+}
+
+impl SyncHandle {
+    pub fn new(object: SyncObject) -> Self {
+        Self { object }
+    }
+}
+
+impl File for SyncHandle {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn sync_object(&self) -> Option<SyncObject> {
+        Some(self.object.clone())
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
+use super::{Barrier, Condvar, Mutex, MutexBlocking, MutexSpin, RwLock, Semaphore};
 use crate::task::{block_current_and_run_next, current_task, pid2task};
 use crate::timer::{add_timer, get_time_ms};
 use alloc::sync::Arc;
@@ -20,26 +20,31 @@ pub fn k_mutex_create(blocking: bool) -> isize {
     } else {
         Some(Arc::new(MutexBlocking::new()))
     };
-    let mut process_inner = process.inner_exclusive_access();
-    if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    if let Some(id) = objects
         .mutex_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.mutex_list[id] = mutex;
+        objects.mutex_list[id] = mutex;
         id as isize
     } else {
-        process_inner.mutex_list.push(mutex);
-        process_inner.mutex_list.len() as isize - 1
+        objects.mutex_list.push(mutex);
+        objects.mutex_list.len() as isize - 1
     }
 }
 
 pub fn k_mutex_lock(mutex_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = Arc::clone(
+        process_inner.sync_objects.lock().mutex_list[mutex_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     drop(process);
     mutex.lock();
@@ -49,7 +54,11 @@ pub fn k_mutex_lock(mutex_id: usize) -> isize {
 pub fn k_mutex_unlock(mutex_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = Arc::clone(
+        process_inner.sync_objects.lock().mutex_list[mutex_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     drop(process);
     mutex.unlock();
@@ -58,21 +67,22 @@ pub fn k_mutex_unlock(mutex_id: usize) -> isize {
 
 pub fn k_semaphore_create(res_count: usize) -> isize {
     let process = pid2task(0).unwrap();
-    let mut process_inner = process.inner_exclusive_access();
-    let id = if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
         .semaphore_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.semaphore_list[id] = Some(Arc::new(Semaphore::new(res_count)));
+        objects.semaphore_list[id] = Some(Arc::new(Semaphore::new(res_count)));
         id
     } else {
-        process_inner
+        objects
             .semaphore_list
             .push(Some(Arc::new(Semaphore::new(res_count))));
-        process_inner.semaphore_list.len() - 1
+        objects.semaphore_list.len() - 1
     };
     id as isize
 }
@@ -80,7 +90,11 @@ pub fn k_semaphore_create(res_count: usize) -> isize {
 pub fn k_semaphore_up(sem_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = Arc::clone(
+        process_inner.sync_objects.lock().semaphore_list[sem_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     sem.up();
     0
@@ -89,7 +103,11 @@ pub fn k_semaphore_up(sem_id: usize) -> isize {
 pub fn k_semaphore_down(sem_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = Arc::clone(
+        process_inner.sync_objects.lock().semaphore_list[sem_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     sem.down();
     0
@@ -97,21 +115,20 @@ pub fn k_semaphore_down(sem_id: usize) -> isize {
 
 pub fn k_condvar_create(_arg: usize) -> isize {
     let process = pid2task(0).unwrap();
-    let mut process_inner = process.inner_exclusive_access();
-    let id = if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
         .condvar_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.condvar_list[id] = Some(Arc::new(Condvar::new()));
+        objects.condvar_list[id] = Some(Arc::new(Condvar::new()));
         id
     } else {
-        process_inner
-            .condvar_list
-            .push(Some(Arc::new(Condvar::new())));
-        process_inner.condvar_list.len() - 1
+        objects.condvar_list.push(Some(Arc::new(Condvar::new())));
+        objects.condvar_list.len() - 1
     };
     id as isize
 }
@@ -119,18 +136,142 @@ pub fn k_condvar_create(_arg: usize) -> isize {
 pub fn k_condvar_signal(condvar_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
+    let condvar = Arc::clone(
+        process_inner.sync_objects.lock().condvar_list[condvar_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     condvar.signal();
     0
 }
 
+pub fn k_condvar_broadcast(condvar_id: usize) -> isize {
+    let process = pid2task(0).unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = Arc::clone(
+        process_inner.sync_objects.lock().condvar_list[condvar_id]
+            .as_ref()
+            .unwrap(),
+    );
+    drop(process_inner);
+    condvar.broadcast();
+    0
+}
+
 pub fn k_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     let process = pid2task(0).unwrap();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let condvar = Arc::clone(
+        process_inner.sync_objects.lock().condvar_list[condvar_id]
+            .as_ref()
+            .unwrap(),
+    );
+    let mutex = Arc::clone(
+        process_inner.sync_objects.lock().mutex_list[mutex_id]
+            .as_ref()
+            .unwrap(),
+    );
     drop(process_inner);
     condvar.wait(mutex);
     0
 }
+
+pub fn k_rwlock_create() -> isize {
+    let process = pid2task(0).unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        objects.rwlock_list[id] = Some(Arc::new(RwLock::new()));
+        id
+    } else {
+        objects.rwlock_list.push(Some(Arc::new(RwLock::new())));
+        objects.rwlock_list.len() - 1
+    };
+    id as isize
+}
+
+fn k_rwlock(rwlock_id: usize) -> Arc<RwLock> {
+    let process = pid2task(0).unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = Arc::clone(
+        process_inner.sync_objects.lock().rwlock_list[rwlock_id]
+            .as_ref()
+            .unwrap(),
+    );
+    rwlock
+}
+
+pub fn k_rwlock_read(rwlock_id: usize) -> isize {
+    k_rwlock(rwlock_id).read();
+    0
+}
+
+pub fn k_rwlock_write(rwlock_id: usize) -> isize {
+    k_rwlock(rwlock_id).write();
+    0
+}
+
+pub fn k_rwlock_unlock(rwlock_id: usize) -> isize {
+    match k_rwlock(rwlock_id).unlock() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+pub fn k_rwlock_upgrade(rwlock_id: usize) -> isize {
+    match k_rwlock(rwlock_id).upgrade() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+pub fn k_rwlock_downgrade(rwlock_id: usize) -> isize {
+    match k_rwlock(rwlock_id).downgrade() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+pub fn k_barrier_create(count: usize) -> isize {
+    assert!(count > 0);
+    let process = pid2task(0).unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
+        .barrier_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        objects.barrier_list[id] = Some(Arc::new(Barrier::new(count)));
+        id
+    } else {
+        objects
+            .barrier_list
+            .push(Some(Arc::new(Barrier::new(count))));
+        objects.barrier_list.len() - 1
+    };
+    id as isize
+}
+
+/// Returns 1 in the last kthread to arrive, 0 in the others.
+pub fn k_barrier_wait(barrier_id: usize) -> isize {
+    let process = pid2task(0).unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let barrier = Arc::clone(
+        process_inner.sync_objects.lock().barrier_list[barrier_id]
+            .as_ref()
+            .unwrap(),
+    );
+    drop(process_inner);
+    drop(process);
+    barrier.wait() as isize
+}
@@ -1,11 +1,30 @@
+mod barrier;
 mod condvar;
+mod deadlock;
+mod futex;
+mod handle;
 mod mutex;
+mod named;
+mod objects;
+mod rcu;
+mod rwlock;
 mod semaphore;
 mod up;
 pub mod ksync;
 
+pub use barrier::Barrier;
 pub use condvar::Condvar;
-pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use deadlock::{new_deadlock_detector, Resource, SharedDeadlockDetector};
+pub use futex::{futex_wait, futex_wake, FutexError};
+pub use handle::{SyncHandle, SyncObject};
+pub use mutex::{update_priority, Mutex, MutexBlocking, MutexSpin};
+pub use named::{
+    open_named_mutex, open_named_semaphore, unlink_named_mutex, unlink_named_semaphore,
+    NamedError, NamedFlags, NAME_MAX,
+};
+pub use objects::{new_sync_objects, SharedSyncObjects, SyncObjects};
+pub use rcu::{rcu_quiescent_state, Rcu};
+pub use rwlock::{RwLock, RwLockError};
 pub use semaphore::Semaphore;
 pub use up::{UPIntrFreeCell, UPIntrRefMut, UPSafeCell};
 pub use ksync::*;
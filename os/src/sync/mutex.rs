@@ -2,43 +2,97 @@ use super::UPIntrFreeCell;
 use crate::task::TaskControlBlock;
 use crate::task::{add_task, current_task};
 use crate::task::{block_current_and_run_next, suspend_current_and_run_next};
+use crate::timer::{block_current_with_timeout, get_time_ms, remove_waiter, TimedWait};
 use alloc::{collections::VecDeque, sync::Arc};
+use core::cmp::Reverse;
 
 pub trait Mutex: Sync + Send {
-    fn lock(&self);
-    fn unlock(&self);
+    fn lock(self: Arc<Self>);
+    /// Callers check they hold the mutex, a free one is left alone.
+    fn unlock(self: Arc<Self>);
+    /// False, without waiting, if the mutex is held.
+    fn try_lock(self: Arc<Self>) -> bool;
+    /// False if the mutex is still held after `ms` milliseconds.
+    fn lock_timeout(self: Arc<Self>, ms: usize) -> bool;
+    /// Held or waited for, it must not be destroyed.
+    fn is_busy(&self) -> bool;
+    /// Make `task`, woken from a condvar, wait for the mutex as if it had
+    /// called `lock`, so it runs again holding the mutex. False if the
+    /// mutex cannot queue waiters, or `task` still holds it, the caller
+    /// then wakes `task` itself.
+    fn requeue(self: Arc<Self>, task: Arc<TaskControlBlock>) -> bool;
+    /// Whether `task` holds the mutex.
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool;
 }
 
 pub struct MutexSpin {
-    locked: lock::Mutex<bool>,
+    owner: lock::Mutex<Option<Arc<TaskControlBlock>>>,
 }
 
 impl MutexSpin {
     pub fn new() -> Self {
         Self {
-            locked: unsafe { lock::Mutex::new(false) },
+            owner: unsafe { lock::Mutex::new(None) },
         }
     }
 }
 
 impl Mutex for MutexSpin {
-    fn lock(&self) {
+    fn lock(self: Arc<Self>) {
+        let task = current_task().unwrap();
         loop {
-            let mut locked = self.locked.lock();
-            if *locked {
-                drop(locked);
+            let mut owner = self.owner.lock();
+            if owner.is_some() {
+                drop(owner);
                 suspend_current_and_run_next();
                 continue;
             } else {
-                *locked = true;
+                *owner = Some(task);
                 return;
             }
         }
     }
 
-    fn unlock(&self) {
-        let mut locked = self.locked.lock();
-        *locked = false;
+    fn unlock(self: Arc<Self>) {
+        let mut owner = self.owner.lock();
+        *owner = None;
+    }
+
+    fn try_lock(self: Arc<Self>) -> bool {
+        let task = current_task().unwrap();
+        let mut owner = self.owner.lock();
+        if owner.is_some() {
+            false
+        } else {
+            *owner = Some(task);
+            true
+        }
+    }
+
+    fn lock_timeout(self: Arc<Self>, ms: usize) -> bool {
+        let expire_ms = get_time_ms() + ms;
+        while !self.clone().try_lock() {
+            if get_time_ms() >= expire_ms {
+                return false;
+            }
+            suspend_current_and_run_next();
+        }
+        true
+    }
+
+    fn is_busy(&self) -> bool {
+        self.owner.lock().is_some()
+    }
+
+    fn requeue(self: Arc<Self>, _task: Arc<TaskControlBlock>) -> bool {
+        false
+    }
+
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool {
+        match &*self.owner.lock() {
+            Some(owner) => Arc::ptr_eq(owner, task),
+            None => false,
+        }
     }
 }
 
@@ -47,7 +101,7 @@ pub struct MutexBlocking {
 }
 
 pub struct MutexBlockingInner {
-    locked: bool,
+    owner: Option<Arc<TaskControlBlock>>,
     wait_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
@@ -56,33 +110,171 @@ impl MutexBlocking {
         Self {
             inner: unsafe {
                 lock::Mutex::new(MutexBlockingInner {
-                    locked: false,
+                    owner: None,
                     wait_queue: VecDeque::new(),
                 })
             },
         }
     }
+
+    fn owner(&self) -> Option<Arc<TaskControlBlock>> {
+        self.inner.lock().owner.clone()
+    }
+
+    fn top_waiter_priority(&self) -> Option<usize> {
+        let mutex_inner = self.inner.lock();
+        mutex_inner
+            .wait_queue
+            .iter()
+            .map(|task| task.priority.effective())
+            .max()
+    }
+
+    /// Give `task` the mutex if it is free, otherwise queue it as a waiter
+    /// and lend its priority to the owner.
+    fn lock_or_enqueue(self: &Arc<Self>, task: Arc<TaskControlBlock>) -> bool {
+        // set before queueing, unlock clears it when it hands the mutex over
+        task.inner_exclusive_access().blocked_on = Some(Arc::clone(self));
+        let mut mutex_inner = self.inner.lock();
+        match mutex_inner.owner.clone() {
+            None => {
+                mutex_inner.owner = Some(Arc::clone(&task));
+                drop(mutex_inner);
+                let mut task_inner = task.inner_exclusive_access();
+                task_inner.blocked_on = None;
+                task_inner.held_mutexes.push(Arc::clone(self));
+                true
+            }
+            Some(owner) => {
+                mutex_inner.wait_queue.push_back(task);
+                drop(mutex_inner);
+                update_priority(owner);
+                false
+            }
+        }
+    }
 }
 
 impl Mutex for MutexBlocking {
-    fn lock(&self) {
-        let mut mutex_inner = self.inner.lock();
-        if mutex_inner.locked {
-            mutex_inner.wait_queue.push_back(current_task().unwrap());
-            drop(mutex_inner);
+    fn lock(self: Arc<Self>) {
+        if !self.lock_or_enqueue(current_task().unwrap()) {
+            // unlock hands the mutex over to the task it wakes
             block_current_and_run_next();
-        } else {
-            mutex_inner.locked = true;
         }
     }
 
-    fn unlock(&self) {
+    fn unlock(self: Arc<Self>) {
         let mut mutex_inner = self.inner.lock();
-        assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            add_task(waking_task);
-        } else {
-            mutex_inner.locked = false;
+        let owner = match mutex_inner.owner.take() {
+            Some(owner) => owner,
+            None => return,
+        };
+        // the waiter of the highest priority, the longest waiting of those
+        let next = mutex_inner
+            .wait_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, task)| Reverse(task.priority.effective()))
+            .map(|(idx, _)| idx)
+            .and_then(|idx| mutex_inner.wait_queue.remove(idx));
+        mutex_inner.owner = next.clone();
+        drop(mutex_inner);
+        owner
+            .inner_exclusive_access()
+            .held_mutexes
+            .retain(|mutex| !Arc::ptr_eq(mutex, &self));
+        update_priority(owner);
+        if let Some(task) = next {
+            // the new owner holds it before it can run and unlock it again
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.blocked_on = None;
+            task_inner.held_mutexes.push(self);
+            drop(task_inner);
+            update_priority(Arc::clone(&task));
+            add_task(task);
+        }
+    }
+
+    fn try_lock(self: Arc<Self>) -> bool {
+        let task = current_task().unwrap();
+        let mut mutex_inner = self.inner.lock();
+        if mutex_inner.owner.is_some() {
+            return false;
+        }
+        mutex_inner.owner = Some(Arc::clone(&task));
+        drop(mutex_inner);
+        task.inner_exclusive_access().held_mutexes.push(self);
+        true
+    }
+
+    fn lock_timeout(self: Arc<Self>, ms: usize) -> bool {
+        if self.lock_or_enqueue(current_task().unwrap()) {
+            return true;
+        }
+        if block_current_with_timeout(self.clone(), ms) {
+            return true;
+        }
+        // no longer waiting, the owner gives back what we lent it
+        current_task().unwrap().inner_exclusive_access().blocked_on = None;
+        if let Some(owner) = self.owner() {
+            update_priority(owner);
+        }
+        false
+    }
+
+    fn is_busy(&self) -> bool {
+        // waiters only queue while there is an owner
+        self.inner.lock().owner.is_some()
+    }
+
+    fn requeue(self: Arc<Self>, task: Arc<TaskControlBlock>) -> bool {
+        // `Condvar::wait_timeout` queues before it unlocks, queued behind
+        // itself the task would be handed its own mutex
+        if self.is_owner(&task) {
+            return false;
+        }
+        if self.lock_or_enqueue(Arc::clone(&task)) {
+            add_task(task);
+        }
+        true
+    }
+
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool {
+        match &self.inner.lock().owner {
+            Some(owner) => Arc::ptr_eq(owner, task),
+            None => false,
+        }
+    }
+}
+
+impl TimedWait for MutexBlocking {
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_waiter(&mut self.inner.lock().wait_queue, task)
+    }
+}
+
+/// Priority inheritance: recompute the effective priority of `task` as the
+/// highest of its base priority and those of the waiters of the blocking
+/// mutexes it holds. A change is passed on to the owner of the mutex
+/// `task` waits for, and so on along the chain.
+pub fn update_priority(task: Arc<TaskControlBlock>) {
+    let mut task = task;
+    loop {
+        let task_inner = task.inner_exclusive_access();
+        let priority = task_inner
+            .held_mutexes
+            .iter()
+            .filter_map(|mutex| mutex.top_waiter_priority())
+            .fold(task.priority.base(), usize::max);
+        let blocked_on = task_inner.blocked_on.clone();
+        drop(task_inner);
+        if !task.priority.set_effective(priority) {
+            return;
+        }
+        // a deadlocked chain leads back to where it started
+        match blocked_on.and_then(|mutex| mutex.owner()) {
+            Some(owner) if !Arc::ptr_eq(&owner, &task) => task = owner,
+            _ => return,
         }
     }
 }
@@ -0,0 +1,107 @@
+//! Semaphores and mutexes with a name, so unrelated processes can open the
+//! same one. A name stays taken until it is unlinked; the object lives on
+//! until the last process that opened it closes it or exits. Semaphores
+//! and mutexes have separate namespaces.
+
+use super::{Mutex, MutexBlocking, Semaphore};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use bitflags::*;
+use lazy_static::*;
+
+/// Longest name accepted, in bytes.
+pub const NAME_MAX: usize = 255;
+
+bitflags! {
+    pub struct NamedFlags: u32 {
+        /// create the object if the name is unused
+        const CREATE = 1 << 0;
+        /// fail if the name is already in use
+        const EXCL = 1 << 1;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NamedError {
+    /// the name is empty or longer than `NAME_MAX`
+    Invalid,
+    /// no object has the name, and CREATE was not given
+    NotFound,
+    /// CREATE and EXCL were given, but the name is taken
+    Exists,
+}
+
+type Registry<T> = lock::Mutex<BTreeMap<String, Arc<T>>>;
+
+lazy_static! {
+    static ref NAMED_SEMAPHORES: Registry<Semaphore> = lock::Mutex::new(BTreeMap::new());
+    static ref NAMED_MUTEXES: Registry<dyn Mutex> = lock::Mutex::new(BTreeMap::new());
+}
+
+fn check_name(name: &str) -> Result<(), NamedError> {
+    if name.is_empty() || name.len() > NAME_MAX {
+        return Err(NamedError::Invalid);
+    }
+    Ok(())
+}
+
+fn open<T: ?Sized>(
+    registry: &Registry<T>,
+    name: &str,
+    flags: NamedFlags,
+    create: impl FnOnce() -> Arc<T>,
+) -> Result<Arc<T>, NamedError> {
+    check_name(name)?;
+    let mut registry = registry.lock();
+    if let Some(object) = registry.get(name) {
+        if flags.contains(NamedFlags::CREATE | NamedFlags::EXCL) {
+            return Err(NamedError::Exists);
+        }
+        return Ok(Arc::clone(object));
+    }
+    if !flags.contains(NamedFlags::CREATE) {
+        return Err(NamedError::NotFound);
+    }
+    let object = create();
+    registry.insert(String::from(name), Arc::clone(&object));
+    Ok(object)
+}
+
+fn unlink<T: ?Sized>(registry: &Registry<T>, name: &str) -> Result<(), NamedError> {
+    check_name(name)?;
+    registry
+        .lock()
+        .remove(name)
+        .map(|_| ())
+        .ok_or(NamedError::NotFound)
+}
+
+/// Open the semaphore called `name`, creating it with `res_count` units if
+/// allowed. The count is ignored when the semaphore exists already.
+pub fn open_named_semaphore(
+    name: &str,
+    flags: NamedFlags,
+    res_count: usize,
+) -> Result<Arc<Semaphore>, NamedError> {
+    open(&NAMED_SEMAPHORES, name, flags, || {
+        Arc::new(Semaphore::new(res_count))
+    })
+}
+
+/// Free the name, processes that opened the semaphore keep using it.
+pub fn unlink_named_semaphore(name: &str) -> Result<(), NamedError> {
+    unlink(&NAMED_SEMAPHORES, name)
+}
+
+/// Open the mutex called `name`, a blocking one, creating it if allowed.
+pub fn open_named_mutex(name: &str, flags: NamedFlags) -> Result<Arc<dyn Mutex>, NamedError> {
+    open(&NAMED_MUTEXES, name, flags, || {
+        Arc::new(MutexBlocking::new()) as Arc<dyn Mutex>
+    })
+}
+
+/// Free the name, processes that opened the mutex keep using it.
+pub fn unlink_named_mutex(name: &str) -> Result<(), NamedError> {
+    unlink(&NAMED_MUTEXES, name)
+}
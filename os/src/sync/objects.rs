@@ -0,0 +1,23 @@
+//! The synchronization objects user code names by id: the index of the
+//! object in the per-process list of its kind.
+
+use super::{Barrier, Condvar, Mutex, RwLock, Semaphore};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+#[derive(Default)]
+pub struct SyncObjects {
+    pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    pub rwlock_list: Vec<Option<Arc<RwLock>>>,
+    pub barrier_list: Vec<Option<Arc<Barrier>>>,
+}
+
+/// Shared by the threads of a process, an id names the same object in
+/// all of them.
+pub type SharedSyncObjects = Arc<lock::Mutex<SyncObjects>>;
+
+pub fn new_sync_objects() -> SharedSyncObjects {
+    Arc::new(lock::Mutex::new(SyncObjects::default()))
+}
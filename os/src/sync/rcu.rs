@@ -0,0 +1,131 @@
+//! Read-copy-update for read-mostly tables like `PID2TCB`. Readers take no
+//! lock and write nothing shared, they follow the pointer to the current
+//! version. A writer publishes a changed copy and retires the old version,
+//! which is freed once every hart has passed a quiescent state.
+//!
+//! Quiescent states are only reported by the scheduler loop in
+//! `Processor::run`, between two tasks, which also frees what has become
+//! safe to free. That is enough because kernel code is never preempted: a
+//! reader runs `read` to the end on the hart it started on, and may not
+//! block or yield inside it, so it is done before that hart schedules.
+
+use crate::config::MAX_CPU_NUM;
+use crate::task::hart_id;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use lazy_static::*;
+
+#[derive(Default)]
+struct HartState {
+    /// a hart that never ran its scheduler loop holds no grace period up
+    online: AtomicBool,
+    /// `GRACE_PERIODS` as of the hart's last quiescent state
+    seen: AtomicUsize,
+}
+
+/// the number of grace periods started so far
+static GRACE_PERIODS: AtomicUsize = AtomicUsize::new(0);
+
+/// the oldest grace period a version in `RETIRED` waits for, `usize::MAX`
+/// if there is none, so that quiescent states need not take the lock
+static OLDEST_RETIRED: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+lazy_static! {
+    static ref HARTS: [HartState; MAX_CPU_NUM] = Default::default();
+    /// old versions of every `Rcu`, with the grace period they wait for
+    static ref RETIRED: lock::Mutex<Vec<(usize, Box<dyn Send>)>> =
+        lock::Mutex::new(Vec::new());
+}
+
+/// Called by every hart between two tasks, when it holds no reference
+/// into any RCU protected version, see the module doc. Frees the old
+/// versions nobody can read any more.
+pub fn rcu_quiescent_state() {
+    let hart = &HARTS[hart_id()];
+    hart.seen
+        .store(GRACE_PERIODS.load(Ordering::SeqCst), Ordering::SeqCst);
+    hart.online.store(true, Ordering::SeqCst);
+    let oldest = OLDEST_RETIRED.load(Ordering::SeqCst);
+    if oldest != usize::MAX && grace_period_over(oldest) {
+        reclaim();
+    }
+}
+
+fn start_grace_period() -> usize {
+    GRACE_PERIODS.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+fn grace_period_over(grace_period: usize) -> bool {
+    HARTS.iter().all(|hart| {
+        !hart.online.load(Ordering::SeqCst) || hart.seen.load(Ordering::SeqCst) >= grace_period
+    })
+}
+
+/// Keep `old` until the grace period started now is over.
+fn retire(old: Box<dyn Send>) {
+    let mut retired = RETIRED.lock();
+    let grace_period = start_grace_period();
+    retired.push((grace_period, old));
+    OLDEST_RETIRED.fetch_min(grace_period, Ordering::SeqCst);
+}
+
+fn reclaim() {
+    let mut retired = RETIRED.lock();
+    let (done, waiting): (Vec<_>, Vec<_>) = core::mem::take(&mut *retired)
+        .into_iter()
+        .partition(|&(grace_period, _)| grace_period_over(grace_period));
+    *retired = waiting;
+    let oldest = retired.iter().map(|&(grace_period, _)| grace_period).min();
+    OLDEST_RETIRED.store(oldest.unwrap_or(usize::MAX), Ordering::SeqCst);
+    drop(retired);
+    // may drop the last references to what the versions hold, so not
+    // under the lock
+    drop(done);
+}
+
+pub struct Rcu<T> {
+    current: AtomicPtr<T>,
+    /// taken by writers one after another
+    writer: lock::Mutex<()>,
+    _owns: PhantomData<Box<T>>,
+}
+
+impl<T: Clone + Send + 'static> Rcu<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            writer: lock::Mutex::new(()),
+            _owns: PhantomData,
+        }
+    }
+
+    /// Run `f` on the current version. `f` must not block or yield, the
+    /// version may be freed once this hart switches tasks.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(unsafe { &*self.current.load(Ordering::SeqCst) })
+    }
+
+    /// Publish a copy of the current version changed by `f` and return
+    /// what `f` returns. Readers see the old version or the new one, the
+    /// old one is freed at the first quiescent state after its grace
+    /// period. Every update copies the whole version, which suits tables
+    /// changed on spawn and exit only.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let writer = self.writer.lock();
+        let old = self.current.load(Ordering::SeqCst);
+        let mut new = Box::new(unsafe { (*old).clone() });
+        let ret = f(&mut new);
+        self.current.store(Box::into_raw(new), Ordering::SeqCst);
+        drop(writer);
+        retire(unsafe { Box::from_raw(old) });
+        ret
+    }
+}
+
+impl<T> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
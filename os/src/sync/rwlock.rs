@@ -0,0 +1,154 @@
+//! Reader-writer locks. They prefer writers: once a writer waits, readers
+//! arriving later queue behind it, so a steady stream of readers cannot
+//! starve writers. Like `MutexBlocking`, a release hands the lock over to
+//! the tasks it wakes.
+
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+use lock::Mutex;
+
+pub enum RwLockError {
+    /// the caller does not hold the lock the way the operation needs
+    NotHeld,
+    /// another reader is already waiting to upgrade, neither could go on
+    Deadlock,
+}
+
+pub struct RwLock {
+    inner: Mutex<RwLockInner>,
+}
+
+pub struct RwLockInner {
+    readers: usize,
+    writer: bool,
+    /// a reader waiting for the other readers to leave
+    upgrading: Option<Arc<TaskControlBlock>>,
+    read_queue: VecDeque<Arc<TaskControlBlock>>,
+    write_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RwLockInner {
+    fn writer_waiting(&self) -> bool {
+        self.upgrading.is_some() || !self.write_queue.is_empty()
+    }
+
+    fn admit_readers(&mut self) {
+        while let Some(task) = self.read_queue.pop_front() {
+            self.readers += 1;
+            add_task(task);
+        }
+    }
+
+    /// Called whenever a holder left: wake an upgrading reader once it is
+    /// the last one, otherwise a writer or else every reader once the lock
+    /// is free.
+    fn hand_over(&mut self) {
+        if self.readers == 1 && self.upgrading.is_some() {
+            self.readers = 0;
+            self.writer = true;
+            add_task(self.upgrading.take().unwrap());
+        } else if self.readers == 0 && !self.writer {
+            if let Some(task) = self.write_queue.pop_front() {
+                self.writer = true;
+                add_task(task);
+            } else {
+                self.admit_readers();
+            }
+        }
+    }
+}
+
+impl RwLock {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                Mutex::new(RwLockInner {
+                    readers: 0,
+                    writer: false,
+                    upgrading: None,
+                    read_queue: VecDeque::new(),
+                    write_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    pub fn read(&self) {
+        let mut inner = self.inner.lock();
+        if inner.writer || inner.writer_waiting() {
+            inner.read_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.readers += 1;
+        }
+    }
+
+    pub fn write(&self) {
+        let mut inner = self.inner.lock();
+        if inner.writer || inner.readers > 0 {
+            inner.write_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.writer = true;
+        }
+    }
+
+    /// Release the lock held for writing, or one hold for reading.
+    pub fn unlock(&self) -> Result<(), RwLockError> {
+        let mut inner = self.inner.lock();
+        if inner.writer {
+            inner.writer = false;
+        } else if inner.readers > 0 {
+            inner.readers -= 1;
+        } else {
+            return Err(RwLockError::NotHeld);
+        }
+        inner.hand_over();
+        Ok(())
+    }
+
+    /// Turn the caller's read hold into the write hold, waiting for the
+    /// other readers to leave. It goes before any writer already waiting.
+    pub fn upgrade(&self) -> Result<(), RwLockError> {
+        let mut inner = self.inner.lock();
+        if inner.writer || inner.readers == 0 {
+            return Err(RwLockError::NotHeld);
+        }
+        if inner.upgrading.is_some() {
+            return Err(RwLockError::Deadlock);
+        }
+        if inner.readers == 1 {
+            inner.readers = 0;
+            inner.writer = true;
+            return Ok(());
+        }
+        inner.upgrading = Some(current_task().unwrap());
+        drop(inner);
+        block_current_and_run_next();
+        Ok(())
+    }
+
+    /// Turn the caller's write hold into a read hold, readers queued behind
+    /// no writer come along.
+    pub fn downgrade(&self) -> Result<(), RwLockError> {
+        let mut inner = self.inner.lock();
+        if !inner.writer {
+            return Err(RwLockError::NotHeld);
+        }
+        inner.writer = false;
+        inner.readers = 1;
+        if !inner.writer_waiting() {
+            inner.admit_readers();
+        }
+        Ok(())
+    }
+
+    /// Held or waited for, it must not be destroyed.
+    pub fn is_busy(&self) -> bool {
+        let inner = self.inner.lock();
+        // readers and writers only queue while it is held
+        inner.writer || inner.readers > 0
+    }
+}
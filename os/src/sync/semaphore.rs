@@ -1,5 +1,6 @@
 use crate::sync::UPIntrFreeCell;
 use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use crate::timer::{block_current_with_timeout, remove_waiter, TimedWait};
 use alloc::{collections::VecDeque, sync::Arc};
 use lock::Mutex;
 
@@ -43,4 +44,44 @@ impl Semaphore {
             block_current_and_run_next();
         }
     }
+
+    /// False, without waiting, if no unit is available.
+    pub fn try_down(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.count > 0 {
+            inner.count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// False if no unit became available within `ms` milliseconds.
+    pub fn down_timeout(self: &Arc<Self>, ms: usize) -> bool {
+        let mut inner = self.inner.lock();
+        inner.count -= 1;
+        if inner.count < 0 {
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            return block_current_with_timeout(self.clone(), ms);
+        }
+        true
+    }
+
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.lock().wait_queue.is_empty()
+    }
+}
+
+impl TimedWait for Semaphore {
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = self.inner.lock();
+        if remove_waiter(&mut inner.wait_queue, task) {
+            // give back the unit it was waiting for
+            inner.count += 1;
+            true
+        } else {
+            false
+        }
+    }
 }
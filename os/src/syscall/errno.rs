@@ -0,0 +1,25 @@
+//! Linux error numbers. Native syscalls report failure as -1, except the
+//! ptrace, sync and message queue calls whose callers need the reason, the
+//! Linux personality returns the negated errno.
+
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EIO: isize = 5;
+pub const ENOEXEC: isize = 8;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const EEXIST: isize = 17;
+pub const ENODEV: isize = 19;
+pub const EINVAL: isize = 22;
+pub const ENOTTY: isize = 25;
+pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
+pub const ENOSYS: isize = 38;
+pub const ELOOP: isize = 40;
+pub const EMSGSIZE: isize = 90;
+pub const ETIMEDOUT: isize = 110;
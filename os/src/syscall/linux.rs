@@ -0,0 +1,706 @@
+//! The Linux personality.
+//!
+//! Binaries without the native ABI note, e.g. static musl programs, use the
+//! Linux RISC-V syscall table. Calls that mean the same in both tables are
+//! forwarded to the native handlers, the others are implemented here.
+//! Failures are reported as negated errno values, unknown syscalls as
+//! -ENOSYS instead of a kernel panic.
+
+use super::errno::*;
+use super::fs::{sys_close, sys_dup, sys_read, sys_write};
+use super::memory::sys_mprotect;
+use super::process::{
+    exec_file, reap_child, stop_status, sys_exit, sys_getpid, sys_getrlimit, sys_kill,
+    sys_setrlimit, sys_yield, translated_str_array, wait_status,
+};
+use super::ptrace::ptrace;
+use super::sync::{futex_errno, sys_sleep, FUTEX_WAIT, FUTEX_WAKE};
+use super::trace::{Arg::*, SyscallInfo};
+use crate::config::{CLOCK_FREQ, PAGE_SIZE, USER_SPACE_END};
+use crate::fs::{make_pipe, open_file, OpenFlags};
+use crate::mm::{
+    translated_ref, translated_refmut, translated_str, user_page_range, MapError, MapPermission,
+    RLimit, VirtAddr, VirtPageNum,
+};
+use crate::sync::{futex_wait, futex_wake};
+use crate::task::{
+    add_task_first_time, copy_to_user, current_task, current_user_token, out_of_memory,
+    user_writable, wait_tracee, SignalFlags, PID2TCB, THREAD_PAGES,
+};
+use crate::timer::get_time;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::mem::size_of;
+
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_DUP: usize = 23;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE2: usize = 59;
+const SYSCALL_READ: usize = 63;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_PTRACE: usize = 117;
+const SYSCALL_SCHED_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_RT_SIGACTION: usize = 134;
+const SYSCALL_RT_SIGPROCMASK: usize = 135;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
+const SYSCALL_GETTIMEOFDAY: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXECVE: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_WAIT4: usize = 260;
+
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    match syscall_id {
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
+        SYSCALL_DUP => or_errno(sys_dup(args[0]), EBADF),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_OPENAT => sys_openat(args[1] as *const u8, args[2] as u32),
+        SYSCALL_CLOSE => or_errno(sys_close(args[0]), EBADF),
+        SYSCALL_PIPE2 => sys_pipe2(args[0] as *mut i32),
+        SYSCALL_READ => or_errno(sys_read(args[0], args[1] as *const u8, args[2]), EBADF),
+        SYSCALL_WRITE => or_errno(sys_write(args[0], args[1] as *const u8, args[2]), EBADF),
+        SYSCALL_READV => sys_readv(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_WRITEV => sys_writev(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2], args[3] as *const TimeSpec),
+        SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(args[0]),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
+        SYSCALL_PTRACE => ptrace(args[0], args[1], args[2], args[3]).unwrap_or_else(|errno| -errno),
+        SYSCALL_SCHED_YIELD => sys_yield(),
+        SYSCALL_KILL => or_errno(sys_kill(args[0], args[1] as u32), ESRCH),
+        // signal handlers are not delivered to Linux programs yet
+        SYSCALL_RT_SIGACTION => 0,
+        SYSCALL_RT_SIGPROCMASK => sys_rt_sigprocmask(args[2] as *mut u64),
+        SYSCALL_UNAME => sys_uname(args[0] as *mut UtsName),
+        SYSCALL_GETRLIMIT => or_errno(sys_getrlimit(args[0], args[1] as *mut RLimit), EINVAL),
+        SYSCALL_SETRLIMIT => or_errno(sys_setrlimit(args[0], args[1] as *const RLimit), EPERM),
+        SYSCALL_GETTIMEOFDAY => sys_gettimeofday(args[0] as *mut TimeVal),
+        SYSCALL_GETPID => current_task().unwrap().tgid as isize,
+        SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_GETUID | SYSCALL_GETEUID | SYSCALL_GETGID | SYSCALL_GETEGID => 0,
+        // a thread's own pid is its Linux tid
+        SYSCALL_GETTID => sys_getpid(),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_CLONE => sys_clone(args[0], args[1], args[2], args[3], args[4]),
+        SYSCALL_EXECVE => sys_execve(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3]),
+        SYSCALL_MPROTECT => or_errno(sys_mprotect(args[0], args[1], args[2]), EINVAL),
+        SYSCALL_WAIT4 => sys_wait4(args[0] as isize, args[1] as *mut i32, args[2]),
+        _ => {
+            println!("[kernel] Unsupported Linux syscall_id: {}", syscall_id);
+            -ENOSYS
+        }
+    }
+}
+
+/// Name and argument kinds of syscall `id`, for the syscall trace.
+pub fn syscall_info(id: usize) -> Option<SyscallInfo> {
+    let info: SyscallInfo = match id {
+        SYSCALL_GETCWD => ("getcwd", &[Hex, Int]),
+        SYSCALL_DUP => ("dup", &[Int]),
+        SYSCALL_IOCTL => ("ioctl", &[Int, Hex, Hex]),
+        SYSCALL_OPENAT => ("openat", &[Int, Str, Hex]),
+        SYSCALL_CLOSE => ("close", &[Int]),
+        SYSCALL_PIPE2 => ("pipe2", &[Hex]),
+        SYSCALL_READ => ("read", &[Int, Hex, Int]),
+        SYSCALL_WRITE => ("write", &[Int, Buf(2), Int]),
+        SYSCALL_READV => ("readv", &[Int, Hex, Int]),
+        SYSCALL_WRITEV => ("writev", &[Int, Hex, Int]),
+        SYSCALL_EXIT => ("exit", &[Int]),
+        SYSCALL_EXIT_GROUP => ("exit_group", &[Int]),
+        SYSCALL_FUTEX => ("futex", &[Hex, Int, Int, Hex]),
+        SYSCALL_SET_TID_ADDRESS => ("set_tid_address", &[Hex]),
+        SYSCALL_NANOSLEEP => ("nanosleep", &[Hex, Hex]),
+        SYSCALL_CLOCK_GETTIME => ("clock_gettime", &[Int, Hex]),
+        SYSCALL_PTRACE => ("ptrace", &[Int, Int, Hex, Hex]),
+        SYSCALL_SCHED_YIELD => ("sched_yield", &[]),
+        SYSCALL_KILL => ("kill", &[Int, Int]),
+        SYSCALL_RT_SIGACTION => ("rt_sigaction", &[Int, Hex, Hex]),
+        SYSCALL_RT_SIGPROCMASK => ("rt_sigprocmask", &[Int, Hex, Hex]),
+        SYSCALL_UNAME => ("uname", &[Hex]),
+        SYSCALL_GETRLIMIT => ("getrlimit", &[Int, Hex]),
+        SYSCALL_SETRLIMIT => ("setrlimit", &[Int, Hex]),
+        SYSCALL_GETTIMEOFDAY => ("gettimeofday", &[Hex, Hex]),
+        SYSCALL_GETPID => ("getpid", &[]),
+        SYSCALL_GETPPID => ("getppid", &[]),
+        SYSCALL_GETUID => ("getuid", &[]),
+        SYSCALL_GETEUID => ("geteuid", &[]),
+        SYSCALL_GETGID => ("getgid", &[]),
+        SYSCALL_GETEGID => ("getegid", &[]),
+        SYSCALL_GETTID => ("gettid", &[]),
+        SYSCALL_BRK => ("brk", &[Hex]),
+        SYSCALL_MUNMAP => ("munmap", &[Hex, Hex]),
+        SYSCALL_CLONE => ("clone", &[Hex, Hex, Hex, Hex, Hex]),
+        SYSCALL_EXECVE => ("execve", &[Str, Hex, Hex]),
+        SYSCALL_MMAP => ("mmap", &[Hex, Hex, Hex, Hex, Int, Hex]),
+        SYSCALL_MPROTECT => ("mprotect", &[Hex, Hex, Hex]),
+        SYSCALL_WAIT4 => ("wait4", &[Int, Hex, Hex, Hex]),
+        _ => return None,
+    };
+    Some(info)
+}
+
+/// Native handlers fail with -1, which has no single errno.
+fn or_errno(ret: isize, errno: isize) -> isize {
+    if ret < 0 {
+        -errno
+    } else {
+        ret
+    }
+}
+
+/// Store `value` at `ptr` in user memory. Return false, having written
+/// nothing, unless user code may write all of it.
+fn put_user<T>(token: usize, ptr: *mut T, value: T) -> bool {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+    copy_to_user(token, ptr as usize, bytes)
+}
+
+/// The file system has no directories, everything lives in `/`.
+fn sys_getcwd(buf: *mut u8, size: usize) -> isize {
+    const CWD: &[u8] = b"/\0";
+    if size < CWD.len() {
+        return -ERANGE;
+    }
+    if !copy_to_user(current_user_token(), buf as usize, CWD) {
+        return -EFAULT;
+    }
+    CWD.len() as isize
+}
+
+const TIOCGWINSZ: usize = 0x5413;
+
+#[repr(C)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+/// Only enough for isatty() and line editing: the console reports a fixed
+/// 80x24 window.
+fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    if !file.is_tty() {
+        return -ENOTTY;
+    }
+    match request {
+        TIOCGWINSZ => {
+            let size = WinSize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            if !put_user(inner.get_user_token(), arg as *mut WinSize, size) {
+                return -EFAULT;
+            }
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+const O_ACCMODE: u32 = 0o3;
+const O_CREAT: u32 = 0o100;
+const O_TRUNC: u32 = 0o1000;
+
+/// The file system is flat, so the directory fd is ignored and leading
+/// `/` and `./` are dropped from the path.
+fn sys_openat(path: *const u8, flags: u32) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = path.trim_start_matches("./").trim_start_matches('/');
+    let mut open_flags = OpenFlags::from_bits_truncate(flags & O_ACCMODE);
+    if flags & O_CREAT != 0 {
+        open_flags |= OpenFlags::CREATE;
+    }
+    if flags & O_TRUNC != 0 {
+        open_flags |= OpenFlags::TRUNC;
+    }
+    if let Some(inode) = open_file(name, open_flags) {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        let fd = inner.alloc_fd();
+        inner.fd_table[fd] = Some(inode);
+        fd as isize
+    } else {
+        -ENOENT
+    }
+}
+
+/// Like the native pipe, but the fds are 32 bit ints.
+fn sys_pipe2(pipe: *mut i32) -> isize {
+    let task = current_task().unwrap();
+    let token = current_user_token();
+    let mut inner = task.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd();
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = inner.alloc_fd();
+    inner.fd_table[write_fd] = Some(pipe_write);
+    let fds = [read_fd as i32, write_fd as i32];
+    if !put_user(token, pipe as *mut [i32; 2], fds) {
+        inner.fd_table[read_fd] = None;
+        inner.fd_table[write_fd] = None;
+        return -EFAULT;
+    }
+    0
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+/// Stops at the first short transfer, like a single read would.
+fn sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let token = current_user_token();
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let iov = *translated_ref(token, unsafe { iov.add(i) });
+        let ret = sys_read(fd, iov.base as *const u8, iov.len);
+        if ret < 0 {
+            return if total > 0 { total } else { -EBADF };
+        }
+        total += ret;
+        if (ret as usize) < iov.len {
+            break;
+        }
+    }
+    total
+}
+
+fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let token = current_user_token();
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let iov = *translated_ref(token, unsafe { iov.add(i) });
+        let ret = sys_write(fd, iov.base as *const u8, iov.len);
+        if ret < 0 {
+            return if total > 0 { total } else { -EBADF };
+        }
+        total += ret;
+        if (ret as usize) < iov.len {
+            break;
+        }
+    }
+    total
+}
+
+/// Kill the other threads of the process, then exit. If the caller is not
+/// the main thread, the parent sees the main thread killed by SIGKILL.
+fn sys_exit_group(exit_code: i32) -> ! {
+    let task = current_task().unwrap();
+    let token = task.inner_exclusive_access().get_user_token();
+    let tasks: Vec<_> = PID2TCB.read(|map| map.values().cloned().collect());
+    for thread in tasks
+        .iter()
+        .filter(|thread| thread.tgid == task.tgid && !Arc::ptr_eq(thread, &task))
+    {
+        let mut inner = thread.inner_exclusive_access();
+        // kernel thread tgids come from another allocator
+        if inner.get_user_token() == token {
+            inner.signals.insert(SignalFlags::SIGKILL);
+        }
+    }
+    drop(tasks);
+    drop(task);
+    sys_exit(exit_code)
+}
+
+fn sys_set_tid_address(tidptr: usize) -> isize {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().clear_child_tid = tidptr;
+    task.pid.0 as isize
+}
+
+const NSEC_PER_SEC: usize = 1_000_000_000;
+const NSEC_PER_MSEC: usize = 1_000_000;
+const USEC_PER_SEC: usize = 1_000_000;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TimeSpec {
+    tv_sec: usize,
+    tv_nsec: usize,
+}
+
+#[repr(C)]
+struct TimeVal {
+    tv_sec: usize,
+    tv_usec: usize,
+}
+
+/// Sleeps are rounded up to whole milliseconds.
+fn sys_nanosleep(req: *const TimeSpec) -> isize {
+    let req = *translated_ref(current_user_token(), req);
+    if req.tv_nsec >= NSEC_PER_SEC {
+        return -EINVAL;
+    }
+    sys_sleep(req.tv_sec * 1000 + (req.tv_nsec + NSEC_PER_MSEC - 1) / NSEC_PER_MSEC)
+}
+
+/// There is no wall clock, every clock counts from boot.
+/// FUTEX_PRIVATE_FLAG and FUTEX_CLOCK_REALTIME make no difference here.
+const FUTEX_CMD_MASK: usize = 0x7f;
+
+fn sys_futex(uaddr: usize, op: usize, val: usize, timeout: *const TimeSpec) -> isize {
+    let token = current_user_token();
+    let result = match op & FUTEX_CMD_MASK {
+        FUTEX_WAIT => {
+            let timeout_ms = if timeout.is_null() {
+                None
+            } else {
+                let timeout = *translated_ref(token, timeout);
+                if timeout.tv_nsec >= NSEC_PER_SEC {
+                    return -EINVAL;
+                }
+                Some(timeout.tv_sec * 1000 + (timeout.tv_nsec + NSEC_PER_MSEC - 1) / NSEC_PER_MSEC)
+            };
+            futex_wait(token, uaddr, val as u32, timeout_ms).map(|_| 0)
+        }
+        FUTEX_WAKE => futex_wake(token, uaddr, val).map(|woken| woken as isize),
+        _ => return -ENOSYS,
+    };
+    result.unwrap_or_else(|err| -futex_errno(err))
+}
+
+fn sys_clock_gettime(_clock_id: usize, tp: *mut TimeSpec) -> isize {
+    let ticks = get_time();
+    let time = TimeSpec {
+        tv_sec: ticks / CLOCK_FREQ,
+        tv_nsec: ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ,
+    };
+    if !put_user(current_user_token(), tp, time) {
+        return -EFAULT;
+    }
+    0
+}
+
+fn sys_gettimeofday(tv: *mut TimeVal) -> isize {
+    let ticks = get_time();
+    let time = TimeVal {
+        tv_sec: ticks / CLOCK_FREQ,
+        tv_usec: ticks % CLOCK_FREQ * USEC_PER_SEC / CLOCK_FREQ,
+    };
+    if !put_user(current_user_token(), tv, time) {
+        return -EFAULT;
+    }
+    0
+}
+
+/// No signal is blocked, the new mask is ignored.
+fn sys_rt_sigprocmask(oldset: *mut u64) -> isize {
+    if !oldset.is_null() && !put_user(current_user_token(), oldset, 0) {
+        return -EFAULT;
+    }
+    0
+}
+
+const UTS_LEN: usize = 65;
+
+#[repr(C)]
+struct UtsName {
+    sysname: [u8; UTS_LEN],
+    nodename: [u8; UTS_LEN],
+    release: [u8; UTS_LEN],
+    version: [u8; UTS_LEN],
+    machine: [u8; UTS_LEN],
+    domainname: [u8; UTS_LEN],
+}
+
+fn uts_field(value: &str) -> [u8; UTS_LEN] {
+    let mut field = [0; UTS_LEN];
+    field[..value.len()].copy_from_slice(value.as_bytes());
+    field
+}
+
+fn sys_uname(buf: *mut UtsName) -> isize {
+    let name = UtsName {
+        sysname: uts_field("Linux"),
+        nodename: uts_field("rcore"),
+        release: uts_field("5.15.0-rcore"),
+        version: uts_field("#1 SMP"),
+        machine: uts_field("riscv64"),
+        domainname: uts_field("(none)"),
+    };
+    if !put_user(current_user_token(), buf, name) {
+        return -EFAULT;
+    }
+    0
+}
+
+fn sys_getppid() -> isize {
+    let task = current_task().unwrap();
+    let parent = task.inner_exclusive_access().parent.clone();
+    parent
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.tgid as isize)
+}
+
+/// Return the new break, or the old one if it cannot be moved.
+fn sys_brk(brk: usize) -> isize {
+    let task = current_task().unwrap();
+    loop {
+        let inner = task.inner_exclusive_access();
+        let mut memory_set = inner.memory_set.lock();
+        if brk < memory_set.heap_base() || brk > USER_SPACE_END {
+            return memory_set.brk() as isize;
+        }
+        match memory_set.set_brk(brk) {
+            Ok(()) => break,
+            Err(MapError::OutOfMemory) => {
+                let pages = (brk - memory_set.brk()) / PAGE_SIZE;
+                drop(memory_set);
+                drop(inner);
+                if !out_of_memory(pages) {
+                    return task.inner_exclusive_access().memory_set.lock().brk() as isize;
+                }
+            }
+            Err(_) => return memory_set.brk() as isize,
+        }
+    }
+    unsafe {
+        asm!("sfence.vma");
+    }
+    brk as isize
+}
+
+fn sys_munmap(start: usize, len: usize) -> isize {
+    let (start_vpn, end_vpn) = match user_page_range(start, len) {
+        Some(range) if len != 0 => range,
+        _ => return -EINVAL,
+    };
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if !inner.memory_set.lock().unmap_range(start_vpn, end_vpn) {
+        return -EINVAL;
+    }
+    drop(inner);
+    unsafe {
+        asm!("sfence.vma");
+    }
+    0
+}
+
+const PROT_MASK: usize = 0x7;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// Anonymous mappings only. MAP_SHARED ones are copied on fork like
+/// private ones, use the shm syscalls to share memory.
+fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize) -> isize {
+    if len == 0 || prot & !PROT_MASK != 0 {
+        return -EINVAL;
+    }
+    if flags & MAP_ANONYMOUS == 0 {
+        return -ENODEV;
+    }
+    if len > USER_SPACE_END {
+        return -ENOMEM;
+    }
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let fixed = if flags & MAP_FIXED != 0 {
+        match user_page_range(start, len) {
+            Some((start_vpn, _)) => Some(start_vpn),
+            None => return -EINVAL,
+        }
+    } else {
+        None
+    };
+    let perm = MapPermission::from_bits((prot << 1) as u8).unwrap();
+    let task = current_task().unwrap();
+    let start_vpn = loop {
+        let inner = task.inner_exclusive_access();
+        let mut memory_set = inner.memory_set.lock();
+        let start_vpn = match fixed {
+            Some(start_vpn) => {
+                // a fixed mapping replaces whatever was there
+                let end_vpn = VirtPageNum(start_vpn.0 + pages);
+                if !memory_set.unmap_range(start_vpn, end_vpn) {
+                    return -EINVAL;
+                }
+                start_vpn
+            }
+            None => match memory_set.find_free_area(pages) {
+                Some(start_vpn) => start_vpn,
+                None => return -ENOMEM,
+            },
+        };
+        let end_vpn = VirtPageNum(start_vpn.0 + pages);
+        match memory_set.map_anonymous(start_vpn, end_vpn, perm) {
+            Ok(()) => break start_vpn,
+            Err(MapError::OutOfMemory) => {
+                drop(memory_set);
+                drop(inner);
+                if !out_of_memory(pages) {
+                    return -ENOMEM;
+                }
+            }
+            Err(_) => return -ENOMEM,
+        }
+    };
+    unsafe {
+        asm!("sfence.vma");
+    }
+    VirtAddr::from(start_vpn).0 as isize
+}
+
+const CLONE_VM: usize = 0x100;
+const CLONE_THREAD: usize = 0x10000;
+const CLONE_SETTLS: usize = 0x80000;
+const CLONE_PARENT_SETTID: usize = 0x100000;
+const CLONE_CHILD_CLEARTID: usize = 0x200000;
+const CLONE_CHILD_SETTID: usize = 0x1000000;
+
+/// With CLONE_THREAD the child is a thread sharing the address space,
+/// otherwise it is a forked process. CLONE_VM alone, as used for vfork,
+/// would need a process sharing its parent's address space and is refused.
+/// The child returns 0 on `stack` if one is given.
+fn sys_clone(flags: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) -> isize {
+    let thread = flags & CLONE_THREAD != 0;
+    if thread != (flags & CLONE_VM != 0) {
+        return -EINVAL;
+    }
+    // the tids are written once the child exists, which is too late to fail
+    let token = current_user_token();
+    let tid_writable = |flag: usize, ptr: usize| {
+        flags & flag == 0
+            || ptr % size_of::<u32>() == 0 && user_writable(token, ptr, size_of::<u32>())
+    };
+    if !tid_writable(CLONE_PARENT_SETTID, ptid) || !tid_writable(CLONE_CHILD_SETTID, ctid) {
+        return -EFAULT;
+    }
+    let task = current_task().unwrap();
+    let pages = if thread {
+        THREAD_PAGES
+    } else {
+        task.inner_exclusive_access()
+            .memory_set
+            .lock()
+            .resident_pages()
+    };
+    let child = loop {
+        let result = if thread {
+            task.new_user_thread(0, 0, task.tgid)
+        } else {
+            task.fork()
+        };
+        match result {
+            Ok(child) => break child,
+            Err(MapError::OutOfMemory) if out_of_memory(pages) => {}
+            Err(_) => return -ENOMEM,
+        }
+    };
+    let child_tid = child.pid.0;
+    let parent_cx = *task.inner_exclusive_access().get_trap_cx();
+    let mut child_inner = child.inner_exclusive_access();
+    let trap_cx = child_inner.get_trap_cx();
+    // a new thread starts where the parent returns to, on its own kernel stack
+    let kernel_sp = trap_cx.kernel_sp;
+    *trap_cx = parent_cx;
+    trap_cx.kernel_sp = kernel_sp;
+    trap_cx.x[10] = 0;
+    if stack != 0 {
+        trap_cx.set_sp(stack);
+    }
+    if flags & CLONE_SETTLS != 0 {
+        trap_cx.x[4] = tls;
+    }
+    if flags & CLONE_CHILD_CLEARTID != 0 {
+        child_inner.clear_child_tid = ctid;
+    }
+    if flags & CLONE_CHILD_SETTID != 0 {
+        *translated_refmut(child_inner.get_user_token(), ctid as *mut u32) = child_tid as u32;
+    }
+    drop(child_inner);
+    if flags & CLONE_PARENT_SETTID != 0 {
+        *translated_refmut(current_user_token(), ptid as *mut u32) = child_tid as u32;
+    }
+    add_task_first_time(child);
+    child_tid as isize
+}
+
+/// The new image starts with a0 = 0, no exit handler from a loader.
+fn sys_execve(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let args = translated_str_array(token, args);
+    let envs = translated_str_array(token, envs);
+    match exec_file(path.as_str(), args, envs) {
+        Ok(_) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+const WNOHANG: usize = 1;
+
+/// Process groups do not exist, so any pid below 1 waits for any child.
+/// Stops of tracees are reported without WUNTRACED, as on Linux.
+fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize) -> isize {
+    let pid = if pid < 1 { -1 } else { pid };
+    loop {
+        let stopped = wait_tracee(pid);
+        if let Ok(Some((found_pid, signum))) = stopped {
+            if !wstatus.is_null() && !put_user(current_user_token(), wstatus, stop_status(signum)) {
+                return -EFAULT;
+            }
+            return found_pid as isize;
+        }
+        match reap_child(pid) {
+            Err(()) if stopped.is_err() => return -ECHILD,
+            Ok(Some((found_pid, exit_code))) => {
+                let status = wait_status(exit_code);
+                if !wstatus.is_null() && !put_user(current_user_token(), wstatus, status) {
+                    return -EFAULT;
+                }
+                return found_pid as isize;
+            }
+            _ if options & WNOHANG != 0 => return 0,
+            _ => {
+                sys_yield();
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::config::PAGE_SIZE;
+use crate::mm::{shm_open, user_page_range, MapError, MapPermission, ShmError, ShmFlags, VirtAddr};
+use crate::task::{current_task, out_of_memory};
+use core::arch::asm;
+
+const PROT_MASK: usize = 0x7;
+
+/// `prot` uses the Linux bits: PROT_READ = 1, PROT_WRITE = 2, PROT_EXEC = 4.
+/// `start` must be page aligned and the whole range must be mapped.
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    let (start_vpn, end_vpn) = match user_page_range(start, len) {
+        Some(range) if prot & !PROT_MASK == 0 => range,
+        _ => return -1,
+    };
+    if len == 0 {
+        return 0;
+    }
+    let perm = MapPermission::from_bits((prot << 1) as u8).unwrap();
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if !inner.memory_set.lock().mprotect(start_vpn, end_vpn, perm) {
+        return -1;
+    }
+    drop(inner);
+    unsafe {
+        asm!("sfence.vma");
+    }
+    0
+}
+
+pub fn sys_shm_open(key: usize, size: usize, flags: u32) -> isize {
+    let flags = match ShmFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    let shm = loop {
+        match shm_open(key, size, flags) {
+            Ok(shm) => break shm,
+            Err(ShmError::OutOfMemory) if out_of_memory(size / PAGE_SIZE) => {}
+            Err(_) => return -1,
+        }
+    };
+    let process = current_task().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .shm_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.shm_list[id] = Some(shm);
+        id
+    } else {
+        process_inner.shm_list.push(Some(shm));
+        process_inner.shm_list.len() - 1
+    };
+    id as isize
+}
+
+/// Map the object behind `shm_id` readable and writable, return its address.
+pub fn sys_shm_map(shm_id: usize) -> isize {
+    let process = current_task().unwrap();
+    loop {
+        let process_inner = process.inner_exclusive_access();
+        let shm = match process_inner.shm_list.get(shm_id) {
+            Some(Some(shm)) => shm.clone(),
+            _ => return -1,
+        };
+        let result = process_inner
+            .memory_set
+            .lock()
+            .map_shared(&shm, MapPermission::R | MapPermission::W | MapPermission::U);
+        drop(process_inner);
+        match result {
+            Ok(start_va) => return usize::from(start_va) as isize,
+            // the frames are there, only page tables are missing
+            Err(MapError::OutOfMemory) if out_of_memory(1) => {}
+            Err(_) => return -1,
+        }
+    }
+}
+
+pub fn sys_shm_unmap(addr: usize) -> isize {
+    let start_va = VirtAddr::from(addr);
+    if !start_va.aligned() {
+        return -1;
+    }
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    if !process_inner
+        .memory_set
+        .lock()
+        .unmap_shared(start_va.floor())
+    {
+        return -1;
+    }
+    drop(process_inner);
+    unsafe {
+        asm!("sfence.vma");
+    }
+    0
+}
+
+pub fn sys_shm_close(shm_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.shm_list.get_mut(shm_id) {
+        Some(item) if item.is_some() => {
+            let shm = item.take();
+            drop(process_inner);
+            // the object may be destroyed here, outside of the PCB lock
+            drop(shm);
+            0
+        }
+        _ => -1,
+    }
+}
@@ -5,38 +5,113 @@ const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_GET_PRIORITY: usize = 141;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_TRYLOCK: usize = 1013;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1014;
+const SYSCALL_MUTEX_DESTROY: usize = 1015;
+const SYSCALL_MUTEX_OPEN: usize = 1016;
+const SYSCALL_MUTEX_UNLINK: usize = 1017;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_TRYDOWN: usize = 1023;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1024;
+const SYSCALL_SEMAPHORE_DESTROY: usize = 1025;
+const SYSCALL_SEMAPHORE_OPEN: usize = 1026;
+const SYSCALL_SEMAPHORE_UNLINK: usize = 1027;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
+const SYSCALL_CONDVAR_DESTROY: usize = 1034;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1035;
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+const SYSCALL_RWLOCK_UPGRADE: usize = 1044;
+const SYSCALL_RWLOCK_DOWNGRADE: usize = 1045;
+const SYSCALL_RWLOCK_DESTROY: usize = 1046;
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+const SYSCALL_BARRIER_DESTROY: usize = 1052;
+const SYSCALL_SYNC_EXPORT: usize = 1060;
+const SYSCALL_SYNC_IMPORT: usize = 1061;
+const SYSCALL_SYNC_CLOSE: usize = 1062;
+const SYSCALL_SHM_OPEN: usize = 1100;
+const SYSCALL_SHM_MAP: usize = 1101;
+const SYSCALL_SHM_UNMAP: usize = 1102;
+const SYSCALL_SHM_CLOSE: usize = 1103;
+const SYSCALL_STRACE: usize = 1110;
+const SYSCALL_STRACE_READ: usize = 1111;
+const SYSCALL_MQ_OPEN: usize = 1120;
+const SYSCALL_MQ_SEND: usize = 1121;
+const SYSCALL_MQ_RECEIVE: usize = 1122;
+const SYSCALL_MQ_UNLINK: usize = 1123;
 
+mod errno;
 mod fs;
+mod linux;
+mod memory;
+mod mqueue;
 mod process;
+mod ptrace;
 mod sync;
 mod thread;
+mod trace;
 
+use crate::mm::RLimit;
+use crate::task::{current_task, Personality};
 use fs::*;
+use memory::*;
+use mqueue::*;
 use process::*;
+use ptrace::*;
 use sync::*;
 use thread::*;
+use trace::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+/// `args` are a0 to a5 of the calling thread, most syscalls use fewer.
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    let (personality, traced) = {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        (inner.personality, inner.syscall_trace)
+    };
+    if !traced {
+        return dispatch(personality, syscall_id, args);
+    }
+    let call = trace_enter(personality, syscall_id, &args);
+    let ret = dispatch(personality, syscall_id, args);
+    trace_leave(call, ret);
+    ret
+}
+
+fn dispatch(personality: Personality, syscall_id: usize, args: [usize; 6]) -> isize {
+    if personality == Personality::Linux {
+        return linux::syscall(syscall_id, args);
+    }
     match syscall_id {
         SYSCALL_DUP => sys_dup(args[0]),
         SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
@@ -45,26 +120,76 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2], args[3]),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_PTRACE => sys_ptrace(args[0], args[1], args[2], args[3]),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
+        SYSCALL_SET_PRIORITY => sys_set_priority(args[0]),
+        SYSCALL_GET_PRIORITY => sys_get_priority(),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1] as *mut RLimit),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1] as *const RLimit),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_EXEC => sys_exec(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_ENABLE_DEADLOCK_DETECT => sys_enable_deadlock_detect(args[0]),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
         SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
         SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
         SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
+        SYSCALL_MUTEX_TRYLOCK => sys_mutex_trylock(args[0]),
+        SYSCALL_MUTEX_LOCK_TIMEOUT => sys_mutex_lock_timeout(args[0], args[1]),
+        SYSCALL_MUTEX_DESTROY => sys_mutex_destroy(args[0]),
+        SYSCALL_MUTEX_OPEN => sys_mutex_open(args[0], args[1] as u32),
+        SYSCALL_MUTEX_UNLINK => sys_mutex_unlink(args[0]),
         SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
         SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
         SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
+        SYSCALL_SEMAPHORE_TRYDOWN => sys_semaphore_trydown(args[0]),
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT => sys_semaphore_down_timeout(args[0], args[1]),
+        SYSCALL_SEMAPHORE_DESTROY => sys_semaphore_destroy(args[0]),
+        SYSCALL_SEMAPHORE_OPEN => sys_semaphore_open(args[0], args[1] as u32, args[2]),
+        SYSCALL_SEMAPHORE_UNLINK => sys_semaphore_unlink(args[0]),
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(args[0]),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_CONDVAR_WAIT_TIMEOUT => sys_condvar_wait_timeout(args[0], args[1], args[2]),
+        SYSCALL_CONDVAR_DESTROY => sys_condvar_destroy(args[0]),
+        SYSCALL_CONDVAR_BROADCAST => sys_condvar_broadcast(args[0]),
+        SYSCALL_RWLOCK_CREATE => sys_rwlock_create(),
+        SYSCALL_RWLOCK_READ => sys_rwlock_read(args[0]),
+        SYSCALL_RWLOCK_WRITE => sys_rwlock_write(args[0]),
+        SYSCALL_RWLOCK_UNLOCK => sys_rwlock_unlock(args[0]),
+        SYSCALL_RWLOCK_UPGRADE => sys_rwlock_upgrade(args[0]),
+        SYSCALL_RWLOCK_DOWNGRADE => sys_rwlock_downgrade(args[0]),
+        SYSCALL_RWLOCK_DESTROY => sys_rwlock_destroy(args[0]),
+        SYSCALL_BARRIER_CREATE => sys_barrier_create(args[0]),
+        SYSCALL_BARRIER_WAIT => sys_barrier_wait(args[0]),
+        SYSCALL_BARRIER_DESTROY => sys_barrier_destroy(args[0]),
+        SYSCALL_SYNC_EXPORT => sys_sync_export(args[0], args[1]),
+        SYSCALL_SYNC_IMPORT => sys_sync_import(args[0]),
+        SYSCALL_SYNC_CLOSE => sys_sync_close(args[0], args[1]),
+        SYSCALL_SHM_OPEN => sys_shm_open(args[0], args[1], args[2] as u32),
+        SYSCALL_SHM_MAP => sys_shm_map(args[0]),
+        SYSCALL_SHM_UNMAP => sys_shm_unmap(args[0]),
+        SYSCALL_SHM_CLOSE => sys_shm_close(args[0]),
+        SYSCALL_STRACE => sys_strace(args[0], args[1]),
+        SYSCALL_STRACE_READ => sys_strace_read(args[0] as *mut usize, args[1] as *mut u8, args[2]),
+        SYSCALL_MQ_OPEN => sys_mq_open(args[0], args[1] as u32, args[2], args[3]),
+        SYSCALL_MQ_SEND => sys_mq_send(args[0], args[1] as *const u8, args[2], args[3], args[4]),
+        SYSCALL_MQ_RECEIVE => {
+            sys_mq_receive(args[0], args[1] as *mut u8, args[2], args[3] as *mut usize, args[4])
+        }
+        SYSCALL_MQ_UNLINK => sys_mq_unlink(args[0]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
@@ -0,0 +1,113 @@
+use super::errno::{EAGAIN, EBADF, EEXIST, EFAULT, EINVAL, EMSGSIZE, ENOENT, ETIMEDOUT};
+use crate::fs::{mq_open, mq_unlink, File, MqError, MqFlags};
+use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
+use crate::task::{copy_to_user, current_task, current_user_token, user_writable};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+fn mq_errno(err: MqError) -> isize {
+    match err {
+        MqError::Invalid => EINVAL,
+        MqError::NotFound => ENOENT,
+        MqError::Exists => EEXIST,
+        MqError::MsgSize => EMSGSIZE,
+        MqError::WouldBlock => EAGAIN,
+        MqError::TimedOut => ETIMEDOUT,
+    }
+}
+
+/// The file behind `fd` if it is a message queue.
+fn mq_file(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = current_task().unwrap();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) if file.mqueue().is_some() => Some(file.clone()),
+        _ => None,
+    }
+}
+
+/// Open the queue called by the string at `name` and return an fd for it.
+/// A queue created here holds `max_msgs` messages of `msg_size` bytes, 0
+/// takes the default. -ENOENT if there is none and -EEXIST if one exists
+/// already but CREATE | EXCL was asked.
+pub fn sys_mq_open(name: usize, flags: u32, max_msgs: usize, msg_size: usize) -> isize {
+    let flags = match MqFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let name = translated_str(current_user_token(), name as *const u8);
+    let mq = match mq_open(&name, flags, max_msgs, msg_size) {
+        Ok(mq) => mq,
+        Err(err) => return -mq_errno(err),
+    };
+    let process = current_task().unwrap();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(mq);
+    fd as isize
+}
+
+/// Send `len` bytes at `buf` with `priority`, waiting for room for at most
+/// `timeout_ms` milliseconds unless that is 0. -EAGAIN if the queue is full
+/// and `fd` was opened NONBLOCK, -EMSGSIZE if the message is too large.
+pub fn sys_mq_send(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    priority: usize,
+    timeout_ms: usize,
+) -> isize {
+    let file = match mq_file(fd) {
+        Some(file) if file.writable() => file,
+        _ => return -EBADF,
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(current_user_token(), buf, len));
+    let data: Vec<u8> = buf.into_iter().map(|byte| unsafe { *byte }).collect();
+    match file.mqueue().unwrap().send(data, priority, timeout_ms) {
+        Ok(()) => 0,
+        Err(err) => -mq_errno(err),
+    }
+}
+
+/// Receive the oldest message of the highest priority into `buf` and return
+/// its length, storing its priority at `priority` unless that is null. The
+/// buffer must hold the largest message the queue takes, or -EMSGSIZE, and
+/// both must be writable, or -EFAULT before any message is taken.
+pub fn sys_mq_receive(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    priority: *mut usize,
+    timeout_ms: usize,
+) -> isize {
+    let file = match mq_file(fd) {
+        Some(file) if file.readable() => file,
+        _ => return -EBADF,
+    };
+    let token = current_user_token();
+    let priority_ok = priority.is_null()
+        || (priority as usize % size_of::<usize>() == 0
+            && user_writable(token, priority as usize, size_of::<usize>()));
+    if !priority_ok || !user_writable(token, buf as usize, len) {
+        return -EFAULT;
+    }
+    let (data, message_priority) = match file.mqueue().unwrap().receive(len, timeout_ms) {
+        Ok(message) => message,
+        Err(err) => return -mq_errno(err),
+    };
+    copy_to_user(token, buf as usize, &data);
+    if !priority.is_null() {
+        *translated_refmut(token, priority) = message_priority;
+    }
+    data.len() as isize
+}
+
+/// -ENOENT if no queue has the name.
+pub fn sys_mq_unlink(name: usize) -> isize {
+    let name = translated_str(current_user_token(), name as *const u8);
+    match mq_unlink(&name) {
+        Ok(()) => 0,
+        Err(err) => -mq_errno(err),
+    }
+}
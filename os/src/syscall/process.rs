@@ -1,13 +1,17 @@
+use super::errno::{ELOOP, ENOENT, ENOEXEC, ENOMEM};
+use crate::config::PAGE_SIZE;
 use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use crate::mm::{translated_ref, translated_refmut, translated_str, MapError, RLimit};
+use crate::sync::update_priority;
 use crate::task::{
     current_task, current_user_token, exit_current_and_run_next,
     suspend_current_and_run_next, SignalFlags, SignalAction, MAX_SIG, add_task_first_time,
-    pid2task,WAIT_LOCK
+    pid2task,WAIT_LOCK, out_of_memory, wait_tracee, PID2TCB
 };
 use crate::timer::get_time_ms;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -28,10 +32,39 @@ pub fn sys_getpid() -> isize {
     current_task().unwrap().pid.0 as isize
 }
 
+/// Set the base priority of the calling thread, higher runs first. It may
+/// go back up to the priority the thread was created with, not beyond.
+pub fn sys_set_priority(prio: usize) -> isize {
+    let task = current_task().unwrap();
+    if prio > task.priority.limit() {
+        return -1;
+    }
+    task.priority.set_base(prio);
+    update_priority(task);
+    prio as isize
+}
+
+/// The priority the calling thread runs at, its base priority or that of
+/// a waiter it inherited.
+pub fn sys_get_priority() -> isize {
+    current_task().unwrap().priority.effective() as isize
+}
+
 pub fn sys_fork() -> isize {
     // let wl = WAIT_LOCK.lock();
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let pages = current_task
+        .inner_exclusive_access()
+        .memory_set
+        .lock()
+        .resident_pages();
+    let new_task = loop {
+        match current_task.fork() {
+            Ok(new_task) => break new_task,
+            Err(MapError::OutOfMemory) if out_of_memory(pages) => {}
+            Err(_) => return -1,
+        }
+    };
 
     // drop(wl);
     let new_pid = new_task.pid.0;
@@ -47,52 +80,126 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    let mut args_vec: Vec<String> = Vec::new();
+/// Read a null terminated array of string pointers, null reads as empty.
+pub fn translated_str_array(token: usize, mut array: *const usize) -> Vec<String> {
+    let mut strings = Vec::new();
+    if array.is_null() {
+        return strings;
+    }
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
-        if arg_str_ptr == 0 {
+        let str_ptr = *translated_ref(token, array);
+        if str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        strings.push(translated_str(token, str_ptr as *const u8));
         unsafe {
-            args = args.add(1);
+            array = array.add(1);
         }
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let current_pid = task.pid.0;
-        let argc = args_vec.len();
-        task.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
-    } else {
-        -1
+    strings
+}
+
+/// Nested `#!` interpreters followed at most, like Linux.
+const MAX_SHEBANG_DEPTH: usize = 4;
+/// Longest `#!` line, including the newline.
+const MAX_SHEBANG_LINE: usize = 256;
+
+/// Split `#!interpreter [argument]` off the first line, Ok(None) if `data`
+/// is not a script. Like Linux, everything after the interpreter is one
+/// argument.
+fn parse_shebang(data: &[u8]) -> Result<Option<(String, Option<String>)>, isize> {
+    if !data.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let line_len = data
+        .iter()
+        .take(MAX_SHEBANG_LINE)
+        .position(|&byte| byte == b'\n')
+        .unwrap_or(data.len().min(MAX_SHEBANG_LINE));
+    let line = core::str::from_utf8(&data[2..line_len]).map_err(|_| ENOEXEC)?;
+    let line = line.trim();
+    let (interp, arg) = match line.split_once(|c: char| c == ' ' || c == '\t') {
+        Some((interp, arg)) => (interp, Some(String::from(arg.trim()))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(ENOEXEC);
     }
+    Ok(Some((String::from(interp), arg)))
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Replace the image of the current task with the program at `path`,
+/// Err holds the errno of the failure. A script is replaced by its
+/// interpreter, with the script path in front of the original arguments.
+pub fn exec_file(path: &str, mut args: Vec<String>, envs: Vec<String>) -> Result<isize, isize> {
+    let mut path = String::from(path);
+    let mut depth = 0;
+    let all_data = loop {
+        // the file system is flat, only the file name of a path counts
+        let name = path.rsplit('/').next().unwrap();
+        let data = open_file(name, OpenFlags::RDONLY).ok_or(ENOENT)?.read_all();
+        let (interp, arg) = match parse_shebang(&data)? {
+            Some(shebang) => shebang,
+            None => break data,
+        };
+        depth += 1;
+        if depth > MAX_SHEBANG_DEPTH {
+            return Err(ELOOP);
+        }
+        let mut interp_args = vec![interp.clone()];
+        interp_args.extend(arg);
+        interp_args.push(path);
+        interp_args.extend(args.into_iter().skip(1));
+        args = interp_args;
+        path = interp;
+    };
+    let task = current_task().unwrap();
+    let argc = args.len();
+    loop {
+        match task.exec(all_data.as_slice(), args.clone(), envs.clone()) {
+            Ok(()) => break,
+            Err(MapError::OutOfMemory) if out_of_memory(all_data.len() / PAGE_SIZE) => {}
+            Err(MapError::InvalidElf) => return Err(ENOEXEC),
+            Err(_) => return Err(ENOMEM),
+        }
+    }
+    // like Linux, a tracee stops with SIGTRAP in its new image
+    let mut inner = task.inner_exclusive_access();
+    if let Some(trace) = inner.ptrace.as_mut() {
+        trace.forget_breakpoints();
+    }
+    if inner.ptrace.is_some() {
+        inner.signals.insert(SignalFlags::SIGTRAP);
+    }
+    drop(inner);
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc as isize)
+}
+
+pub fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let args_vec = translated_str_array(token, args);
+    let envs_vec = translated_str_array(token, envs);
+    exec_file(path.as_str(), args_vec, envs_vec).unwrap_or(-1)
+}
 
-    
+/// Reap a zombie child, `pid` -1 means any child.
+/// Err if there is no such child, Ok(None) if it is still running.
+pub fn reap_child(pid: isize) -> Result<Option<(usize, i32)>, ()> {
     let task = current_task().unwrap();
     // find a child process
-    
+
     // ---- access current PCB exclusively
     let _ = WAIT_LOCK.lock();
     let mut inner = task.inner_exclusive_access();
-    
+
     if !inner
         .children
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        println!("no child process with pid {}", pid);
-        return -1;
+        return Err(());
         // ---- release current PCB
     }
 
@@ -111,14 +218,54 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code.unwrap();
-        found_pid as isize
+        Ok(Some((found_pid, exit_code.unwrap())))
     } else {
-        -2
+        Ok(None)
     }
     // ---- release current PCB automatically
 }
 
+const WUNTRACED: usize = 2;
+
+/// Exit codes of killed tasks are the negated signal number.
+pub fn wait_status(exit_code: i32) -> i32 {
+    if exit_code < 0 {
+        -exit_code & 0x7f
+    } else {
+        (exit_code & 0xff) << 8
+    }
+}
+
+/// The status of a tracee that stopped with `signum`.
+pub fn stop_status(signum: u32) -> i32 {
+    (signum as i32) << 8 | 0x7f
+}
+
+/// If there is not a child process whose pid is same as given, return -1.
+/// Else if there is a child process but it is still running, return -2.
+/// With WUNTRACED, stops of tracees are reported too and the status is
+/// encoded like on Linux, see `wait_status`.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    let traced = options & WUNTRACED != 0;
+    let stopped = if traced { wait_tracee(pid) } else { Err(()) };
+    if let Ok(Some((found_pid, signum))) = stopped {
+        *translated_refmut(current_user_token(), exit_code_ptr) = stop_status(signum);
+        return found_pid as isize;
+    }
+    match reap_child(pid) {
+        Err(()) if stopped.is_err() => {
+            println!("no child process with pid {}", pid);
+            -1
+        }
+        Ok(Some((found_pid, exit_code))) => {
+            let status = if traced { wait_status(exit_code) } else { exit_code };
+            *translated_refmut(current_user_token(), exit_code_ptr) = status;
+            found_pid as isize
+        }
+        _ => -2,
+    }
+}
+
 pub fn sys_kill(pid: usize, signum: u32) -> isize {
     let signum = signum as usize;
     if let Some(task) = pid2task(pid) {
@@ -201,3 +348,61 @@ pub fn sys_sigaction(signum: i32, action: *const SignalAction, old_action: *mut
     }
     -1
 }
+
+const RLIMIT_CORE: usize = 4;
+const RLIMIT_RSS: usize = 5;
+const RLIMIT_AS: usize = 9;
+
+/// Only the memory and core dump limits are supported; without demand
+/// paging the resident set and the address space are the same, so
+/// RLIMIT_RSS and RLIMIT_AS both name the memory limit.
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let limit = match resource {
+        RLIMIT_RSS | RLIMIT_AS => inner.memory_set.lock().mem_limit(),
+        RLIMIT_CORE => inner.core_limit,
+        _ => return -1,
+    };
+    *translated_refmut(inner.get_user_token(), rlim) = limit;
+    0
+}
+
+/// The hard limit can only be lowered and bounds the soft limit.
+/// The limits are the process's: the memory limit lives in the address
+/// space its threads share, the core limit is set for all of them. Both
+/// are inherited by the processes it forks afterwards.
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let old = match resource {
+        RLIMIT_RSS | RLIMIT_AS => inner.memory_set.lock().mem_limit(),
+        RLIMIT_CORE => inner.core_limit,
+        _ => return -1,
+    };
+    let new = *translated_ref(inner.get_user_token(), rlim);
+    if new.rlim_cur > new.rlim_max || new.rlim_max > old.rlim_max {
+        return -1;
+    }
+    if resource != RLIMIT_CORE {
+        inner.memory_set.lock().set_mem_limit(new);
+        return 0;
+    }
+    // every thread keeps a copy of the core limit
+    let token = inner.get_user_token();
+    drop(inner);
+    let threads: Vec<_> = PID2TCB.read(|map| {
+        map.values()
+            .filter(|thread| thread.tgid == task.tgid)
+            .cloned()
+            .collect()
+    });
+    for thread in threads {
+        let mut inner = thread.inner_exclusive_access();
+        // kernel thread tgids come from another allocator
+        if inner.get_user_token() == token {
+            inner.core_limit = new;
+        }
+    }
+    0
+}
@@ -0,0 +1,142 @@
+//! ptrace, the tracer side. Stops and steps happen in task/ptrace.rs.
+
+use super::errno::{EFAULT, EIO, EPERM, ESRCH};
+use crate::task::{
+    copy_to_user, current_task, current_user_token, pid2task, read_user, write_user, Resume,
+    SignalFlags, Tracee, MAX_SIG,
+};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::mem::size_of;
+
+const PTRACE_TRACEME: usize = 0;
+const PTRACE_PEEKDATA: usize = 2;
+const PTRACE_POKEDATA: usize = 5;
+const PTRACE_CONT: usize = 7;
+const PTRACE_KILL: usize = 8;
+const PTRACE_SINGLESTEP: usize = 9;
+const PTRACE_GETREGS: usize = 12;
+const PTRACE_SETREGS: usize = 13;
+const PTRACE_ATTACH: usize = 16;
+const PTRACE_DETACH: usize = 17;
+const PTRACE_SYSCALL: usize = 24;
+
+/// Registers as the tracer sees them, pc in place of x0.
+type UserRegs = [usize; 32];
+
+/// The parent becomes the tracer.
+fn traceme() -> Result<isize, isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.ptrace.is_some() {
+        return Err(EPERM);
+    }
+    let parent = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .ok_or(EPERM)?;
+    inner.ptrace = Some(Tracee::new(&parent));
+    Ok(0)
+}
+
+/// The tracee stops with SIGSTOP at its next trap.
+fn attach(pid: usize) -> Result<isize, isize> {
+    let tracer = current_task().unwrap();
+    let tracee = pid2task(pid).ok_or(ESRCH)?;
+    if tracee.tgid == tracer.tgid {
+        return Err(EPERM);
+    }
+    let mut inner = tracee.inner_exclusive_access();
+    if inner.ptrace.is_some() || inner.is_zombie() {
+        return Err(EPERM);
+    }
+    inner.ptrace = Some(Tracee::new(&tracer));
+    inner.signals.insert(SignalFlags::SIGSTOP);
+    Ok(0)
+}
+
+/// Err holds the errno. PEEKDATA and GETREGS store their result at `data`,
+/// -EFAULT if the caller cannot write there,
+/// the resuming requests take the signal to deliver from it. All requests
+/// but TRACEME and ATTACH need a tracee of the caller, stopped unless the
+/// request is KILL.
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> Result<isize, isize> {
+    match request {
+        PTRACE_TRACEME => return traceme(),
+        PTRACE_ATTACH => return attach(pid),
+        _ => {}
+    }
+    let tracer = current_task().unwrap();
+    let token = current_user_token();
+    let tracee = pid2task(pid).ok_or(ESRCH)?;
+    let mut inner = tracee.inner_exclusive_access();
+    let stopped = match inner.ptrace.as_ref() {
+        Some(trace) if trace.is_traced_by(&tracer) => trace.stop.is_some(),
+        _ => return Err(ESRCH),
+    };
+    if request == PTRACE_KILL {
+        inner.signals.insert(SignalFlags::SIGKILL);
+        return Ok(0);
+    }
+    if !stopped {
+        return Err(ESRCH);
+    }
+    let tracee_token = inner.get_user_token();
+    let resume = match request {
+        PTRACE_PEEKDATA => {
+            let mut word = [0u8; 8];
+            if !read_user(tracee_token, addr, &mut word) {
+                return Err(EIO);
+            }
+            drop(inner);
+            if !copy_to_user(token, data, &word) {
+                return Err(EFAULT);
+            }
+            return Ok(0);
+        }
+        PTRACE_POKEDATA => {
+            if !write_user(tracee_token, addr, &data.to_le_bytes()) {
+                return Err(EIO);
+            }
+            return Ok(0);
+        }
+        PTRACE_GETREGS => {
+            let cx = inner.get_trap_cx();
+            let mut regs: UserRegs = cx.x;
+            regs[0] = cx.sepc;
+            drop(inner);
+            let bytes: Vec<u8> = regs.iter().flat_map(|reg| reg.to_le_bytes()).collect();
+            if !copy_to_user(token, data, &bytes) {
+                return Err(EFAULT);
+            }
+            return Ok(0);
+        }
+        PTRACE_SETREGS => {
+            let mut bytes = [0u8; size_of::<UserRegs>()];
+            if !read_user(token, data, &mut bytes) {
+                return Err(EFAULT);
+            }
+            let cx = inner.get_trap_cx();
+            cx.sepc = usize::from_le_bytes(bytes[..8].try_into().unwrap());
+            for (reg, chunk) in cx.x[1..].iter_mut().zip(bytes[8..].chunks_exact(8)) {
+                *reg = usize::from_le_bytes(chunk.try_into().unwrap());
+            }
+            return Ok(0);
+        }
+        PTRACE_CONT => Resume::Continue,
+        PTRACE_SYSCALL => Resume::Syscall,
+        PTRACE_SINGLESTEP => Resume::Step,
+        PTRACE_DETACH => Resume::Detach,
+        _ => return Err(EIO),
+    };
+    if data > MAX_SIG {
+        return Err(EIO);
+    }
+    inner.ptrace.as_mut().unwrap().resume(resume, data as u32);
+    Ok(0)
+}
+
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    ptrace(request, pid, addr, data).unwrap_or_else(|errno| -errno)
+}
@@ -1,5 +1,13 @@
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
-use crate::task::{block_current_and_run_next, current_task};
+use super::errno::{
+    EAGAIN, EBADF, EBUSY, EDEADLK, EEXIST, EFAULT, EINVAL, ENOENT, EPERM, ETIMEDOUT,
+};
+use crate::mm::translated_str;
+use crate::sync::{
+    futex_wait, futex_wake, open_named_mutex, open_named_semaphore, unlink_named_mutex,
+    unlink_named_semaphore, Barrier, Condvar, FutexError, Mutex, MutexBlocking, MutexSpin,
+    NamedError, NamedFlags, Resource, RwLock, RwLockError, Semaphore, SyncHandle, SyncObject,
+};
+use crate::task::{block_current_and_run_next, current_task, current_user_token};
 use crate::timer::{add_timer, get_time_ms};
 use alloc::sync::Arc;
 
@@ -11,6 +19,12 @@ pub fn sys_sleep(ms: usize) -> isize {
     0
 }
 
+/// The object in slot `id` of one of the per-process lists. Calls naming
+/// a slot never handed out or whose object was destroyed fail with -EINVAL.
+fn lookup<T: ?Sized>(list: &[Option<Arc<T>>], id: usize) -> Option<Arc<T>> {
+    list.get(id).and_then(|slot| slot.clone())
+}
+
 pub fn sys_mutex_create(blocking: bool) -> isize {
     let process = current_task().unwrap();
     let mutex: Option<Arc<dyn Mutex>> = if !blocking {
@@ -18,98 +32,224 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
     } else {
         Some(Arc::new(MutexBlocking::new()))
     };
-    let mut process_inner = process.inner_exclusive_access();
-    if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let resource = Resource::mutex(mutex.as_ref().unwrap());
+    let id = if let Some(id) = objects
         .mutex_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.mutex_list[id] = mutex;
-        id as isize
+        objects.mutex_list[id] = mutex;
+        id
     } else {
-        process_inner.mutex_list.push(mutex);
-        process_inner.mutex_list.len() as isize - 1
-    }
+        objects.mutex_list.push(mutex);
+        objects.mutex_list.len() - 1
+    };
+    process_inner.deadlock_detector.lock().add(resource, 1);
+    id as isize
 }
 
+/// Fails with -EDEADLK, not -1, when deadlock detection is on and waiting
+/// would never end.
 pub fn sys_mutex_lock(mutex_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
     drop(process_inner);
+    let thread = process.pid.0;
     drop(process);
+    let resource = Resource::mutex(&mutex);
+    if !detector.lock().request(thread, resource) {
+        return -EDEADLK;
+    }
     mutex.lock();
+    detector.lock().acquired(thread, resource);
     0
 }
 
+/// -EPERM unless the calling thread holds the mutex.
 pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    if !mutex.is_owner(&process) {
+        return -EPERM;
+    }
+    let detector = Arc::clone(&process_inner.deadlock_detector);
     drop(process_inner);
+    let thread = process.pid.0;
     drop(process);
+    detector.lock().released(thread, Resource::mutex(&mutex));
     mutex.unlock();
     0
 }
 
+/// -EBUSY if another thread holds the mutex.
+pub fn sys_mutex_trylock(mutex_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
+    drop(process_inner);
+    let thread = process.pid.0;
+    drop(process);
+    if !mutex.try_lock() {
+        return -EBUSY;
+    }
+    detector.lock().acquired(thread, Resource::mutex(&mutex));
+    0
+}
+
+/// -ETIMEDOUT if the mutex is still held after `ms` milliseconds. A wait
+/// that ends by itself cannot deadlock, so it is not checked.
+pub fn sys_mutex_lock_timeout(mutex_id: usize, ms: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
+    drop(process_inner);
+    let thread = process.pid.0;
+    drop(process);
+    if !mutex.lock_timeout(ms) {
+        return -ETIMEDOUT;
+    }
+    detector.lock().acquired(thread, Resource::mutex(&mutex));
+    0
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     let process = current_task().unwrap();
-    let mut process_inner = process.inner_exclusive_access();
-    let id = if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let sem = Arc::new(Semaphore::new(res_count));
+    let resource = Resource::semaphore(&sem);
+    let id = if let Some(id) = objects
         .semaphore_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.semaphore_list[id] = Some(Arc::new(Semaphore::new(res_count)));
+        objects.semaphore_list[id] = Some(sem);
         id
     } else {
-        process_inner
-            .semaphore_list
-            .push(Some(Arc::new(Semaphore::new(res_count))));
-        process_inner.semaphore_list.len() - 1
+        objects.semaphore_list.push(Some(sem));
+        objects.semaphore_list.len() - 1
     };
+    process_inner
+        .deadlock_detector
+        .lock()
+        .add(resource, res_count);
     id as isize
 }
 
 pub fn sys_semaphore_up(sem_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = match lookup(&process_inner.sync_objects.lock().semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
     drop(process_inner);
+    detector
+        .lock()
+        .released(process.pid.0, Resource::semaphore(&sem));
     sem.up();
     0
 }
 
+/// Fails with -EDEADLK like `sys_mutex_lock`.
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = match lookup(&process_inner.sync_objects.lock().semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
     drop(process_inner);
+    let thread = process.pid.0;
+    drop(process);
+    let resource = Resource::semaphore(&sem);
+    if !detector.lock().request(thread, resource) {
+        return -EDEADLK;
+    }
     sem.down();
+    detector.lock().acquired(thread, resource);
+    0
+}
+
+/// -EAGAIN if no unit is available.
+pub fn sys_semaphore_trydown(sem_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let sem = match lookup(&process_inner.sync_objects.lock().semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
+    drop(process_inner);
+    let thread = process.pid.0;
+    drop(process);
+    if !sem.try_down() {
+        return -EAGAIN;
+    }
+    detector.lock().acquired(thread, Resource::semaphore(&sem));
+    0
+}
+
+/// -ETIMEDOUT if no unit became available within `ms` milliseconds.
+pub fn sys_semaphore_down_timeout(sem_id: usize, ms: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let sem = match lookup(&process_inner.sync_objects.lock().semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
+    drop(process_inner);
+    let thread = process.pid.0;
+    drop(process);
+    if !sem.down_timeout(ms) {
+        return -ETIMEDOUT;
+    }
+    detector.lock().acquired(thread, Resource::semaphore(&sem));
     0
 }
 
 pub fn sys_condvar_create(_arg: usize) -> isize {
     let process = current_task().unwrap();
-    let mut process_inner = process.inner_exclusive_access();
-    let id = if let Some(id) = process_inner
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
         .condvar_list
         .iter()
         .enumerate()
         .find(|(_, item)| item.is_none())
         .map(|(id, _)| id)
     {
-        process_inner.condvar_list[id] = Some(Arc::new(Condvar::new()));
+        objects.condvar_list[id] = Some(Arc::new(Condvar::new()));
         id
     } else {
-        process_inner
-            .condvar_list
-            .push(Some(Arc::new(Condvar::new())));
-        process_inner.condvar_list.len() - 1
+        objects.condvar_list.push(Some(Arc::new(Condvar::new())));
+        objects.condvar_list.len() - 1
     };
     id as isize
 }
@@ -117,18 +257,510 @@ pub fn sys_condvar_create(_arg: usize) -> isize {
 pub fn sys_condvar_signal(condvar_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
+    let condvar = match lookup(&process_inner.sync_objects.lock().condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -EINVAL,
+    };
     drop(process_inner);
     condvar.signal();
     0
 }
 
+/// Wake every thread waiting on the condvar. They get the mutex they wait
+/// with one after another.
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match lookup(&process_inner.sync_objects.lock().condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    condvar.broadcast();
+    0
+}
+
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     let process = current_task().unwrap();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let condvar = match lookup(&process_inner.sync_objects.lock().condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -EINVAL,
+    };
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
     drop(process_inner);
+    let thread = process.pid.0;
+    let resource = Resource::mutex(&mutex);
+    detector.lock().released(thread, resource);
     condvar.wait(mutex);
+    detector.lock().acquired(thread, resource);
+    0
+}
+
+/// -ETIMEDOUT if nobody signaled within `ms` milliseconds, the mutex is
+/// held again either way.
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, ms: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match lookup(&process_inner.sync_objects.lock().condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -EINVAL,
+    };
+    let mutex = match lookup(&process_inner.sync_objects.lock().mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    let detector = Arc::clone(&process_inner.deadlock_detector);
+    drop(process_inner);
+    let thread = process.pid.0;
+    let resource = Resource::mutex(&mutex);
+    detector.lock().released(thread, resource);
+    let signaled = condvar.wait_timeout(mutex, ms);
+    detector.lock().acquired(thread, resource);
+    if signaled {
+        0
+    } else {
+        -ETIMEDOUT
+    }
+}
+
+pub fn sys_rwlock_create() -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        objects.rwlock_list[id] = Some(Arc::new(RwLock::new()));
+        id
+    } else {
+        objects.rwlock_list.push(Some(Arc::new(RwLock::new())));
+        objects.rwlock_list.len() - 1
+    };
+    id as isize
+}
+
+fn rwlock_errno(err: RwLockError) -> isize {
+    match err {
+        RwLockError::NotHeld => EPERM,
+        RwLockError::Deadlock => EDEADLK,
+    }
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match lookup(&process_inner.sync_objects.lock().rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    rwlock.read();
+    0
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match lookup(&process_inner.sync_objects.lock().rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    rwlock.write();
     0
 }
+
+/// -EPERM if the lock is held neither for reading nor for writing.
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match lookup(&process_inner.sync_objects.lock().rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    match rwlock.unlock() {
+        Ok(()) => 0,
+        Err(err) => -rwlock_errno(err),
+    }
+}
+
+/// -EPERM if the lock is not held for reading, -EDEADLK if another reader
+/// is already upgrading.
+pub fn sys_rwlock_upgrade(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match lookup(&process_inner.sync_objects.lock().rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    match rwlock.upgrade() {
+        Ok(()) => 0,
+        Err(err) => -rwlock_errno(err),
+    }
+}
+
+/// -EPERM if the lock is not held for writing.
+pub fn sys_rwlock_downgrade(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match lookup(&process_inner.sync_objects.lock().rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    match rwlock.downgrade() {
+        Ok(()) => 0,
+        Err(err) => -rwlock_errno(err),
+    }
+}
+
+/// -EINVAL for a barrier nobody could pass.
+pub fn sys_barrier_create(count: usize) -> isize {
+    if count == 0 {
+        return -EINVAL;
+    }
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let id = if let Some(id) = objects
+        .barrier_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        objects.barrier_list[id] = Some(Arc::new(Barrier::new(count)));
+        id
+    } else {
+        objects
+            .barrier_list
+            .push(Some(Arc::new(Barrier::new(count))));
+        objects.barrier_list.len() - 1
+    };
+    id as isize
+}
+
+/// Returns 1 in the last thread to arrive, 0 in the others.
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let barrier = match lookup(&process_inner.sync_objects.lock().barrier_list, barrier_id) {
+        Some(barrier) => barrier,
+        None => return -EINVAL,
+    };
+    drop(process_inner);
+    drop(process);
+    barrier.wait() as isize
+}
+
+/// -EBUSY while the mutex is held or waited for.
+pub fn sys_mutex_destroy(mutex_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let mutex = match lookup(&objects.mutex_list, mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    if mutex.is_busy() {
+        return -EBUSY;
+    }
+    objects.mutex_list[mutex_id] = None;
+    process_inner
+        .deadlock_detector
+        .lock()
+        .remove(Resource::mutex(&mutex));
+    0
+}
+
+/// -EBUSY while a thread waits in `down`.
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let sem = match lookup(&objects.semaphore_list, sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    if sem.has_waiters() {
+        return -EBUSY;
+    }
+    objects.semaphore_list[sem_id] = None;
+    process_inner
+        .deadlock_detector
+        .lock()
+        .remove(Resource::semaphore(&sem));
+    0
+}
+
+/// -EBUSY while a thread waits on the condvar.
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let condvar = match lookup(&objects.condvar_list, condvar_id) {
+        Some(condvar) => condvar,
+        None => return -EINVAL,
+    };
+    if condvar.has_waiters() {
+        return -EBUSY;
+    }
+    objects.condvar_list[condvar_id] = None;
+    0
+}
+
+/// -EBUSY while the lock is held or waited for.
+pub fn sys_rwlock_destroy(rwlock_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let rwlock = match lookup(&objects.rwlock_list, rwlock_id) {
+        Some(rwlock) => rwlock,
+        None => return -EINVAL,
+    };
+    if rwlock.is_busy() {
+        return -EBUSY;
+    }
+    objects.rwlock_list[rwlock_id] = None;
+    0
+}
+
+/// -EBUSY while threads wait at the barrier.
+pub fn sys_barrier_destroy(barrier_id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let barrier = match lookup(&objects.barrier_list, barrier_id) {
+        Some(barrier) => barrier,
+        None => return -EINVAL,
+    };
+    if barrier.has_waiters() {
+        return -EBUSY;
+    }
+    objects.barrier_list[barrier_id] = None;
+    0
+}
+
+pub const SYNC_MUTEX: usize = 0;
+pub const SYNC_SEMAPHORE: usize = 1;
+pub const SYNC_CONDVAR: usize = 2;
+pub const SYNC_RWLOCK: usize = 3;
+pub const SYNC_BARRIER: usize = 4;
+
+/// Open an fd referring to object `id` of `kind`, one of the SYNC_*
+/// constants. Children inherit it and `sys_sync_import` it.
+pub fn sys_sync_export(kind: usize, id: usize) -> isize {
+    let process = current_task().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let objects = process_inner.sync_objects.lock();
+    let object = match kind {
+        SYNC_MUTEX => lookup(&objects.mutex_list, id).map(SyncObject::Mutex),
+        SYNC_SEMAPHORE => lookup(&objects.semaphore_list, id).map(SyncObject::Semaphore),
+        SYNC_CONDVAR => lookup(&objects.condvar_list, id).map(SyncObject::Condvar),
+        SYNC_RWLOCK => lookup(&objects.rwlock_list, id).map(SyncObject::RwLock),
+        SYNC_BARRIER => lookup(&objects.barrier_list, id).map(SyncObject::Barrier),
+        _ => None,
+    };
+    drop(objects);
+    let object = match object {
+        Some(object) => object,
+        None => return -EINVAL,
+    };
+    let fd = process_inner.alloc_fd();
+    process_inner.fd_table[fd] = Some(Arc::new(SyncHandle::new(object)));
+    fd as isize
+}
+
+fn insert<T: ?Sized>(list: &mut Vec<Option<Arc<T>>>, object: Arc<T>) -> usize {
+    if let Some(id) = list.iter().position(|slot| slot.is_none()) {
+        list[id] = Some(object);
+        id
+    } else {
+        list.push(Some(object));
+        list.len() - 1
+    }
+}
+
+/// Put the object `fd` refers to into the list of its kind and return its
+/// id there. -EBADF if `fd` is not open, -EINVAL if it is no sync handle.
+/// The deadlock detector does not track imported objects.
+pub fn sys_sync_import(fd: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let object = match process_inner.fd_table.get(fd) {
+        Some(Some(file)) => file.sync_object(),
+        _ => return -EBADF,
+    };
+    let id = match object {
+        Some(SyncObject::Mutex(mutex)) => insert(&mut objects.mutex_list, mutex),
+        Some(SyncObject::Semaphore(sem)) => insert(&mut objects.semaphore_list, sem),
+        Some(SyncObject::Condvar(condvar)) => insert(&mut objects.condvar_list, condvar),
+        Some(SyncObject::RwLock(rwlock)) => insert(&mut objects.rwlock_list, rwlock),
+        Some(SyncObject::Barrier(barrier)) => insert(&mut objects.barrier_list, barrier),
+        None => return -EINVAL,
+    };
+    id as isize
+}
+
+/// Drop the caller's reference to object `id` of `kind`. Unlike destroy
+/// it does not care whether other processes use the object, it only fails
+/// with -EBUSY for a mutex the calling thread holds.
+pub fn sys_sync_close(kind: usize, id: usize) -> isize {
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    let resource = match kind {
+        SYNC_MUTEX => match lookup(&objects.mutex_list, id) {
+            Some(mutex) if mutex.is_owner(&process) => return -EBUSY,
+            Some(mutex) => {
+                objects.mutex_list[id] = None;
+                Some(Resource::mutex(&mutex))
+            }
+            None => return -EINVAL,
+        },
+        SYNC_SEMAPHORE => match objects.semaphore_list.get_mut(id).and_then(Option::take) {
+            Some(sem) => Some(Resource::semaphore(&sem)),
+            None => return -EINVAL,
+        },
+        SYNC_CONDVAR => match objects.condvar_list.get_mut(id) {
+            Some(slot) if slot.take().is_some() => None,
+            _ => return -EINVAL,
+        },
+        SYNC_RWLOCK => match objects.rwlock_list.get_mut(id) {
+            Some(slot) if slot.take().is_some() => None,
+            _ => return -EINVAL,
+        },
+        SYNC_BARRIER => match objects.barrier_list.get_mut(id) {
+            Some(slot) if slot.take().is_some() => None,
+            _ => return -EINVAL,
+        },
+        _ => return -EINVAL,
+    };
+    if let Some(resource) = resource {
+        process_inner.deadlock_detector.lock().remove(resource);
+    }
+    0
+}
+
+fn named_errno(err: NamedError) -> isize {
+    match err {
+        NamedError::Invalid => EINVAL,
+        NamedError::NotFound => ENOENT,
+        NamedError::Exists => EEXIST,
+    }
+}
+
+/// Open the semaphore called by the string at `name`, created with
+/// `res_count` units if `flags` allow, and return its id. -ENOENT if there
+/// is none and -EEXIST if one exists already but CREATE | EXCL was asked.
+/// Like imported ones, named semaphores are not deadlock checked.
+pub fn sys_semaphore_open(name: usize, flags: u32, res_count: usize) -> isize {
+    let flags = match NamedFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let name = translated_str(current_user_token(), name as *const u8);
+    let sem = match open_named_semaphore(&name, flags, res_count) {
+        Ok(sem) => sem,
+        Err(err) => return -named_errno(err),
+    };
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    insert(&mut objects.semaphore_list, sem) as isize
+}
+
+/// -ENOENT if no semaphore has the name.
+pub fn sys_semaphore_unlink(name: usize) -> isize {
+    let name = translated_str(current_user_token(), name as *const u8);
+    match unlink_named_semaphore(&name) {
+        Ok(()) => 0,
+        Err(err) => -named_errno(err),
+    }
+}
+
+/// Like `sys_semaphore_open`, for a blocking mutex.
+pub fn sys_mutex_open(name: usize, flags: u32) -> isize {
+    let flags = match NamedFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -EINVAL,
+    };
+    let name = translated_str(current_user_token(), name as *const u8);
+    let mutex = match open_named_mutex(&name, flags) {
+        Ok(mutex) => mutex,
+        Err(err) => return -named_errno(err),
+    };
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let mut objects = process_inner.sync_objects.lock();
+    insert(&mut objects.mutex_list, mutex) as isize
+}
+
+/// -ENOENT if no mutex has the name.
+pub fn sys_mutex_unlink(name: usize) -> isize {
+    let name = translated_str(current_user_token(), name as *const u8);
+    match unlink_named_mutex(&name) {
+        Ok(()) => 0,
+        Err(err) => -named_errno(err),
+    }
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let enabled = match enabled {
+        0 => false,
+        1 => true,
+        _ => return -1,
+    };
+    let process = current_task().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.deadlock_detector.lock().enabled = enabled;
+    0
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+pub fn futex_errno(err: FutexError) -> isize {
+    match err {
+        FutexError::Fault => EFAULT,
+        FutexError::Again => EAGAIN,
+        FutexError::TimedOut => ETIMEDOUT,
+    }
+}
+
+/// FUTEX_WAIT sleeps while the u32 at `uaddr` holds `val`, for at most
+/// `timeout_ms` milliseconds unless that is 0. FUTEX_WAKE wakes up to `val`
+/// waiters and returns how many it woke.
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, timeout_ms: usize) -> isize {
+    let token = current_user_token();
+    let result = match op {
+        FUTEX_WAIT => {
+            let timeout_ms = if timeout_ms == 0 {
+                None
+            } else {
+                Some(timeout_ms)
+            };
+            futex_wait(token, uaddr, val as u32, timeout_ms).map(|_| 0)
+        }
+        FUTEX_WAKE => futex_wake(token, uaddr, val).map(|woken| woken as isize),
+        _ => return -EINVAL,
+    };
+    result.unwrap_or_else(|err| -futex_errno(err))
+}
@@ -1,26 +1,32 @@
 use crate::{
-    mm::kernel_token,
-    task::{add_task, current_task, TaskControlBlock},
+    mm::{kernel_token, MapError},
+    task::{add_task, current_task, out_of_memory, TaskControlBlock, THREAD_PAGES},
     trap::{trap_handler, TrapContext},
 };
 use crate::task::{
     add_task_first_time,
     pid2task,
-    trap_cx_bottom_from_pid,
-    remove_from_pid2task
+    remove_from_pid2task,
+    PID2TCB
 };
+use alloc::sync::Arc;
+use core::arch::asm;
 
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     let current_task = current_task().unwrap();
     let current_pid = current_task.pid.0;
 
-    let new_task = current_task.new_user_thread(entry, arg, current_pid);
+    let new_task = loop {
+        match current_task.new_user_thread(entry, arg, current_pid) {
+            Ok(new_task) => break new_task,
+            Err(MapError::OutOfMemory) if out_of_memory(THREAD_PAGES) => {}
+            Err(_) => return -1,
+        }
+    };
 
     let new_pid = new_task.pid.0;
 
-    let cx = trap_cx_bottom_from_pid(new_pid);
-    // println!("cx: {:#x}", cx);
     // add new task to scheduler
     add_task_first_time(new_task.clone());
     drop(current_task);
@@ -53,7 +59,11 @@ pub fn sys_waittid(pid: usize) -> i32 {
 
         if let Some(waited_exit_code)  = task.inner_exclusive_access().exit_code {
 
-            // thread has exited
+            // thread has exited, only one of concurrent joiners gets it
+            if PID2TCB.update(|map| map.remove(&pid)).is_none() {
+                return -1;
+            }
+            release_thread(&task);
             return waited_exit_code;
 
         }else {
@@ -67,3 +77,23 @@ pub fn sys_waittid(pid: usize) -> i32 {
     }
 
 }
+
+/// Give the tid of a joined thread back to its process, together with
+/// the stack and trap context laid out by it.
+fn release_thread(task: &Arc<TaskControlBlock>) {
+    let mut inner = task.inner_exclusive_access();
+    let tid = inner.tid;
+    inner.memory_set.lock().remove_thread_areas(tid);
+    inner.tid_allocator.lock().dealloc(tid);
+    let parent = inner.parent.take();
+    drop(inner);
+    unsafe {
+        asm!("sfence.vma");
+    }
+    if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+        parent
+            .inner_exclusive_access()
+            .children
+            .retain(|child| !Arc::ptr_eq(child, task));
+    }
+}
@@ -0,0 +1,294 @@
+//! Kernel side syscall tracing.
+//!
+//! A process with `syscall_trace` set logs each of its syscalls, with the
+//! decoded arguments, the return value and how long the call took, to a
+//! ring buffer shared by all traced processes. `sys_strace_read` streams
+//! the buffer to user space. Arguments are decoded before the call runs,
+//! exec and exit take away the memory they point to.
+
+use super::*;
+use crate::config::CLOCK_FREQ;
+use crate::mm::{translated_byte_buffer, translated_refmut};
+use crate::task::{current_task, current_user_token, read_user, Personality, PID2TCB};
+use crate::timer::get_time;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+use lock::Mutex;
+
+/// Lines kept before the oldest ones are dropped.
+const LOG_LINES: usize = 512;
+/// Longest string or buffer shown.
+const MAX_SHOWN: usize = 32;
+
+/// How an argument is shown.
+#[derive(Clone, Copy)]
+pub enum Arg {
+    Int,
+    Hex,
+    /// a NUL terminated string
+    Str,
+    /// a buffer whose length is the argument with this index
+    Buf(usize),
+}
+
+pub type SyscallInfo = (&'static str, &'static [Arg]);
+
+use Arg::*;
+
+const NATIVE_SYSCALLS: &[(usize, &str, &[Arg])] = &[
+    (SYSCALL_DUP, "dup", &[Int]),
+    (SYSCALL_OPEN, "open", &[Str, Hex]),
+    (SYSCALL_CLOSE, "close", &[Int]),
+    (SYSCALL_PIPE, "pipe", &[Hex]),
+    (SYSCALL_READ, "read", &[Int, Hex, Int]),
+    (SYSCALL_WRITE, "write", &[Int, Buf(2), Int]),
+    (SYSCALL_EXIT, "exit", &[Int]),
+    (SYSCALL_FUTEX, "futex", &[Hex, Int, Int, Int]),
+    (SYSCALL_SLEEP, "sleep", &[Int]),
+    (SYSCALL_PTRACE, "ptrace", &[Int, Int, Hex, Hex]),
+    (SYSCALL_YIELD, "yield", &[]),
+    (SYSCALL_KILL, "kill", &[Int, Int]),
+    (SYSCALL_SET_PRIORITY, "set_priority", &[Int]),
+    (SYSCALL_GET_PRIORITY, "get_priority", &[]),
+    (SYSCALL_GETRLIMIT, "getrlimit", &[Int, Hex]),
+    (SYSCALL_SETRLIMIT, "setrlimit", &[Int, Hex]),
+    (SYSCALL_GET_TIME, "get_time", &[]),
+    (SYSCALL_GETPID, "getpid", &[]),
+    (SYSCALL_FORK, "fork", &[]),
+    (SYSCALL_EXEC, "exec", &[Str, Hex, Hex]),
+    (SYSCALL_MPROTECT, "mprotect", &[Hex, Hex, Hex]),
+    (SYSCALL_WAITPID, "waitpid", &[Int, Hex, Hex]),
+    (
+        SYSCALL_ENABLE_DEADLOCK_DETECT,
+        "enable_deadlock_detect",
+        &[Int],
+    ),
+    (SYSCALL_THREAD_CREATE, "thread_create", &[Hex, Hex]),
+    (SYSCALL_GETTID, "gettid", &[]),
+    (SYSCALL_WAITTID, "waittid", &[Int]),
+    (SYSCALL_MUTEX_CREATE, "mutex_create", &[Int]),
+    (SYSCALL_MUTEX_LOCK, "mutex_lock", &[Int]),
+    (SYSCALL_MUTEX_UNLOCK, "mutex_unlock", &[Int]),
+    (SYSCALL_MUTEX_TRYLOCK, "mutex_trylock", &[Int]),
+    (
+        SYSCALL_MUTEX_LOCK_TIMEOUT,
+        "mutex_lock_timeout",
+        &[Int, Int],
+    ),
+    (SYSCALL_MUTEX_DESTROY, "mutex_destroy", &[Int]),
+    (SYSCALL_MUTEX_OPEN, "mutex_open", &[Str, Hex]),
+    (SYSCALL_MUTEX_UNLINK, "mutex_unlink", &[Str]),
+    (SYSCALL_SEMAPHORE_CREATE, "semaphore_create", &[Int]),
+    (SYSCALL_SEMAPHORE_UP, "semaphore_up", &[Int]),
+    (SYSCALL_SEMAPHORE_DOWN, "semaphore_down", &[Int]),
+    (SYSCALL_SEMAPHORE_TRYDOWN, "semaphore_trydown", &[Int]),
+    (
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT,
+        "semaphore_down_timeout",
+        &[Int, Int],
+    ),
+    (SYSCALL_SEMAPHORE_DESTROY, "semaphore_destroy", &[Int]),
+    (SYSCALL_SEMAPHORE_OPEN, "semaphore_open", &[Str, Hex, Int]),
+    (SYSCALL_SEMAPHORE_UNLINK, "semaphore_unlink", &[Str]),
+    (SYSCALL_CONDVAR_CREATE, "condvar_create", &[Int]),
+    (SYSCALL_CONDVAR_SIGNAL, "condvar_signal", &[Int]),
+    (SYSCALL_CONDVAR_WAIT, "condvar_wait", &[Int, Int]),
+    (
+        SYSCALL_CONDVAR_WAIT_TIMEOUT,
+        "condvar_wait_timeout",
+        &[Int, Int, Int],
+    ),
+    (SYSCALL_CONDVAR_DESTROY, "condvar_destroy", &[Int]),
+    (SYSCALL_CONDVAR_BROADCAST, "condvar_broadcast", &[Int]),
+    (SYSCALL_RWLOCK_CREATE, "rwlock_create", &[]),
+    (SYSCALL_RWLOCK_READ, "rwlock_read", &[Int]),
+    (SYSCALL_RWLOCK_WRITE, "rwlock_write", &[Int]),
+    (SYSCALL_RWLOCK_UNLOCK, "rwlock_unlock", &[Int]),
+    (SYSCALL_RWLOCK_UPGRADE, "rwlock_upgrade", &[Int]),
+    (SYSCALL_RWLOCK_DOWNGRADE, "rwlock_downgrade", &[Int]),
+    (SYSCALL_RWLOCK_DESTROY, "rwlock_destroy", &[Int]),
+    (SYSCALL_BARRIER_CREATE, "barrier_create", &[Int]),
+    (SYSCALL_BARRIER_WAIT, "barrier_wait", &[Int]),
+    (SYSCALL_BARRIER_DESTROY, "barrier_destroy", &[Int]),
+    (SYSCALL_SYNC_EXPORT, "sync_export", &[Int, Int]),
+    (SYSCALL_SYNC_IMPORT, "sync_import", &[Int]),
+    (SYSCALL_SYNC_CLOSE, "sync_close", &[Int, Int]),
+    (SYSCALL_SHM_OPEN, "shm_open", &[Int, Hex, Hex]),
+    (SYSCALL_SHM_MAP, "shm_map", &[Int]),
+    (SYSCALL_SHM_UNMAP, "shm_unmap", &[Int]),
+    (SYSCALL_SHM_CLOSE, "shm_close", &[Int]),
+    (SYSCALL_STRACE, "strace", &[Int, Int]),
+    (SYSCALL_STRACE_READ, "strace_read", &[Hex, Hex, Int]),
+    (SYSCALL_MQ_OPEN, "mq_open", &[Str, Hex, Int, Int]),
+    (SYSCALL_MQ_SEND, "mq_send", &[Int, Buf(2), Int, Int, Int]),
+    (SYSCALL_MQ_RECEIVE, "mq_receive", &[Int, Hex, Int, Hex, Int]),
+    (SYSCALL_MQ_UNLINK, "mq_unlink", &[Str]),
+];
+
+struct TraceLog {
+    lines: VecDeque<String>,
+    /// sequence number of the next line pushed
+    next: usize,
+}
+
+impl TraceLog {
+    /// Sequence number of the oldest line kept.
+    fn first(&self) -> usize {
+        self.next - self.lines.len()
+    }
+
+    fn push(&mut self, line: String) {
+        if self.lines.len() == LOG_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        self.next += 1;
+    }
+}
+
+lazy_static! {
+    static ref TRACE_LOG: Mutex<TraceLog> = Mutex::new(TraceLog {
+        lines: VecDeque::new(),
+        next: 0,
+    });
+}
+
+/// A traced call on its way through the kernel.
+pub struct TracedCall {
+    line: String,
+    start: usize,
+}
+
+fn shown_bytes(token: usize, va: usize, len: usize, nul_terminated: bool) -> String {
+    let mut bytes = Vec::new();
+    let mut byte = [0u8];
+    while bytes.len() < len.min(MAX_SHOWN)
+        && read_user(token, va.wrapping_add(bytes.len()), &mut byte)
+    {
+        if nul_terminated && byte[0] == 0 {
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    let more = if bytes.len() < len && bytes.len() == MAX_SHOWN {
+        "..."
+    } else {
+        ""
+    };
+    format!("{:?}{}", String::from_utf8_lossy(&bytes), more)
+}
+
+fn describe(info: Option<SyscallInfo>, id: usize, args: &[usize; 6]) -> String {
+    let (name, kinds) = match info {
+        Some(info) => (String::from(info.0), info.1),
+        None => (format!("syscall_{}", id), &[Hex; 6][..]),
+    };
+    let token = current_user_token();
+    let shown: Vec<String> = kinds
+        .iter()
+        .zip(args.iter())
+        .map(|(kind, &arg)| match *kind {
+            Int => format!("{}", arg as isize),
+            Hex => format!("{:#x}", arg),
+            Str => shown_bytes(token, arg, usize::MAX, true),
+            Buf(len) => shown_bytes(token, arg, args[len], false),
+        })
+        .collect();
+    format!(
+        "[{}] {}({})",
+        current_task().unwrap().pid.0,
+        name,
+        shown.join(", ")
+    )
+}
+
+/// Describe the call about to be made. Calls that do not return are
+/// logged right away.
+pub fn trace_enter(personality: Personality, id: usize, args: &[usize; 6]) -> TracedCall {
+    let info = match personality {
+        Personality::Native => NATIVE_SYSCALLS
+            .iter()
+            .find(|call| call.0 == id)
+            .map(|&(_, name, kinds)| (name, kinds)),
+        Personality::Linux => linux::syscall_info(id),
+    };
+    let line = describe(info, id, args);
+    if let Some(("exit" | "exit_group", _)) = info {
+        TRACE_LOG.lock().push(format!("{} = ?\n", line));
+    }
+    TracedCall {
+        line,
+        start: get_time(),
+    }
+}
+
+pub fn trace_leave(call: TracedCall, ret: isize) {
+    let us = (get_time() - call.start) * 1_000_000 / CLOCK_FREQ;
+    TRACE_LOG
+        .lock()
+        .push(format!("{} = {} <{}us>\n", call.line, ret, us));
+}
+
+/// Turn tracing of every thread of process `pid` on or off, 0 is the
+/// calling process. Children forked later inherit the setting.
+pub fn sys_strace(pid: usize, enable: usize) -> isize {
+    let tgid = match pid {
+        0 => current_task().unwrap().tgid,
+        pid => pid,
+    };
+    let threads: Vec<Arc<_>> = PID2TCB.read(|map| {
+        map.values()
+            .filter(|task| task.tgid == tgid)
+            .cloned()
+            .collect()
+    });
+    if threads.is_empty() {
+        return -1;
+    }
+    for thread in threads {
+        thread.inner_exclusive_access().syscall_trace = enable != 0;
+    }
+    0
+}
+
+/// Copy whole log lines, starting with line `*seq`, into `buf` and
+/// advance `*seq` past them. Lines that were dropped before they were read
+/// are counted in a line of their own, a `*seq` past the end of the log
+/// moves to the end. Returns the number of bytes copied, 0 when there is
+/// nothing new.
+pub fn sys_strace_read(seq: *mut usize, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let seq = translated_refmut(token, seq);
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let log = TRACE_LOG.lock();
+        let first = log.first();
+        if *seq > log.next {
+            *seq = log.next;
+        }
+        if *seq < first {
+            out.extend_from_slice(format!("({} lines lost)\n", first - *seq).as_bytes());
+            *seq = first;
+        }
+        while *seq < log.next {
+            let line = log.lines[*seq - first].as_bytes();
+            if !out.is_empty() && out.len() + line.len() > len {
+                break;
+            }
+            out.extend_from_slice(line);
+            *seq += 1;
+        }
+    }
+    // a line longer than the whole buffer is cut
+    out.truncate(len);
+    let mut copied = 0;
+    for part in translated_byte_buffer(token, buf, out.len()) {
+        part.copy_from_slice(&out[copied..copied + part.len()]);
+        copied += part.len();
+    }
+    copied as isize
+}
@@ -0,0 +1,329 @@
+//! ELF core dumps.
+//!
+//! A task killed by SIGSEGV, SIGILL, SIGTRAP, SIGABRT or SIGFPE writes
+//! `core.<pid>` into the root directory if its RLIMIT_CORE allows, like
+//! Linux does with `ulimit -c`. The file follows the Linux RISC-V layout, so that
+//! `riscv64-unknown-elf-gdb <program> core.<pid>` can read it: one PT_LOAD
+//! per user area, and a PT_NOTE with the registers of every thread.
+//!
+//! The other threads of the process are not stopped for the dump. Their
+//! registers are the ones saved when they last entered the kernel, so only
+//! those of the faulting thread are sure to be accurate; a sibling that
+//! is running in user mode on another hart has moved on since.
+
+use super::{current_task, SignalFlags, TaskControlBlock, PID2TCB};
+use crate::config::PAGE_SIZE;
+use crate::fs::{open_file, OpenFlags};
+use crate::mm::{MapPermission, PageTable, VirtPageNum};
+use crate::trap::{FpContext, TrapContext};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+/// RVC and the double float ABI, like the programs we run.
+const EF_RISCV: u32 = 0x5;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_SIGINFO: u32 = 0x5349_4749;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+/// Sizes of the Linux riscv64 note structures.
+const PRSTATUS_SIZE: usize = 376;
+const PRPSINFO_SIZE: usize = 136;
+const SIGINFO_SIZE: usize = 128;
+const FPREGSET_SIZE: usize = 264;
+
+/// Written for pages that are not mapped, too big for a kernel stack.
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+/// What the killed thread was doing, for NT_SIGINFO.
+pub struct FaultInfo {
+    pub signum: u32,
+    /// the faulting address or instruction, None if the signal was sent
+    pub addr: Option<usize>,
+}
+
+/// Registers of one thread.
+struct ThreadState {
+    pid: usize,
+    trap_cx: TrapContext,
+    fp_cx: FpContext,
+}
+
+fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Append a note owned by "CORE".
+fn push_note(notes: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    notes.extend_from_slice(&(NAME.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&note_type.to_le_bytes());
+    notes.extend_from_slice(NAME);
+    notes.resize(align4(notes.len()), 0);
+    notes.extend_from_slice(desc);
+    notes.resize(align4(notes.len()), 0);
+}
+
+fn prstatus(thread: &ThreadState, signum: u32, ppid: usize, tgid: usize) -> Vec<u8> {
+    let mut desc = vec![0; PRSTATUS_SIZE];
+    // pr_info.si_signo and pr_cursig
+    put(&mut desc, 0, &signum.to_le_bytes());
+    put(&mut desc, 12, &(signum as u16).to_le_bytes());
+    // pr_pid, pr_ppid, pr_pgrp, pr_sid
+    put(&mut desc, 32, &(thread.pid as u32).to_le_bytes());
+    put(&mut desc, 36, &(ppid as u32).to_le_bytes());
+    put(&mut desc, 40, &(tgid as u32).to_le_bytes());
+    put(&mut desc, 44, &(tgid as u32).to_le_bytes());
+    // pr_reg is pc, then x1 to x31
+    put(&mut desc, 112, &thread.trap_cx.sepc.to_le_bytes());
+    for i in 1..32 {
+        put(&mut desc, 112 + i * 8, &thread.trap_cx.x[i].to_le_bytes());
+    }
+    // pr_fpvalid
+    put(&mut desc, 368, &1u32.to_le_bytes());
+    desc
+}
+
+fn fpregset(thread: &ThreadState) -> Vec<u8> {
+    let mut desc = vec![0; FPREGSET_SIZE];
+    for (i, f) in thread.fp_cx.f.iter().enumerate() {
+        put(&mut desc, i * 8, &f.to_le_bytes());
+    }
+    put(&mut desc, 256, &(thread.fp_cx.fcsr as u32).to_le_bytes());
+    desc
+}
+
+fn prpsinfo(pid: usize, ppid: usize) -> Vec<u8> {
+    let mut desc = vec![0; PRPSINFO_SIZE];
+    // pr_state and pr_sname: running
+    desc[1] = b'R';
+    put(&mut desc, 24, &(pid as u32).to_le_bytes());
+    put(&mut desc, 28, &(ppid as u32).to_le_bytes());
+    put(&mut desc, 32, &(pid as u32).to_le_bytes());
+    put(&mut desc, 36, &(pid as u32).to_le_bytes());
+    desc
+}
+
+fn siginfo(fault: &FaultInfo) -> Vec<u8> {
+    // SEGV_MAPERR, ILL_ILLOPC and friends are 1, SI_USER is 0
+    let si_code: u32 = if fault.addr.is_some() { 1 } else { 0 };
+    let mut desc = vec![0; SIGINFO_SIZE];
+    put(&mut desc, 0, &fault.signum.to_le_bytes());
+    put(&mut desc, 8, &si_code.to_le_bytes());
+    put(&mut desc, 16, &fault.addr.unwrap_or(0).to_le_bytes());
+    desc
+}
+
+fn program_header(
+    p_type: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+    align: usize,
+) -> [u8; PHDR_SIZE] {
+    let mut phdr = [0; PHDR_SIZE];
+    put(&mut phdr, 0, &p_type.to_le_bytes());
+    put(&mut phdr, 4, &flags.to_le_bytes());
+    put(&mut phdr, 8, &(offset as u64).to_le_bytes());
+    put(&mut phdr, 16, &(vaddr as u64).to_le_bytes());
+    put(&mut phdr, 32, &(filesz as u64).to_le_bytes());
+    put(&mut phdr, 40, &(memsz as u64).to_le_bytes());
+    put(&mut phdr, 48, &(align as u64).to_le_bytes());
+    phdr
+}
+
+fn elf_header(phnum: usize) -> [u8; EHDR_SIZE] {
+    let mut ehdr = [0; EHDR_SIZE];
+    // ELFCLASS64, ELFDATA2LSB, EV_CURRENT
+    put(&mut ehdr, 0, &[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    put(&mut ehdr, 16, &ET_CORE.to_le_bytes());
+    put(&mut ehdr, 18, &EM_RISCV.to_le_bytes());
+    put(&mut ehdr, 20, &1u32.to_le_bytes());
+    put(&mut ehdr, 32, &(EHDR_SIZE as u64).to_le_bytes());
+    put(&mut ehdr, 48, &EF_RISCV.to_le_bytes());
+    put(&mut ehdr, 52, &(EHDR_SIZE as u16).to_le_bytes());
+    put(&mut ehdr, 54, &(PHDR_SIZE as u16).to_le_bytes());
+    put(&mut ehdr, 56, &(phnum as u16).to_le_bytes());
+    ehdr
+}
+
+fn segment_flags(perm: MapPermission) -> u32 {
+    let mut flags = 0;
+    if perm.contains(MapPermission::R) {
+        flags |= PF_R;
+    }
+    if perm.contains(MapPermission::W) {
+        flags |= PF_W;
+    }
+    if perm.contains(MapPermission::X) {
+        flags |= PF_X;
+    }
+    flags
+}
+
+/// Signals whose default action dumps core.
+pub fn dumps_core(signum: u32) -> bool {
+    [
+        SignalFlags::SIGILL,
+        SignalFlags::SIGTRAP,
+        SignalFlags::SIGABRT,
+        SignalFlags::SIGFPE,
+        SignalFlags::SIGSEGV,
+    ]
+    .iter()
+    .any(|signal| signal.bits() == 1 << signum)
+}
+
+/// Write the core of the current task's process, if its RLIMIT_CORE
+/// allows. Must be called before the task exits, with interrupts on for
+/// the block device. Only the registers of the current task are accurate,
+/// see the module documentation.
+pub fn dump_core_of_current(fault: &FaultInfo) {
+    if !dumps_core(fault.signum) {
+        return;
+    }
+    let task = current_task().unwrap();
+    let (token, core_limit, ppid, memory_set) = {
+        let inner = task.inner_exclusive_access();
+        let ppid = inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.tgid);
+        (
+            inner.get_user_token(),
+            inner.core_limit,
+            ppid,
+            Arc::clone(&inner.memory_set),
+        )
+    };
+    if core_limit.rlim_cur == 0 {
+        return;
+    }
+
+    // the faulting thread comes first, gdb takes it for the current one
+    let mut threads: Vec<Arc<TaskControlBlock>> = PID2TCB.read(|map| {
+        map.values()
+            .filter(|thread| thread.tgid == task.tgid && !Arc::ptr_eq(thread, &task))
+            .cloned()
+            .collect()
+    });
+    threads.insert(0, task.clone());
+    // the threads share the address space, the stacks of all are in it
+    let areas: BTreeMap<_, _> = memory_set
+        .lock()
+        .user_areas()
+        .into_iter()
+        .map(|(start, end, perm)| (start, (end, perm)))
+        .collect();
+    let mut states = Vec::new();
+    for thread in threads.iter() {
+        let inner = thread.inner_exclusive_access();
+        // kernel thread tgids come from another allocator
+        if inner.get_user_token() != token {
+            continue;
+        }
+        // saved at the thread's last trap, stale if it is running now
+        states.push(ThreadState {
+            pid: thread.pid.0,
+            trap_cx: *inner.get_trap_cx(),
+            fp_cx: inner.fp_cx,
+        });
+    }
+
+    let mut notes = Vec::new();
+    for (i, state) in states.iter().enumerate() {
+        push_note(
+            &mut notes,
+            NT_PRSTATUS,
+            &prstatus(state, fault.signum, ppid, task.tgid),
+        );
+        if i == 0 {
+            push_note(&mut notes, NT_PRPSINFO, &prpsinfo(task.tgid, ppid));
+            push_note(&mut notes, NT_SIGINFO, &siginfo(fault));
+        }
+        push_note(&mut notes, NT_FPREGSET, &fpregset(state));
+    }
+    let phnum = 1 + areas.len();
+    let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let data_offset = (notes_offset + notes.len() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    let pages: usize = areas.iter().map(|(start, (end, _))| end.0 - start.0).sum();
+    if data_offset + pages * PAGE_SIZE > core_limit.rlim_cur {
+        println!("[kernel] core of process {} exceeds RLIMIT_CORE", task.tgid);
+        return;
+    }
+
+    let mut headers = Vec::with_capacity(data_offset);
+    headers.extend_from_slice(&elf_header(phnum));
+    headers.extend_from_slice(&program_header(
+        PT_NOTE,
+        0,
+        notes_offset,
+        0,
+        notes.len(),
+        0,
+        4,
+    ));
+    let mut offset = data_offset;
+    for (start, (end, perm)) in areas.iter() {
+        let size = (end.0 - start.0) * PAGE_SIZE;
+        let vaddr = start.0 * PAGE_SIZE;
+        headers.extend_from_slice(&program_header(
+            PT_LOAD,
+            segment_flags(*perm),
+            offset,
+            vaddr,
+            size,
+            size,
+            PAGE_SIZE,
+        ));
+        offset += size;
+    }
+    headers.extend_from_slice(&notes);
+    headers.resize(data_offset, 0);
+
+    let name = format!("core.{}", task.tgid);
+    let file = match open_file(name.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY) {
+        Some(file) => file,
+        None => return,
+    };
+    let page_table = PageTable::from_token(token);
+    let mut complete = file.write_all(&headers);
+    for (start, (end, _)) in areas.iter() {
+        for vpn in start.0..end.0 {
+            if !complete {
+                break;
+            }
+            complete = match page_table.translate(VirtPageNum(vpn)) {
+                Some(pte) if pte.is_valid() => file.write_all(pte.ppn().get_bytes_array()),
+                // keep the offsets right for pages that are not mapped
+                _ => file.write_all(&ZERO_PAGE),
+            };
+        }
+    }
+    if complete {
+        println!("[kernel] core dumped to {}", name);
+    } else {
+        println!("[kernel] disk full, {} is truncated", name);
+    }
+}
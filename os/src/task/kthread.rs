@@ -5,7 +5,7 @@ use crate::trap::{
 };
 use crate::mm::kernel_token;
 use crate::config::{
-    MEMORY_END, PAGE_SIZE,
+    memory_end, PAGE_SIZE,
 };
 use super::{
     TaskControlBlock,
@@ -162,13 +162,13 @@ pub fn kernel_tgid_alloc() -> TgidHandle {
 
 pub fn kthread_trap_cx_bottom_from_tid(tgid: usize) -> usize {
     //guard page
-    let memory_end = MEMORY_END + PAGE_SIZE;
+    let memory_end = memory_end() + PAGE_SIZE;
     memory_end + tgid * PAGE_SIZE
 }
 
 pub fn kthread_stack_bottom_from_tid(tgid: usize) -> usize {
     //guard page +  trap cx for kthread
-    let memory_end = MEMORY_END + PAGE_SIZE + 0x100000;
+    let memory_end = memory_end() + PAGE_SIZE + 0x100000;
     memory_end + tgid * STACK_SIZE
 }
 
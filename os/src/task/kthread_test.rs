@@ -1,14 +1,27 @@
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use lock::Mutex;
 
 use crate::sync::ksync::{
     k_mutex_create,
     k_mutex_lock,
     k_mutex_unlock,
+    k_rwlock_create,
+    k_rwlock_read,
+    k_rwlock_write,
+    k_rwlock_unlock,
+    k_barrier_create,
+    k_barrier_wait,
 };
 
 use super::{
     current_task,
+    pid2task,
+    TaskControlBlock,
+    PID2TCB,
 };
 
 use crate::task::kthread::{
@@ -34,6 +47,10 @@ pub fn kthread_test() {
     create_kthread(kthread_stop_test as usize);
     // pid 5
     create_kthread(kthread_test_sem as usize);
+    // pid 6
+    create_kthread(kthread_test_barrier as usize);
+    // pid 7
+    create_kthread(kthread_test_rcu as usize);
 }
 
 #[no_mangle]
@@ -127,4 +144,121 @@ pub fn kthread_test_sem(){
     assert_eq!(unsafe { A }, PER_THREAD * THREAD_COUNT);
     println!("kthread sem test pass time cost is {}ms", get_time_ms() - start);
     do_exit(0);
-}
\ No newline at end of file
+}
+
+static mut B: usize = 0;
+static mut RWLOCK: usize = 0;
+static mut BARRIER: usize = 0;
+const ROUNDS: usize = 10;
+
+// every round each kthread writes once, then all of them read what was written
+unsafe fn g(){
+    for round in 1..=ROUNDS {
+        k_rwlock_write(RWLOCK);
+        B += 1;
+        k_rwlock_unlock(RWLOCK);
+        k_barrier_wait(BARRIER);
+        k_rwlock_read(RWLOCK);
+        assert_eq!(B, round * THREAD_COUNT);
+        k_rwlock_unlock(RWLOCK);
+        k_barrier_wait(BARRIER);
+    }
+    do_exit(0)
+}
+
+#[no_mangle]
+pub fn kthread_test_barrier(){
+    unsafe {
+        RWLOCK = k_rwlock_create() as usize;
+        BARRIER = k_barrier_create(THREAD_COUNT) as usize;
+    }
+
+    let mut v = Vec::new();
+    for _ in 0..THREAD_COUNT {
+        v.push( create_kthread(g as usize) );
+    }
+    for pid in v.iter() {
+        wait_for_completion(*pid as usize);
+    }
+
+    assert_eq!(unsafe { B }, ROUNDS * THREAD_COUNT);
+    println!("kthread barrier test pass");
+    do_exit(0);
+}
+const LOOKUPS: usize = 100000;
+const CHURN: usize = 32;
+static CONTROLLER: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// PID2TCB the way it was before RCU, to compare lookups with
+    static ref LOCKED_PID2TCB: Mutex<BTreeMap<usize, Arc<TaskControlBlock>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+// kthreadd and the controlling kthread stay in the map the whole time
+fn rcu_reader(){
+    let controller = CONTROLLER.load(Ordering::SeqCst);
+    for _ in 0..LOOKUPS {
+        assert!(pid2task(0).is_some());
+        assert!(pid2task(controller).is_some());
+    }
+    do_exit(0)
+}
+
+fn locked_reader(){
+    let controller = CONTROLLER.load(Ordering::SeqCst);
+    for _ in 0..LOOKUPS {
+        assert!(LOCKED_PID2TCB.lock().get(&0).map(Arc::clone).is_some());
+        assert!(LOCKED_PID2TCB.lock().get(&controller).map(Arc::clone).is_some());
+    }
+    do_exit(0)
+}
+
+fn exit_now(){
+    do_exit(0)
+}
+
+// enters and leaves LOCKED_PID2TCB as it did PID2TCB, under the lock
+fn exit_locked(){
+    let task = current_task().unwrap();
+    let pid = task.pid.0;
+    LOCKED_PID2TCB.lock().insert(pid, task);
+    LOCKED_PID2TCB.lock().remove(&pid);
+    do_exit(0)
+}
+
+/// Runs THREAD_COUNT readers to the end while CHURN kthreads running
+/// `churn` come and go, and returns how long that took.
+fn time_readers(reader: fn(), churn: fn()) -> usize {
+    let start = get_time_ms();
+    let mut v = Vec::new();
+    for _ in 0..THREAD_COUNT {
+        v.push( create_kthread(reader as usize) );
+    }
+    // every kthread spawned and reaped is two updates under the readers
+    for _ in 0..CHURN {
+        wait_for_completion(create_kthread(churn as usize) as usize);
+    }
+    for pid in v.iter() {
+        wait_for_completion(*pid as usize);
+    }
+    (get_time_ms() - start).max(1)
+}
+
+// both kinds of readers see the same updates, the locked ones wait for them
+#[no_mangle]
+pub fn kthread_test_rcu(){
+    CONTROLLER.store(current_task().unwrap().pid.0, Ordering::SeqCst);
+    let lookups = THREAD_COUNT * LOOKUPS * 2;
+
+    let rcu_ms = time_readers(rcu_reader, exit_now);
+    println!("rcu: {} lookups in {}ms, {} per ms", lookups, rcu_ms, lookups / rcu_ms);
+
+    *LOCKED_PID2TCB.lock() = PID2TCB.read(|map| map.clone());
+    let locked_ms = time_readers(locked_reader, exit_locked);
+    LOCKED_PID2TCB.lock().clear();
+    println!("lock: {} lookups in {}ms, {} per ms", lookups, locked_ms, lookups / locked_ms);
+
+    println!("kthread rcu test pass");
+    do_exit(0);
+}
@@ -1,27 +1,36 @@
-use super::TaskControlBlock;
+use super::{TaskControlBlock, PRIORITY_MAX};
+use crate::sync::Rcu;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::cmp::Reverse;
 use lazy_static::*;
-use lock::Mutex;
 use alloc::collections::{BTreeMap};
 
+/// Every this many tasks fetched ahead of it, a ready task counts as one
+/// priority higher, up to PRIORITY_MAX, so that a busy higher priority
+/// cannot starve it.
+const AGING_FETCHES: usize = 32;
+
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// ready tasks with the fetch count they were added at
+    ready_queue: VecDeque<(Arc<TaskControlBlock>, usize)>,
+    fetches: usize,
 }
 
-/// A simple FIFO scheduler.
+/// Runs the ready task of the highest aged priority that waited longest.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
             ready_queue: VecDeque::new(),
+            fetches: 0,
         }
     }
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        self.ready_queue.push_back((task, self.fetches));
     }
     #[allow(unused)]
     pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
-        for (idx, task_item) in self.ready_queue.iter().enumerate() {
+        for (idx, (task_item, _)) in self.ready_queue.iter().enumerate() {
             if task_item.pid.0 == task.pid.0 {
                 self.ready_queue.remove(idx);
                 break;
@@ -30,7 +39,17 @@ impl TaskManager {
     }
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
         // May need to concern affinity
-        self.ready_queue.pop_front()
+        let fetches = self.fetches;
+        let (idx, _) = self
+            .ready_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (task, added))| {
+                let aged = task.priority.effective() + (fetches - added) / AGING_FETCHES;
+                Reverse(aged.min(PRIORITY_MAX))
+            })?;
+        self.fetches += 1;
+        self.ready_queue.remove(idx).map(|(task, _)| task)
     }
 
     #[allow(unused)]
@@ -39,37 +58,36 @@ impl TaskManager {
         if q.is_empty() || q.len() == 1 {
             return;
         }
-        let front_pid = q.front().unwrap().pid.0;
+        let front_pid = q.front().unwrap().0.pid.0;
         if front_pid == pid {
             debug!("[Taskmgr] Task {} already at front", pid);
             return;
         }
         q.rotate_left(1);
         while {
-            let f_pid = q.front().unwrap().pid.0;
+            let f_pid = q.front().unwrap().0.pid.0;
             f_pid != pid && f_pid != front_pid
         } {
             q.rotate_left(1);
         }
-        if q.front().unwrap().pid.0 == pid {
+        if q.front().unwrap().0.pid.0 == pid {
             debug!("[Taskmgr] Prioritized task {}", pid);
         }
     }
 }
 
 lazy_static! {
-    pub static ref PID2TCB: Mutex<BTreeMap<usize, Arc<TaskControlBlock>>> =Mutex::new(BTreeMap::new());
+    /// looked up on every pid based syscall, changed on spawn and exit only
+    pub static ref PID2TCB: Rcu<BTreeMap<usize, Arc<TaskControlBlock>>> = Rcu::new(BTreeMap::new());
 }
 
 
 pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
-    let map = PID2TCB.lock();
-    map.get(&pid).map(Arc::clone)
+    PID2TCB.read(|map| map.get(&pid).map(Arc::clone))
 }
 
 pub fn remove_from_pid2task(pid: usize) {
-    let mut map = PID2TCB.lock();
-    if map.remove(&pid).is_none() {
+    if PID2TCB.update(|map| map.remove(&pid)).is_none() {
         panic!("cannot find pid {} in pid2task!", pid);
     }
 }
@@ -77,5 +95,5 @@ pub fn remove_from_pid2task(pid: usize) {
 
 
 pub fn insert_into_pid2task(pid: usize, task: Arc<TaskControlBlock>) {
-    PID2TCB.lock().insert(pid, task);
+    PID2TCB.update(|map| map.insert(pid, task));
 }
\ No newline at end of file
@@ -7,10 +7,18 @@ mod pid;
 mod pool;
 mod action;
 mod signal;
+mod oom;
+mod personality;
+mod user_stack;
+mod coredump;
+mod ptrace;
+mod priority;
 pub mod kthread;
 pub mod kthread_test;
 
 use crate::fs::{open_file, OpenFlags};
+use crate::mm::{translated_refmut, MemorySet, VirtAddr};
+use crate::sync::{futex_wake, Mutex as _};
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
 use alloc::{sync::Arc};
@@ -19,6 +27,15 @@ use lazy_static::*;
 pub use context::TaskContext;
 pub use signal::{SignalFlags, MAX_SIG};
 pub use action::{SignalAction, SignalActions};
+pub use oom::{out_of_memory, THREAD_PAGES};
+pub use personality::Personality;
+pub use priority::{Priority, PRIORITY_DEFAULT, PRIORITY_MAX};
+pub use coredump::{dump_core_of_current, FaultInfo};
+pub use ptrace::{
+    copy_to_user, ptrace_signal_stop, ptrace_syscall_stop, read_user, user_writable, wait_tracee,
+    write_user, Resume, Tracee,
+    SIGTRAP_SYSCALL,
+};
 pub use processor::{
     run_tasks,
     current_task,
@@ -27,14 +44,19 @@ pub use processor::{
     take_current_task,
     schedule,
     hart_id,
-    current_trap_cx_user_va
+    current_trap_cx_user_va,
+    current_save_fp,
+    current_restore_fp
 };
 pub use pid::{
     PidHandle, pid_alloc, KernelStack,
     RecycleAllocator,
-    ustack_bottom_from_pid,
-    trap_cx_bottom_from_pid,
+    ustack_bottom_from_tid,
+    trap_cx_bottom_from_tid,
     kstack_alloc,
+    TidAllocator,
+    new_tid_allocator,
+    tid_alloc,
 };
 pub use manager::{
     PID2TCB,
@@ -114,6 +136,17 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     // Record exit code
     inner.exit_code = Some(exit_code);
     
+    // set_tid_address and CLONE_CHILD_CLEARTID, joiners wait for the zero;
+    // user code may have passed any address
+    if inner.clear_child_tid != 0 && user_u32_writable(&inner.memory_set.lock(), inner.clear_child_tid) {
+        *translated_refmut(inner.get_user_token(), inner.clear_child_tid as *mut u32) = 0;
+        let _ = futex_wake(inner.get_user_token(), inner.clear_child_tid, 1);
+    }
+
+    // mutexes it still holds go to their next waiters once the locks below
+    // are released, a dead owner would keep them locked forever
+    let held_mutexes = core::mem::take(&mut inner.held_mutexes);
+
     // main thread exit
     if pid == tgid {
         remove_from_pid2task(pid);
@@ -126,19 +159,27 @@ pub fn exit_current_and_run_next(exit_code: i32) {
 
         //clean up children dealloc resources
         inner.children.clear();
-        // deallocate user space
-        inner.memory_set.recycle_data_pages();
+        // deallocate user space, unless threads still run in it; then it
+        // goes with the last of them
+        if Arc::strong_count(&inner.memory_set) == 1 {
+            inner.memory_set.lock().recycle_data_pages();
+        }
         // deallocate fdtable
         inner.fd_table.clear();
+        // drop shared memory handles
+        inner.shm_list.clear();
 
     }
     // release initproc lock
     drop(initproc_inner);
 
     drop(inner);
-    drop(task);
     drop(wl);
     // **** release current PCB lock
+    for mutex in held_mutexes {
+        mutex.unlock();
+    }
+    drop(task);
 
     // we do not have to save task context
     let mut _unused = TaskContext::zero_init();
@@ -147,6 +188,15 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     schedule(&mut _unused as *mut TaskContext);
 }
 
+/// Whether user code may write the u32 at `va`.
+fn user_u32_writable(memory_set: &MemorySet, va: usize) -> bool {
+    va % core::mem::size_of::<u32>() == 0
+        && matches!(
+            memory_set.translate(VirtAddr::from(va).floor()),
+            Some(pte) if pte.is_valid() && pte.is_user() && pte.writable()
+        )
+}
+
 lazy_static! {
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
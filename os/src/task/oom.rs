@@ -0,0 +1,99 @@
+//! The out-of-memory killer.
+//!
+//! Allocations of user memory report `MapError::OutOfMemory` instead of
+//! panicking. The syscall that hit it calls `out_of_memory` with no lock
+//! held, which kills the process with the highest badness and waits for its
+//! memory to come back, after which the syscall retries. A request that
+//! would not fit even with every other process gone kills nothing.
+
+use super::{current_task, suspend_current_and_run_next, SignalFlags, TaskControlBlock};
+use super::{INITPROC, PID2TCB};
+use crate::config::{PAGE_SIZE, USER_STACK_SIZE};
+use crate::mm::free_frames;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// How many times to yield while waiting for a victim to exit.
+/// A victim blocked in the kernel may take longer, then the caller fails.
+const OOM_WAIT_YIELDS: usize = 100;
+
+/// The frames a new thread needs, its user stack and trap context.
+pub const THREAD_PAGES: usize = USER_STACK_SIZE / PAGE_SIZE + 1;
+
+/// Badness of a process is its resident page count, threads share it.
+/// Kernel threads and initproc own no user frames and are never chosen.
+fn badness(task: &Arc<TaskControlBlock>) -> usize {
+    if task.pid.0 != task.tgid || Arc::ptr_eq(task, &INITPROC) {
+        return 0;
+    }
+    let inner = task.inner_exclusive_access();
+    if inner.is_zombie() || inner.signals.contains(SignalFlags::SIGKILL) {
+        return 0;
+    }
+    inner.memory_set.lock().resident_pages()
+}
+
+/// Kill the process with the highest badness and wait until it has exited.
+/// `pages` is how many frames the failed request needs at least.
+///
+/// Return true if the caller should retry its allocation, false if the
+/// request cannot be met by killing, there is nothing left to kill, the
+/// victim did not exit in time or the caller itself was chosen. In the
+/// latter case the caller exits with SIGKILL on its way back to user mode.
+pub fn out_of_memory(pages: usize) -> bool {
+    let tasks: Vec<Arc<TaskControlBlock>> = PID2TCB.read(|map| map.values().cloned().collect());
+    let current = current_task().unwrap();
+    // the caller counts too, it may be the one to go
+    let reclaimable: usize = tasks.iter().map(badness).sum();
+    if free_frames() + reclaimable < pages {
+        println!(
+            "[kernel] Out of memory: {} pages cannot be freed for process {}",
+            pages, current.tgid
+        );
+        return false;
+    }
+    let victim = match tasks
+        .iter()
+        .map(|task| (badness(task), task))
+        .filter(|(badness, _)| *badness > 0)
+        .max_by_key(|(badness, task)| (*badness, task.pid.0))
+    {
+        Some((badness, task)) => {
+            println!(
+                "[kernel] Out of memory: killed process {}, {} pages",
+                task.pid.0, badness
+            );
+            task.clone()
+        }
+        None => {
+            println!("[kernel] Out of memory: no process to kill");
+            return false;
+        }
+    };
+    // threads of the victim share its page table, kernel thread tgids
+    // come from another allocator and may collide with user pids
+    let token = victim.inner_exclusive_access().get_user_token();
+    let group: Vec<Arc<TaskControlBlock>> = tasks
+        .into_iter()
+        .filter(|task| task.inner_exclusive_access().get_user_token() == token)
+        .collect();
+    for task in group.iter() {
+        task.inner_exclusive_access()
+            .signals
+            .insert(SignalFlags::SIGKILL);
+    }
+    if group.iter().any(|task| Arc::ptr_eq(task, &current)) {
+        return false;
+    }
+    drop(current);
+    for _ in 0..OOM_WAIT_YIELDS {
+        if group
+            .iter()
+            .all(|task| task.inner_exclusive_access().is_zombie())
+        {
+            return true;
+        }
+        suspend_current_and_run_next();
+    }
+    false
+}
@@ -0,0 +1,77 @@
+//! Syscall personalities.
+//!
+//! Programs linked against user_lib carry an ELF note naming the native
+//! ABI. Any other binary is taken for a Linux one, e.g. a static musl
+//! program, and gets the Linux RISC-V syscall table, see `syscall::linux`.
+
+use xmas_elf::program::Type;
+use xmas_elf::ElfFile;
+
+/// Owner name and type of the note emitted by user_lib.
+const NOTE_NAME: &[u8] = b"rCore\0";
+const NT_RCORE_ABI: u32 = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Personality {
+    Native,
+    Linux,
+}
+
+impl Personality {
+    /// `elf_data` has been checked by the loader already.
+    pub fn of_elf(elf_data: &[u8]) -> Self {
+        let elf = match ElfFile::new(elf_data) {
+            Ok(elf) => elf,
+            Err(_) => return Personality::Native,
+        };
+        let branded = elf.program_iter().any(|ph| {
+            if ph.get_type() != Ok(Type::Note) {
+                return false;
+            }
+            let start = ph.offset() as usize;
+            let end = start + ph.file_size() as usize;
+            elf_data
+                .get(start..end)
+                .map_or(false, |notes| has_abi_note(notes))
+        });
+        if branded {
+            Personality::Native
+        } else {
+            Personality::Linux
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Walk the notes of a PT_NOTE segment: namesz, descsz and type, followed
+/// by the name and the descriptor, each padded to 4 bytes.
+fn has_abi_note(notes: &[u8]) -> bool {
+    let mut offset = 0;
+    while offset + 12 <= notes.len() {
+        let (namesz, descsz, note_type) = match (
+            read_u32(notes, offset),
+            read_u32(notes, offset + 4),
+            read_u32(notes, offset + 8),
+        ) {
+            (Some(namesz), Some(descsz), Some(note_type)) => {
+                (namesz as usize, descsz as usize, note_type)
+            }
+            _ => return false,
+        };
+        let name_start = offset + 12;
+        let name = notes.get(name_start..name_start + namesz);
+        if name == Some(NOTE_NAME) && note_type == NT_RCORE_ABI {
+            return true;
+        }
+        offset = name_start + align4(namesz) + align4(descsz);
+    }
+    false
+}
+
+fn align4(size: usize) -> usize {
+    (size + 3) & !3
+}
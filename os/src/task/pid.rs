@@ -1,3 +1,4 @@
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
 use lock::Mutex;
@@ -7,11 +8,13 @@ use crate::config::{
     TRAMPOLINE,
     KERNEL_STACK_SIZE,
     USER_STACK_SIZE,
-    TRAP_CONTEXT_BASE
+    TRAP_CONTEXT_BASE,
+    MAX_THREADS
 };
 
 
 
+#[derive(Clone)]
 pub struct RecycleAllocator {
     current: usize,
     recycled: Vec<usize>,
@@ -127,12 +130,33 @@ impl KernelStack {
 
 
 
-pub fn trap_cx_bottom_from_pid(pid: usize) -> usize {
-    TRAP_CONTEXT_BASE - pid * PAGE_SIZE
+/// Thread resources are laid out by the thread's index in its process,
+/// see `tid_alloc`, so each process only reserves MAX_THREADS slots.
+pub fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
 }
 
-pub fn ustack_bottom_from_pid(pid: usize) -> usize {
-    0x30000 + pid * (PAGE_SIZE + USER_STACK_SIZE)
+/// `stack_base` comes from the address space's `AddressLayout`.
+pub fn ustack_bottom_from_tid(stack_base: usize, tid: usize) -> usize {
+    stack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
+}
+
+/// Shared by the threads of a process; the main thread owns tid 0.
+pub type TidAllocator = Arc<Mutex<RecycleAllocator>>;
+
+pub fn new_tid_allocator() -> TidAllocator {
+    Arc::new(Mutex::new(RecycleAllocator::new(1)))
+}
+
+/// Return None if the process already has MAX_THREADS threads.
+pub fn tid_alloc(allocator: &TidAllocator) -> Option<usize> {
+    let mut allocator = allocator.lock();
+    let tid = allocator.alloc();
+    if tid >= MAX_THREADS {
+        allocator.dealloc(tid);
+        return None;
+    }
+    Some(tid)
 }
 
 
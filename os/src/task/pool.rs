@@ -52,10 +52,8 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_POOL.lock().add(task);
 }
 pub fn add_task_first_time(task: Arc<TaskControlBlock>) {
-    use super::PID2TCB;
-    PID2TCB
-        .lock()
-        .insert(task.getpid(), Arc::clone(&task));
+    use super::insert_into_pid2task;
+    insert_into_pid2task(task.getpid(), Arc::clone(&task));
     TASK_POOL.lock().add(task);
 }
 
@@ -0,0 +1,59 @@
+//! Scheduling priorities. The scheduler picks a ready task of the highest
+//! effective priority, aged by how long it has been waiting so that lower
+//! ones are not starved, see `TaskManager::fetch`. The effective priority
+//! is the base priority a task asked for, raised while it holds a blocking
+//! mutex a higher priority task waits for, see `update_priority` in
+//! sync/mutex.rs. A task may not ask for more than it started with.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const PRIORITY_MAX: usize = 31;
+pub const PRIORITY_DEFAULT: usize = 16;
+
+/// Kept outside the task's inner lock, the scheduler reads it for every
+/// ready task it considers.
+pub struct Priority {
+    base: AtomicUsize,
+    effective: AtomicUsize,
+    /// the base priority the task started with
+    limit: usize,
+}
+
+impl Priority {
+    pub fn new(base: usize) -> Self {
+        Self {
+            base: AtomicUsize::new(base),
+            effective: AtomicUsize::new(base),
+            limit: base,
+        }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base.load(Ordering::Relaxed)
+    }
+
+    pub fn effective(&self) -> usize {
+        self.effective.load(Ordering::Relaxed)
+    }
+
+    /// The highest base priority the task may ask for.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The effective priority follows once it is recomputed.
+    pub fn set_base(&self, base: usize) {
+        self.base.store(base, Ordering::Relaxed);
+    }
+
+    /// Returns whether the effective priority changed.
+    pub fn set_effective(&self, effective: usize) -> bool {
+        self.effective.swap(effective, Ordering::Relaxed) != effective
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::new(PRIORITY_DEFAULT)
+    }
+}
@@ -9,7 +9,9 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 use crate::trap::TrapContext;
-use crate::config::CPU_NUM;
+use riscv::register::sstatus::FS;
+use crate::config::MAX_CPU_NUM;
+use crate::sync::rcu_quiescent_state;
 use lazy_static::*;
 use core::arch::asm;
 pub struct Processor {
@@ -22,6 +24,7 @@ impl Default for Processor {
             inner: RefCell::new(ProcessorInner {
                 current: None,
                 idle_task_cx: TaskContext::zero_init(),
+                fp_owner: 0,
             }),
         }
     }
@@ -33,6 +36,8 @@ unsafe impl Sync for Processor {}
 struct ProcessorInner {
     current: Option<Arc<TaskControlBlock>>,
     idle_task_cx: TaskContext,
+    // address of the task whose user FP registers this hart holds
+    fp_owner: usize,
 }
 
 impl Processor {
@@ -41,6 +46,7 @@ impl Processor {
             inner: RefCell::new(ProcessorInner {
                 current: None,
                 idle_task_cx: TaskContext::zero_init(),
+                fp_owner: 0,
             }),
         }
     }
@@ -93,6 +99,8 @@ impl Processor {
     #[no_mangle]
     pub fn run(&self) {
         loop {
+            // the task that ran last is off this hart
+            rcu_quiescent_state();
             if let Some(task) = fetch_task() {
                 self.run_next(task);
                 self.suspend_current();
@@ -113,7 +121,7 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSORS: [Processor; CPU_NUM] = Default::default();
+    pub static ref PROCESSORS: [Processor; MAX_CPU_NUM] = Default::default();
 }
 
 
@@ -170,6 +178,34 @@ pub fn current_trap_cx_user_va() -> usize {
 }
 
 
+/// Save the FP registers of the current task if it wrote them since they
+/// were last saved, the task's FpContext is up to date afterwards.
+pub fn current_save_fp() {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    if trap_cx.fs() == FS::Dirty {
+        inner.fp_cx.save();
+        trap_cx.set_fs(FS::Clean);
+    }
+}
+
+/// Load the FP registers of the current task, unless this hart has not
+/// run the user code of another task since.
+pub fn current_restore_fp() {
+    let task = current_task().unwrap();
+    let hart = hart_id();
+    let mut inner = task.inner_exclusive_access();
+    let mut processor = PROCESSORS[hart].inner.borrow_mut();
+    let owner = Arc::as_ptr(&task) as usize;
+    if inner.fp_hart != Some(hart) || processor.fp_owner != owner {
+        inner.fp_cx.restore();
+        inner.fp_hart = Some(hart);
+        processor.fp_owner = owner;
+    }
+    inner.get_trap_cx().set_fs(FS::Clean);
+}
+
 pub fn current_trap_cx_kernel_va() -> usize {
     let task = current_task().unwrap();
     let tgid = task.tgid;
@@ -0,0 +1,396 @@
+//! Process tracing, the tracee side of ptrace.
+//!
+//! A traced thread stops before a fatal signal takes effect, around
+//! syscalls if its tracer asked for that, and after single steps. Stopped
+//! threads spin in the kernel until their tracer resumes them, like frozen
+//! ones do. RISC-V has no single step in hardware, so a step plants
+//! c.ebreak on every instruction that may come next and stops at the
+//! breakpoint trap.
+//!
+//! Only the stepping thread knows its temporary breakpoints: another
+//! thread of the tracee that runs into one takes it for a real SIGTRAP.
+
+use super::{
+    current_task, suspend_current_and_run_next, SignalFlags, TaskControlBlock, MAX_SIG, PID2TCB,
+};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::mm::{PageTable, VirtAddr};
+use crate::trap::TrapContext;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+
+/// Reported for syscall stops, like PTRACE_O_TRACESYSGOOD on Linux.
+pub const SIGTRAP_SYSCALL: u32 = 5 | 0x80;
+const C_EBREAK: u16 = 0x9002;
+
+/// SIGKILL never stops a tracee.
+const STOPPING_SIGNALS: SignalFlags = SignalFlags::from_bits_truncate(
+    SignalFlags::SIGINT.bits()
+        | SignalFlags::SIGILL.bits()
+        | SignalFlags::SIGTRAP.bits()
+        | SignalFlags::SIGABRT.bits()
+        | SignalFlags::SIGFPE.bits()
+        | SignalFlags::SIGSEGV.bits()
+        | SignalFlags::SIGSTOP.bits(),
+);
+
+/// How the tracer lets a stopped tracee go on.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    /// stop again at the next syscall entry or exit
+    Syscall,
+    /// stop again after one instruction
+    Step,
+    Detach,
+}
+
+pub struct Tracee {
+    pub tracer: Weak<TaskControlBlock>,
+    /// what the tracee stopped with, None while it runs
+    pub stop: Option<u32>,
+    /// waitpid told the tracer about `stop` already
+    pub reported: bool,
+    pub resume: Resume,
+    /// delivered when the tracee resumes, 0 for none
+    pub resume_signal: u32,
+    /// temporary breakpoints of a step and the halfwords they replaced
+    step_breakpoints: Vec<(usize, u16)>,
+}
+
+impl Tracee {
+    pub fn new(tracer: &Arc<TaskControlBlock>) -> Self {
+        Self {
+            tracer: Arc::downgrade(tracer),
+            stop: None,
+            reported: false,
+            resume: Resume::Continue,
+            resume_signal: 0,
+            step_breakpoints: Vec::new(),
+        }
+    }
+
+    /// Any thread of the tracing process may issue requests.
+    pub fn is_traced_by(&self, task: &TaskControlBlock) -> bool {
+        self.tracer
+            .upgrade()
+            .map_or(false, |tracer| tracer.tgid == task.tgid)
+    }
+
+    /// Called by the tracer on a stopped tracee.
+    pub fn resume(&mut self, resume: Resume, signal: u32) {
+        self.stop = None;
+        self.resume = resume;
+        self.resume_signal = signal;
+    }
+
+    /// After exec, the image the breakpoints were planted in is gone.
+    pub fn forget_breakpoints(&mut self) {
+        self.step_breakpoints.clear();
+    }
+
+    fn remove_breakpoints(&mut self, token: usize) {
+        // in reverse, should two of them share an address
+        for (addr, half) in self.step_breakpoints.drain(..).rev() {
+            write_user(token, addr, &half.to_le_bytes());
+        }
+    }
+
+    fn plant_breakpoints(&mut self, token: usize, cx: &TrapContext) {
+        for pc in next_pcs(token, cx) {
+            if self.step_breakpoints.iter().any(|(addr, _)| *addr == pc) {
+                continue;
+            }
+            let mut half = [0u8; 2];
+            if read_user(token, pc, &mut half) && write_user(token, pc, &C_EBREAK.to_le_bytes()) {
+                self.step_breakpoints.push((pc, u16::from_le_bytes(half)));
+            }
+        }
+    }
+}
+
+/// The byte at `va` in the address space of `token`, None if it is not
+/// mapped for user code. Permissions do not matter, breakpoints go into
+/// read only text.
+fn user_byte(token: usize, va: usize) -> Option<&'static mut u8> {
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(va);
+    match page_table.translate(va.floor()) {
+        Some(pte) if pte.is_valid() && pte.is_user() => {
+            page_table.translate_va(va).map(|pa| pa.get_mut())
+        }
+        _ => None,
+    }
+}
+
+/// Copy user memory of another address space into `buf`, false if some of
+/// it is not mapped.
+pub fn read_user(token: usize, va: usize, buf: &mut [u8]) -> bool {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        match user_byte(token, va.wrapping_add(i)) {
+            Some(user) => *byte = *user,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Write `data` into user memory of another address space, false if some
+/// of it is not mapped. What comes before the hole is written anyway.
+pub fn write_user(token: usize, va: usize, data: &[u8]) -> bool {
+    for (i, byte) in data.iter().enumerate() {
+        match user_byte(token, va.wrapping_add(i)) {
+            Some(user) => *user = *byte,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Whether user code may write all `len` bytes at `va`.
+pub fn user_writable(token: usize, va: usize, len: usize) -> bool {
+    let end = match va.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return false,
+    };
+    if len == 0 {
+        return true;
+    }
+    let page_table = PageTable::from_token(token);
+    (va / PAGE_SIZE..=(end - 1) / PAGE_SIZE).all(|page| {
+        matches!(
+            page_table.translate(VirtAddr::from(page * PAGE_SIZE).floor()),
+            Some(pte) if pte.is_valid() && pte.is_user() && pte.writable()
+        )
+    })
+}
+
+/// Like `write_user`, but only into memory user code may write, and
+/// nothing is written unless all of it is.
+pub fn copy_to_user(token: usize, va: usize, data: &[u8]) -> bool {
+    user_writable(token, va, data.len()) && write_user(token, va, data)
+}
+
+fn sign_extend(value: u32, bits: u32) -> usize {
+    (((value << (32 - bits)) as i32) >> (32 - bits)) as isize as usize
+}
+
+/// Where the instruction at the pc of `cx` may go next, both ways for
+/// branches.
+fn next_pcs(token: usize, cx: &TrapContext) -> Vec<usize> {
+    let pc = cx.sepc;
+    let reg = |i: u32| if i == 0 { 0 } else { cx.x[i as usize] };
+    let mut bytes = [0u8; 4];
+    if !read_user(token, pc, &mut bytes[..2]) {
+        return Vec::new();
+    }
+    let half = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+    if half & 0b11 != 0b11 {
+        let rs1 = (half >> 7) & 0x1f;
+        let rs2 = (half >> 2) & 0x1f;
+        return match (half & 0b11, half >> 13) {
+            // c.j
+            (0b01, 0b101) => {
+                let offset = ((half >> 12) & 1) << 11
+                    | ((half >> 11) & 1) << 4
+                    | ((half >> 9) & 0b11) << 8
+                    | ((half >> 8) & 1) << 10
+                    | ((half >> 7) & 1) << 6
+                    | ((half >> 6) & 1) << 7
+                    | ((half >> 3) & 0b111) << 1
+                    | ((half >> 2) & 1) << 5;
+                vec![pc.wrapping_add(sign_extend(offset, 12))]
+            }
+            // c.beqz and c.bnez
+            (0b01, 0b110) | (0b01, 0b111) => {
+                let offset = ((half >> 12) & 1) << 8
+                    | ((half >> 10) & 0b11) << 3
+                    | ((half >> 5) & 0b11) << 6
+                    | ((half >> 3) & 0b11) << 1
+                    | ((half >> 2) & 1) << 5;
+                vec![pc + 2, pc.wrapping_add(sign_extend(offset, 9))]
+            }
+            // c.jr and c.jalr, rs1 0 is c.ebreak
+            (0b10, 0b100) if rs2 == 0 && rs1 != 0 => vec![reg(rs1) & !1],
+            _ => vec![pc + 2],
+        };
+    }
+    if !read_user(token, pc, &mut bytes) {
+        return Vec::new();
+    }
+    let inst = u32::from_le_bytes(bytes);
+    match inst & 0x7f {
+        // jal
+        0x6f => {
+            let offset = (inst >> 31) << 20
+                | ((inst >> 21) & 0x3ff) << 1
+                | ((inst >> 20) & 1) << 11
+                | ((inst >> 12) & 0xff) << 12;
+            vec![pc.wrapping_add(sign_extend(offset, 21))]
+        }
+        // jalr
+        0x67 => vec![reg((inst >> 15) & 0x1f).wrapping_add(sign_extend(inst >> 20, 12)) & !1],
+        // conditional branches
+        0x63 => {
+            let offset = (inst >> 31) << 12
+                | ((inst >> 25) & 0x3f) << 5
+                | ((inst >> 8) & 0xf) << 1
+                | ((inst >> 7) & 1) << 11;
+            vec![pc + 4, pc.wrapping_add(sign_extend(offset, 13))]
+        }
+        _ => vec![pc + 4],
+    }
+}
+
+fn tracer_alive(tracer: &Weak<TaskControlBlock>) -> bool {
+    tracer
+        .upgrade()
+        .map_or(false, |tracer| !tracer.inner_exclusive_access().is_zombie())
+}
+
+/// Stop the current thread until its tracer resumes it and return the
+/// signal to deliver then, 0 for none. Without a living tracer the thread
+/// is detached instead and `signum` is delivered.
+fn stop_current(signum: u32) -> u32 {
+    let task = current_task().unwrap();
+    // never hold our own lock while taking the tracer's
+    let tracer = match task.inner_exclusive_access().ptrace.as_ref() {
+        Some(trace) => trace.tracer.clone(),
+        None => return signum,
+    };
+    if !tracer_alive(&tracer) {
+        task.inner_exclusive_access().ptrace = None;
+        return signum;
+    }
+    {
+        let mut inner = task.inner_exclusive_access();
+        let token = inner.get_user_token();
+        let trace = inner.ptrace.as_mut().unwrap();
+        trace.remove_breakpoints(token);
+        trace.stop = Some(signum);
+        trace.reported = false;
+    }
+    loop {
+        suspend_current_and_run_next();
+        let mut inner = task.inner_exclusive_access();
+        let killed = inner.signals.contains(SignalFlags::SIGKILL);
+        let trace = inner.ptrace.as_mut().unwrap();
+        if killed {
+            trace.stop = None;
+            return 0;
+        }
+        if trace.stop.is_none() {
+            break;
+        }
+        drop(inner);
+        if !tracer_alive(&tracer) {
+            task.inner_exclusive_access().ptrace = None;
+            return signum;
+        }
+    }
+    let mut inner = task.inner_exclusive_access();
+    let token = inner.get_user_token();
+    let cx = inner.get_trap_cx();
+    let trace = inner.ptrace.as_mut().unwrap();
+    let signal = trace.resume_signal;
+    let resume = trace.resume;
+    match resume {
+        Resume::Step => trace.plant_breakpoints(token, cx),
+        Resume::Detach => inner.ptrace = None,
+        Resume::Continue | Resume::Syscall => {}
+    }
+    // the tracer may have changed code, from another hart too
+    unsafe {
+        asm!("fence.i");
+    }
+    signal
+}
+
+/// Syscall entry and exit stops, if the tracer asked for them. Returns
+/// false if the tracee got killed while it was stopped. Like on Linux, the
+/// tracer cannot send signals from these stops.
+pub fn ptrace_syscall_stop() -> bool {
+    let task = current_task().unwrap();
+    let traced = task
+        .inner_exclusive_access()
+        .ptrace
+        .as_ref()
+        .map_or(false, |trace| trace.resume == Resume::Syscall);
+    if !traced {
+        return true;
+    }
+    stop_current(SIGTRAP_SYSCALL);
+    !task
+        .inner_exclusive_access()
+        .signals
+        .contains(SignalFlags::SIGKILL)
+}
+
+/// Let the tracer see each pending fatal signal, or SIGSTOP, before it
+/// takes effect. What the tracer sends back replaces the signal.
+pub fn ptrace_signal_stop() {
+    let task = current_task().unwrap();
+    let mut delivered = SignalFlags::empty();
+    loop {
+        let signal = {
+            let mut inner = task.inner_exclusive_access();
+            if inner.ptrace.is_none() {
+                return;
+            }
+            let pending = (inner.signals & STOPPING_SIGNALS) - delivered;
+            if pending.is_empty() {
+                return;
+            }
+            let signal = SignalFlags::from_bits_truncate(1 << pending.bits().trailing_zeros());
+            inner.signals.remove(signal);
+            signal
+        };
+        let resume = stop_current(signal.bits().trailing_zeros());
+        if resume == 0 || resume as usize > MAX_SIG {
+            continue;
+        }
+        let flag = SignalFlags::from_bits_truncate(1 << resume);
+        // nothing acts on SIGSTOP but the stop itself
+        if flag != SignalFlags::SIGSTOP {
+            task.inner_exclusive_access().signals.insert(flag);
+            delivered |= flag;
+        }
+    }
+}
+
+/// A stop of a thread the current process traces that waitpid has not
+/// reported yet, `pid` -1 means any. Err if there is no such tracee,
+/// Ok(None) if it is running.
+pub fn wait_tracee(pid: isize) -> Result<Option<(usize, u32)>, ()> {
+    let current = current_task().unwrap();
+    let tasks: Vec<Arc<TaskControlBlock>> = PID2TCB.read(|map| {
+        map.values()
+            .filter(|task| pid == -1 || task.pid.0 == pid as usize)
+            .cloned()
+            .collect()
+    });
+    let mut found = false;
+    for task in tasks {
+        let mut inner = task.inner_exclusive_access();
+        if inner.is_zombie() {
+            continue;
+        }
+        if let Some(trace) = inner.ptrace.as_mut() {
+            if !trace.is_traced_by(&current) {
+                continue;
+            }
+            found = true;
+            if let (Some(signum), false) = (trace.stop, trace.reported) {
+                trace.reported = true;
+                return Ok(Some((task.pid.0, signum)));
+            }
+        }
+    }
+    if found {
+        Ok(None)
+    } else {
+        Err(())
+    }
+}
@@ -45,6 +45,8 @@ impl SignalFlags {
             Some((-2, "Killed, SIGINT=2"))
         } else if self.contains(Self::SIGILL) {
             Some((-4, "Illegal Instruction, SIGILL=4"))
+        } else if self.contains(Self::SIGTRAP) {
+            Some((-5, "Trace/breakpoint trap, SIGTRAP=5"))
         } else if self.contains(Self::SIGABRT) {
             Some((-6, "Aborted, SIGABRT=6"))
         } else if self.contains(Self::SIGFPE) {
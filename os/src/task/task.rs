@@ -2,15 +2,18 @@ use core::borrow::BorrowMut;
 use core::ops::DerefMut;
 
 use crate::mm::{
+    RLimit,
+    RLIM_INFINITY,
+    MapError,
     MemorySet,
+    SharedMemorySet,
     PhysPageNum,
     KERNEL_SPACE, 
     VirtAddr,
-    translated_refmut,
     MapPermission,
         kernel_token
 };
-use crate::trap::{TrapContext, trap_handler};
+use crate::trap::{FpContext, TrapContext, trap_handler};
 use crate::config::{TRAP_CONTEXT,PAGE_SIZE};
 use super::TaskContext;
 use super::{PidHandle, pid_alloc, KernelStack,insert_into_pid2task, add_task, kernel_tgid_alloc,kstack_alloc};
@@ -21,18 +24,24 @@ use alloc::string::String;
 use k210_pac::aes::en;
 use lock::{Mutex, MutexGuard};
 use crate::sync::{
-    Mutex as MyMutex,
-    Semaphore,
-    Condvar,
+    MutexBlocking,
+    new_deadlock_detector,
+    new_sync_objects,
+    SharedDeadlockDetector,
+    SharedSyncObjects,
 };
 use core::arch::asm;
 
 
-use crate::fs::{File, Stdin, Stdout};
+use crate::fs::{open_file, File, OpenFlags, Stdin, Stdout};
 use super::{
     SignalFlags,
     SignalActions,
+    Personality,
+    Priority,
+    Tracee,
 };
+use super::user_stack::{auxv_of, init_user_stack, AT_BASE};
 
 use crate::task::kthread::{
     new_kthread_trap_cx,
@@ -40,11 +49,14 @@ use crate::task::kthread::{
 };
 use crate::mm::{
     PhysAddr,
+    SharedMemory,
 };
 
 use crate::task::{
-    ustack_bottom_from_pid,
-    trap_cx_bottom_from_pid,
+    trap_cx_bottom_from_tid,
+    TidAllocator,
+    new_tid_allocator,
+    tid_alloc,
     kthread_trap_cx_bottom_from_tid,
     kthread_stack_bottom_from_tid
 };
@@ -55,6 +67,8 @@ pub struct TaskControlBlock {
     pub tgid: usize,
     pub kernel_stack: KernelStack,
     // mutable
+    // scheduling priority, see priority.rs
+    pub priority: Priority,
     inner: Mutex<TaskControlBlockInner>,
 }
 
@@ -63,7 +77,8 @@ pub struct TaskControlBlockInner {
     pub base_size: usize,
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
-    pub memory_set: MemorySet,
+    // the address space, shared by the threads of the process
+    pub memory_set: SharedMemorySet,
     pub parent: Option<Weak<TaskControlBlock>>,
     pub children: Vec<Arc<TaskControlBlock>>,
     pub exit_code: Option<i32>,
@@ -79,12 +94,42 @@ pub struct TaskControlBlockInner {
     // if the task is frozen by a signal
     pub frozen: bool,
     pub trap_ctx_backup: Option<TrapContext>,
-    pub mutex_list: Vec<Option<Arc<dyn MyMutex>>>,
-    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
-    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    // mutexes, semaphores, condvars, rwlocks and barriers by id, see sync/objects.rs
+    pub sync_objects: SharedSyncObjects,
+    // who holds and waits for the mutexes and semaphores, see sync/deadlock.rs
+    pub deadlock_detector: SharedDeadlockDetector,
+    pub shm_list: Vec<Option<Arc<SharedMemory>>>,
     pub flags: usize,
+    // index of the thread in its process, places its stack and trap context
+    pub tid: usize,
+    pub tid_allocator: TidAllocator,
+    // user FP registers, current whenever the task is in the kernel
+    pub fp_cx: FpContext,
+    // the hart whose registers were last loaded from fp_cx
+    pub fp_hart: Option<usize>,
+    // which syscall table the task uses, chosen by exec
+    pub personality: Personality,
+    // zeroed when the thread exits, see set_tid_address
+    pub clear_child_tid: usize,
+    // largest core dump the process may write, see coredump.rs
+    pub core_limit: RLimit,
+    // set while a tracer controls this thread, see ptrace.rs
+    pub ptrace: Option<Tracee>,
+    // log every syscall to the trace buffer, see syscall/trace.rs
+    pub syscall_trace: bool,
+    // set by check_timer when a timed wait ended without a wakeup
+    pub wait_timed_out: bool,
+    // blocking mutexes held and waited for, see update_priority in sync/mutex.rs
+    pub held_mutexes: Vec<Arc<MutexBlocking>>,
+    pub blocked_on: Option<Arc<MutexBlocking>>,
 }
 
+/// No core dumps unless a process raises its soft limit.
+const CORE_LIMIT_DEFAULT: RLimit = RLimit {
+    rlim_cur: 0,
+    rlim_max: RLIM_INFINITY,
+};
+
 impl TaskControlBlockInner {
     pub fn get_task_cx_ptr(&mut self) -> *mut TaskContext {
         &mut self.task_cx as *mut TaskContext
@@ -93,7 +138,7 @@ impl TaskControlBlockInner {
         self.trap_cx_ppn.get_mut()
     }
     pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+        self.memory_set.lock().token()
     }
     fn get_status(&self) -> TaskStatus {
         self.task_status
@@ -120,7 +165,7 @@ impl TaskControlBlock {
         self.inner.lock()
     }
     pub fn trap_cx_user_va(&self) -> usize {
-        trap_cx_bottom_from_pid(self.pid.0)
+        trap_cx_bottom_from_tid(self.inner_exclusive_access().tid)
     }    
     pub fn new(elf_data: &[u8]) -> Self {
         // alloc a pid 
@@ -130,11 +175,15 @@ impl TaskControlBlock {
         // println!("new tcb pid {} tgid {}", pid, tgid);
     
         // memory_set with elf program headers/trampoline/trap context/user stack        
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data, pid);
+        let (memory_set, user_sp, elf_info) = MemorySet::from_elf(elf_data).unwrap();
+        let entry_point = elf_info.entry;
+        // initproc runs without arguments and environment
+        let (user_sp, argv_base) =
+            init_user_stack(&memory_set, user_sp, &[], &[], &auxv_of(&elf_info));
 
         // for tcb::new()   and tcb::exec()     
-        // ustack/trap_cx =  ustack_bottom_from_pid(0) trap_cx_bottom_from_pid(0)
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid).into();
+        // ustack/trap_cx =  ustack_bottom_from_tid(0) trap_cx_bottom_from_tid(0)
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(0).into();
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(trap_cx_bottom_va).into())
             .unwrap()
@@ -153,13 +202,14 @@ impl TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
             tgid: tgid,
+            priority: Priority::default(),
             inner: unsafe {
                 Mutex::new(TaskControlBlockInner {
                     trap_cx_ppn,
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(Mutex::new(memory_set)),
                     parent: None,
                     children: Vec::new(),
                     exit_code: None,
@@ -178,10 +228,22 @@ impl TaskControlBlock {
                     killed: false,
                     frozen: false,
                     trap_ctx_backup: None,
-                    mutex_list: Vec::new(),
-                    semaphore_list: Vec::new(),
-                    condvar_list: Vec::new(),
+                    sync_objects: new_sync_objects(),
+                    deadlock_detector: new_deadlock_detector(),
+                    shm_list: Vec::new(),
                     flags: 0,
+                    tid: 0,
+                    tid_allocator: new_tid_allocator(),
+                    fp_cx: FpContext::zero_init(),
+                    fp_hart: None,
+                    personality: Personality::of_elf(elf_data),
+                    clear_child_tid: 0,
+                    core_limit: CORE_LIMIT_DEFAULT,
+                    ptrace: None,
+                    syscall_trace: false,
+                    wait_timed_out: false,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
                 })
             },
         };
@@ -195,55 +257,65 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
+        trap_cx.x[10] = 0;
+        trap_cx.x[11] = argv_base;
 
         // println!("new tcb trap cx :{:#x?}", trap_cx);
         task_control_block
     }
-    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) {
-
-        let parent_pid = self.pid.0;
+    pub fn exec(&self, elf_data: &[u8], args: Vec<String>, envs: Vec<String>) -> Result<(), MapError> {
 
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data, parent_pid);
+        let (mut memory_set, user_sp, elf_info) = MemorySet::from_elf(elf_data)?;
+        let mut entry_point = elf_info.entry;
+        let mut auxv = auxv_of(&elf_info);
+        // a dynamically linked program is started by its loader, which
+        // finds the program through AT_PHDR and AT_ENTRY; the file system
+        // is flat, so only the file name of the loader counts
+        if let Some(interp) = elf_info.interp.as_ref() {
+            let name = interp.rsplit('/').next().unwrap();
+            let interp_data = open_file(name, OpenFlags::RDONLY)
+                .ok_or(MapError::InvalidElf)?
+                .read_all();
+            let (interp_base, interp_entry) = memory_set.load_interp(&interp_data)?;
+            auxv.push((AT_BASE, interp_base));
+            entry_point = interp_entry;
+        }
 
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(parent_pid).into();
+        // the limit survives exec, the new image must fit into it
+        let mem_limit = self.inner_exclusive_access().memory_set.lock().mem_limit();
+        if memory_set.resident_pages() * PAGE_SIZE > mem_limit.rlim_cur {
+            return Err(MapError::LimitExceeded);
+        }
+        memory_set.set_mem_limit(mem_limit);
+
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(0).into();
 
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(trap_cx_bottom_va).into())
             .unwrap()
             .ppn();
         // println!("exec trap_cx_ppn {:#x?}", trap_cx_ppn);
-        // push arguments on user stack
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    memory_set.token(),
-                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0;
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translated_refmut(memory_set.token(), p as *mut u8) = *c;
-                p += 1;
-            }
-            *translated_refmut(memory_set.token(), p as *mut u8) = 0;
-        }
-        // make the user_sp aligned to 8B for k210 platform
-        user_sp -= user_sp % core::mem::size_of::<usize>();
+        // push arguments, environment and auxv on user stack
+        let (user_sp, argv_base) =
+            init_user_stack(&memory_set, user_sp, &args, &envs, &auxv);
 
         // **** access current TCB exclusively
         let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
+        // substitute memory_set, other threads keep the old one
+        inner.memory_set = Arc::new(Mutex::new(memory_set));
         // update trap_cx ppn
         inner.trap_cx_ppn = trap_cx_ppn;
+        // the caller becomes the main thread of the new image
+        inner.tid = 0;
+        inner.tid_allocator = new_tid_allocator();
+        // the new image starts with clear FP registers
+        inner.fp_cx = FpContext::zero_init();
+        inner.fp_hart = None;
+        inner.personality = Personality::of_elf(elf_data);
+        inner.clear_child_tid = 0;
+        // the segments were mapped into the old image only
+        let shm_list = core::mem::take(&mut inner.shm_list);
         // println!("set trap cx entry point {:#x?} user_sp {:#x?} kernel_stack_top {:#x?}", entry_point, user_sp, self.kernel_stack.get_top());
         // initialize trap_cx
         let mut trap_cx = TrapContext::app_init_context(
@@ -258,8 +330,12 @@ impl TaskControlBlock {
         // println!("exec trap cx :{:#x?}", trap_cx);
         *inner.get_trap_cx() = trap_cx;
         // **** release current PCB
+        drop(inner);
+        // the objects may be destroyed here, outside of the PCB lock
+        drop(shm_list);
+        Ok(())
     }
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Result<Arc<TaskControlBlock>, MapError> {
 
         let parent_pid = self.pid.0;
         let pid_handle = pid_alloc();
@@ -272,9 +348,12 @@ impl TaskControlBlock {
         let mut parent_inner = self.inner_exclusive_access();
 
         // copy user space(include trap context)
-        let (memory_set, user_sp) = MemorySet::from_existed_user(&parent_inner.memory_set, pid);
+        // the child continues in the forking thread's slot
+        let tid = parent_inner.tid;
+        let (memory_set, user_sp) =
+            MemorySet::from_existed_user(&parent_inner.memory_set.lock(), tid)?;
 
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid as usize).into();
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(tid).into();
 
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(trap_cx_bottom_va).into())
@@ -301,13 +380,14 @@ impl TaskControlBlock {
             pid: pid_handle,
             tgid: tgid,
             kernel_stack,
+            priority: Priority::new(self.priority.base()),
             inner: unsafe {
                 Mutex::new(TaskControlBlockInner {
                     trap_cx_ppn,
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(Mutex::new(memory_set)),
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: None,
@@ -320,10 +400,24 @@ impl TaskControlBlock {
                     killed: false,
                     frozen: false,
                     trap_ctx_backup: None,
-                    mutex_list: Vec::new(),
-                    semaphore_list: Vec::new(),
-                    condvar_list: Vec::new(),
+                    sync_objects: new_sync_objects(),
+                    deadlock_detector: new_deadlock_detector(),
+                    shm_list: parent_inner.shm_list.clone(),
                     flags: 0,
+                    tid,
+                    // slots of the other threads are copied along with their stacks
+                    tid_allocator: Arc::new(Mutex::new(parent_inner.tid_allocator.lock().clone())),
+                    // saved when the parent trapped into fork
+                    fp_cx: parent_inner.fp_cx,
+                    fp_hart: None,
+                    personality: parent_inner.personality,
+                    clear_child_tid: 0,
+                    core_limit: parent_inner.core_limit,
+                    ptrace: None,
+                    syscall_trace: parent_inner.syscall_trace,
+                    wait_timed_out: false,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
                 })
             },
         });
@@ -346,11 +440,11 @@ impl TaskControlBlock {
         drop(parent_inner);
         drop(inner);
 
-        child
+        Ok(child)
     }
 
 
-    pub fn new_user_thread(self: &Arc<TaskControlBlock>, entry_point: usize, arg: usize, parent_pid:usize) -> Arc<TaskControlBlock> {
+    pub fn new_user_thread(self: &Arc<TaskControlBlock>, entry_point: usize, arg: usize, parent_pid:usize) -> Result<Arc<TaskControlBlock>, MapError> {
 
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
@@ -363,14 +457,24 @@ impl TaskControlBlock {
         // ---- hold parent PCB lock
         let mut parent_inner = self.inner_exclusive_access();
 
-        // copy user space(include trap context)
-        let (memory_set, user_sp) = MemorySet::from_existed(&parent_inner.memory_set, pid);
+        let tid = tid_alloc(&parent_inner.tid_allocator).ok_or(MapError::LimitExceeded)?;
+
+        // map the thread's stack and trap context into the process's space
+        let mut memory_set = parent_inner.memory_set.lock();
+        let user_sp = match memory_set.map_thread(tid) {
+            Ok(user_sp) => user_sp,
+            Err(err) => {
+                parent_inner.tid_allocator.lock().dealloc(tid);
+                return Err(err);
+            }
+        };
         
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_pid(pid).into();
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(tid).into();
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(trap_cx_bottom_va).into())
             .unwrap()
             .ppn();
+        drop(memory_set);
         // println!("new uthread trap_cx_ppn {:#x?}", trap_cx_ppn);
         // get parent pid
 
@@ -391,13 +495,14 @@ impl TaskControlBlock {
             pid: pid_handle,
             tgid: tgid,
             kernel_stack,
+            priority: Priority::new(self.priority.base()),
             inner: unsafe {
                 Mutex::new(TaskControlBlockInner {
                     trap_cx_ppn,
                     base_size: parent_inner.base_size,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::clone(&parent_inner.memory_set),
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: None,
@@ -409,10 +514,22 @@ impl TaskControlBlock {
                     killed: false,
                     frozen: false,
                     trap_ctx_backup: None,
-                    mutex_list: parent_inner.mutex_list.clone(),
-                    semaphore_list: parent_inner.semaphore_list.clone(),
-                    condvar_list: parent_inner.condvar_list.clone(),
+                    sync_objects: Arc::clone(&parent_inner.sync_objects),
+                    deadlock_detector: Arc::clone(&parent_inner.deadlock_detector),
+                    shm_list: parent_inner.shm_list.clone(),
                     flags: 0,
+                    tid,
+                    tid_allocator: Arc::clone(&parent_inner.tid_allocator),
+                    fp_cx: FpContext::zero_init(),
+                    fp_hart: None,
+                    personality: parent_inner.personality,
+                    clear_child_tid: 0,
+                    core_limit: parent_inner.core_limit,
+                    ptrace: None,
+                    syscall_trace: parent_inner.syscall_trace,
+                    wait_timed_out: false,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
                 })
             },
         });
@@ -447,7 +564,7 @@ impl TaskControlBlock {
         // println!("new user thread trap cx :{:#x?}", trap_cx);
         drop(inner);
         // return   
-        task_control_block
+        Ok(task_control_block)
     }
 
     pub fn kthreadd_create(entry: usize) -> Arc<TaskControlBlock> {
@@ -486,7 +603,7 @@ impl TaskControlBlock {
         unsafe{
             asm!("sfence.vma");
         }
-        let memory_set = MemorySet::kernel_copy();
+        let memory_set = Arc::new(Mutex::new(MemorySet::kernel_copy()));
 
         let mut context = TaskContext::zero_init();
         let context_va = &context as *const TaskContext as usize;
@@ -503,6 +620,7 @@ impl TaskControlBlock {
             pid: pid_handle,
             tgid: tgid,
             kernel_stack,
+            priority: Priority::default(),
             inner: Mutex::new(
                 TaskControlBlockInner {
                     trap_cx_ppn,
@@ -522,10 +640,22 @@ impl TaskControlBlock {
                     killed: false,
                     frozen: false,
                     trap_ctx_backup: None,
-                    mutex_list: Vec::new(),
-                    semaphore_list: Vec::new(),
-                    condvar_list: Vec::new(),
+                    sync_objects: new_sync_objects(),
+                    deadlock_detector: new_deadlock_detector(),
+                    shm_list: Vec::new(),
                     flags: 0,
+                    tid: 0,
+                    tid_allocator: new_tid_allocator(),
+                    fp_cx: FpContext::zero_init(),
+                    fp_hart: None,
+                    personality: Personality::Native,
+                    clear_child_tid: 0,
+                    core_limit: CORE_LIMIT_DEFAULT,
+                    ptrace: None,
+                    syscall_trace: false,
+                    wait_timed_out: false,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
                 })
             },
         );
@@ -577,7 +707,7 @@ impl TaskControlBlock {
         let va: VirtAddr = trap_cx_bottom_va.into();
         let trap_cx_ppn = KERNEL_SPACE.exclusive_access().translate(va.into()).unwrap().ppn();
 
-        let memory_set = MemorySet::kernel_copy();
+        let memory_set = Arc::new(Mutex::new(MemorySet::kernel_copy()));
         let mut context = TaskContext::zero_init();
         let context_va = &context as *const TaskContext as usize;
         let context_pa = PhysAddr::from(context_va);
@@ -603,6 +733,7 @@ impl TaskControlBlock {
             pid: pid_handle,
             tgid: tgid,
             kernel_stack,
+            priority: Priority::default(),
             inner: Mutex::new(
                 TaskControlBlockInner {
                     trap_cx_ppn,
@@ -621,10 +752,22 @@ impl TaskControlBlock {
                     killed: false,
                     frozen: false,
                     trap_ctx_backup: None,
-                    mutex_list: kthreadd_inner.mutex_list.clone(),
-                    semaphore_list: kthreadd_inner.semaphore_list.clone(),
-                    condvar_list: kthreadd_inner.condvar_list.clone(),
+                    sync_objects: Arc::clone(&kthreadd_inner.sync_objects),
+                    deadlock_detector: Arc::clone(&kthreadd_inner.deadlock_detector),
+                    shm_list: Vec::new(),
                     flags: 0,
+                    tid: 0,
+                    tid_allocator: new_tid_allocator(),
+                    fp_cx: FpContext::zero_init(),
+                    fp_hart: None,
+                    personality: Personality::Native,
+                    clear_child_tid: 0,
+                    core_limit: CORE_LIMIT_DEFAULT,
+                    ptrace: None,
+                    syscall_trace: false,
+                    wait_timed_out: false,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
                 })
             },
         );
@@ -0,0 +1,98 @@
+//! The initial user stack of a new image, laid out as the Linux ABI
+//! expects:
+//!
+//! sp -> argc, argv[0..argc], NULL, envp[..], NULL, auxv pairs, AT_NULL
+//!
+//! with the strings and the AT_RANDOM bytes above. Native programs find
+//! argc and argv in a0 and a1 as well.
+
+use crate::config::PAGE_SIZE;
+use crate::mm::{ElfInfo, MemorySet};
+use crate::random::rand_u64;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_HWCAP: usize = 16;
+pub const AT_RANDOM: usize = 25;
+
+/// One bit per single letter ISA extension, as Linux reports them.
+const fn isa_bit(extension: u8) -> usize {
+    1 << (extension - b'A')
+}
+
+/// RV64GC: IMAFD plus compressed instructions.
+const HWCAP_ISA: usize =
+    isa_bit(b'I') | isa_bit(b'M') | isa_bit(b'A') | isa_bit(b'F') | isa_bit(b'D') | isa_bit(b'C');
+
+/// Bytes of AT_RANDOM, libc seeds its stack protector from them.
+const RANDOM_BYTES: usize = 16;
+
+/// The auxiliary vector describing a loaded image.
+pub fn auxv_of(elf_info: &ElfInfo) -> Vec<(usize, usize)> {
+    alloc::vec![
+        (AT_PHDR, elf_info.phdr),
+        (AT_PHENT, elf_info.phent),
+        (AT_PHNUM, elf_info.phnum),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf_info.entry),
+        (AT_HWCAP, HWCAP_ISA),
+    ]
+}
+
+/// Push the strings and the vectors below `user_sp`, return the new sp,
+/// 16 byte aligned, and the address of argv. AT_RANDOM is added to `auxv`.
+pub fn init_user_stack(
+    memory_set: &MemorySet,
+    mut user_sp: usize,
+    args: &[String],
+    envs: &[String],
+    auxv: &[(usize, usize)],
+) -> (usize, usize) {
+    let mut push_strings = |strings: &[String]| -> Vec<usize> {
+        strings
+            .iter()
+            .map(|string| {
+                user_sp -= string.len() + 1;
+                memory_set.write_bytes(user_sp, string.as_bytes());
+                memory_set.write_bytes(user_sp + string.len(), &[0]);
+                user_sp
+            })
+            .collect()
+    };
+    let argv = push_strings(args);
+    let envp = push_strings(envs);
+    user_sp -= RANDOM_BYTES;
+    for offset in (0..RANDOM_BYTES).step_by(size_of::<u64>()) {
+        memory_set.write_bytes(user_sp + offset, &rand_u64().to_ne_bytes());
+    }
+    let random = user_sp;
+
+    let mut vector = Vec::new();
+    vector.push(args.len());
+    vector.extend(argv);
+    vector.push(0);
+    vector.extend(envp);
+    vector.push(0);
+    for (key, value) in auxv.iter() {
+        vector.push(*key);
+        vector.push(*value);
+    }
+    vector.push(AT_RANDOM);
+    vector.push(random);
+    vector.push(AT_NULL);
+    vector.push(0);
+
+    user_sp = (user_sp - vector.len() * size_of::<usize>()) & !0xf;
+    for (i, word) in vector.iter().enumerate() {
+        memory_set.write_bytes(user_sp + i * size_of::<usize>(), &word.to_ne_bytes());
+    }
+    (user_sp, user_sp + size_of::<usize>())
+}
@@ -3,8 +3,8 @@ use core::cmp::Ordering;
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
 use crate::sync::UPIntrFreeCell;
-use crate::task::{add_task, TaskControlBlock};
-use alloc::collections::BinaryHeap;
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use alloc::collections::{BinaryHeap, VecDeque};
 use alloc::sync::Arc;
 use lazy_static::*;
 use riscv::register::time;
@@ -27,6 +27,33 @@ pub fn set_next_trigger() {
 pub struct TimerCondVar {
     pub expire_ms: usize,
     pub task: Arc<TaskControlBlock>,
+    /// set for timed waits, the task is only woken if still waiting there
+    pub wait: Option<Arc<dyn TimedWait>>,
+}
+
+/// A primitive with a wait queue that a task can leave on a timeout.
+pub trait TimedWait: Send + Sync {
+    /// Take `task` off the wait queue and undo what waiting did. False if
+    /// it was woken already, a wakeup and a timeout racing on two harts
+    /// are decided under the primitive's lock.
+    fn cancel_wait(&self, task: &Arc<TaskControlBlock>) -> bool;
+}
+
+/// Remove `task` from `wait_queue`, false if it is not there.
+pub fn remove_waiter(
+    wait_queue: &mut VecDeque<Arc<TaskControlBlock>>,
+    task: &Arc<TaskControlBlock>,
+) -> bool {
+    match wait_queue
+        .iter()
+        .position(|waiter| Arc::ptr_eq(waiter, task))
+    {
+        Some(i) => {
+            wait_queue.remove(i);
+            true
+        }
+        None => false,
+    }
 }
 
 impl PartialEq for TimerCondVar {
@@ -56,7 +83,35 @@ lazy_static! {
 
 pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
     let mut timers = TIMERS.exclusive_access();
-    timers.push(TimerCondVar { expire_ms, task });
+    timers.push(TimerCondVar {
+        expire_ms,
+        task,
+        wait: None,
+    });
+}
+
+/// Block the current task, which the caller has put on the wait queue of
+/// `wait` and released its lock, until it is woken or `ms` milliseconds
+/// pass. Returns false on a timeout.
+pub fn block_current_with_timeout(wait: Arc<dyn TimedWait>, ms: usize) -> bool {
+    let task = current_task().unwrap();
+    // taken after the primitive's lock is gone, check_timer holds TIMERS
+    // while it takes that lock
+    TIMERS.exclusive_access().push(TimerCondVar {
+        expire_ms: get_time_ms() + ms,
+        task: Arc::clone(&task),
+        wait: Some(wait),
+    });
+    block_current_and_run_next();
+    let timed_out = core::mem::take(&mut task.inner_exclusive_access().wait_timed_out);
+    if !timed_out {
+        // woken in time, the timer must not end a later wait
+        let mut timers = TIMERS.exclusive_access();
+        let mut pending = core::mem::take(&mut *timers).into_vec();
+        pending.retain(|timer| !Arc::ptr_eq(&timer.task, &task));
+        *timers = BinaryHeap::from(pending);
+    }
+    !timed_out
 }
 
 pub fn check_timer() {
@@ -64,8 +119,16 @@ pub fn check_timer() {
     let mut timers = TIMERS.exclusive_access();
     while let Some(timer) = timers.peek() {
         if timer.expire_ms <= current_ms {
-            add_task(Arc::clone(&timer.task));
-            timers.pop();
+            let timer = timers.pop().unwrap();
+            match timer.wait {
+                None => add_task(timer.task),
+                Some(wait) => {
+                    if wait.cancel_wait(&timer.task) {
+                        timer.task.inner_exclusive_access().wait_timed_out = true;
+                        add_task(timer.task);
+                    }
+                }
+            }
         } else {
             break;
         }
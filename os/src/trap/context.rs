@@ -1,4 +1,8 @@
-use riscv::register::sstatus::{self, Sstatus, SPP};
+use riscv::register::sstatus::{self, Sstatus, FS, SPP};
+
+/// FS field of sstatus, bits 13 and 14.
+const SSTATUS_FS_SHIFT: usize = 13;
+const SSTATUS_FS_MASK: usize = 0b11 << SSTATUS_FS_SHIFT;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -15,6 +19,15 @@ impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    /// State of the user floating-point registers when the task trapped.
+    pub fn fs(&self) -> FS {
+        self.sstatus.fs()
+    }
+    /// `Sstatus` only sets FS in the CSR, patch the saved copy directly.
+    pub fn set_fs(&mut self, fs: FS) {
+        let bits = unsafe { &mut *(&mut self.sstatus as *mut Sstatus as *mut usize) };
+        *bits = (*bits & !SSTATUS_FS_MASK) | ((fs as usize) << SSTATUS_FS_SHIFT);
+    }
     pub fn app_init_context(
         entry: usize,
         sp: usize,
@@ -34,6 +47,8 @@ impl TrapContext {
             trap_handler,
         };
         cx.set_sp(sp);
+        // registers are loaded from a zeroed FpContext before the first sret
+        cx.set_fs(FS::Initial);
         cx
     }
 }
@@ -0,0 +1,47 @@
+//! Floating-point registers of user tasks.
+//!
+//! The kernel never uses the `f` registers, so they keep the user values
+//! across a trap. `sstatus.FS` of the trapped task says whether it wrote
+//! them since they were last saved: a Dirty state is saved when the task
+//! traps, and marked Clean. A hart only loads them on the way back to user
+//! mode if it last ran the user code of another task.
+
+use core::arch::global_asm;
+use riscv::register::sstatus::{self, FS};
+
+global_asm!(include_str!("fp.S"));
+
+extern "C" {
+    fn __save_fp(fp_cx_ptr: *mut FpContext);
+    fn __restore_fp(fp_cx_ptr: *const FpContext);
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FpContext {
+    pub f: [u64; 32],
+    pub fcsr: usize,
+}
+
+impl FpContext {
+    /// The state of a new image, all registers zero and round to nearest.
+    pub fn zero_init() -> Self {
+        Self {
+            f: [0; 32],
+            fcsr: 0,
+        }
+    }
+    /// Save the registers of this hart, FS must not be Off.
+    pub fn save(&mut self) {
+        unsafe {
+            __save_fp(self as *mut FpContext);
+        }
+    }
+    /// Load the registers of this hart, turning the unit on first.
+    pub fn restore(&self) {
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            __restore_fp(self as *const FpContext);
+        }
+    }
+}
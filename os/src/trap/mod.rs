@@ -1,10 +1,13 @@
 mod context;
+mod fp;
 
 use crate::config::TRAMPOLINE;
 use crate::syscall::syscall;
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
+    check_signals_of_current, current_add_signal, current_restore_fp, current_save_fp,
+    current_trap_cx, current_trap_cx_user_va, current_user_token, dump_core_of_current,
+    exit_current_and_run_next, ptrace_signal_stop, ptrace_syscall_stop,
+    suspend_current_and_run_next, FaultInfo, SignalFlags,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
@@ -61,8 +64,12 @@ fn disable_supervisor_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    // before anything may schedule another task on this hart
+    current_save_fp();
     let scause = scause::read();
     let stval = stval::read();
+    // for the core dump, None if the signal comes from elsewhere
+    let mut fault_addr = None;
     //println!("into {:?}", scause.cause());
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
@@ -70,11 +77,19 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
             enable_supervisor_interrupt();
-            // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
-            // cx is changed during sys_exec, so we have to call it again
-            cx = current_trap_cx();
-            cx.x[10] = result as usize;
+            // a tracer may rewrite the call at the entry stop
+            if ptrace_syscall_stop() {
+                cx = current_trap_cx();
+                // get system call return value
+                let result = syscall(
+                    cx.x[17],
+                    [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+                );
+                // cx is changed during sys_exec, so we have to call it again
+                cx = current_trap_cx();
+                cx.x[10] = result as usize;
+                ptrace_syscall_stop();
+            }
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
@@ -82,11 +97,17 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
+            fault_addr = Some(stval);
             current_add_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
+            fault_addr = Some(current_trap_cx().sepc);
             current_add_signal(SignalFlags::SIGILL);
         }
+        Trap::Exception(Exception::Breakpoint) => {
+            fault_addr = Some(current_trap_cx().sepc);
+            current_add_signal(SignalFlags::SIGTRAP);
+        }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
@@ -103,9 +124,17 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // a tracer sees fatal signals first and may cancel them
+    ptrace_signal_stop();
     // check signals
     if let Some((errno, msg)) = check_signals_of_current() {
         println!("[kernel] {}", msg);
+        // the dump goes through the block device
+        enable_supervisor_interrupt();
+        dump_core_of_current(&FaultInfo {
+            signum: -errno as u32,
+            addr: fault_addr,
+        });
         exit_current_and_run_next(errno);
     }
     trap_return();
@@ -121,6 +150,7 @@ pub fn trap_return() -> ! {
         sstatus::set_spp(sstatus::SPP::User);
     }
     set_user_trap_entry();
+    current_restore_fp();
 
     let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
@@ -177,3 +207,4 @@ pub fn trap_from_kernel(_trap_cx: &TrapContext) {
 }
 
 pub use context::TrapContext;
+pub use fp::FpContext;
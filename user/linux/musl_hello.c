@@ -0,0 +1,56 @@
+/*
+ * A static musl program for the Linux personality of the kernel, run by
+ * usertests. Built by `make linux` in user/, see the Makefile there.
+ */
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/uio.h>
+#include <time.h>
+#include <unistd.h>
+
+#define PAGE_SIZE 4096
+
+static void check(int ok, const char *what)
+{
+	if (!ok) {
+		fprintf(stderr, "musl_hello: %s failed, errno %d\n", what, errno);
+		exit(1);
+	}
+}
+
+int main(void)
+{
+	struct iovec iov[2] = {
+		{ .iov_base = "Hello, ", .iov_len = 7 },
+		{ .iov_base = "musl!\n", .iov_len = 6 },
+	};
+	check(writev(STDOUT_FILENO, iov, 2) == 13, "writev");
+
+	/* musl's sbrk only reports the break, move it with the raw syscall */
+	long brk = syscall(SYS_brk, 0);
+	check(syscall(SYS_brk, brk + 2 * PAGE_SIZE) == brk + 2 * PAGE_SIZE, "brk up");
+	char *heap = (char *)brk;
+	heap[0] = 1;
+	heap[2 * PAGE_SIZE - 1] = 2;
+	check(syscall(SYS_brk, brk) == brk, "brk down");
+
+	char *map = mmap(NULL, 3 * PAGE_SIZE, PROT_READ | PROT_WRITE,
+			 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	check(map != MAP_FAILED, "mmap");
+	check(map[0] == 0 && map[3 * PAGE_SIZE - 1] == 0, "mmap zeroed");
+	map[PAGE_SIZE] = 3;
+	check(munmap(map, 3 * PAGE_SIZE) == 0, "munmap");
+
+	/* pointers user code may not write are refused, not followed */
+	check(syscall(SYS_uname, (void *)8) == -1 && errno == EFAULT, "uname EFAULT");
+	check(syscall(SYS_clock_gettime, CLOCK_REALTIME, (void *)8) == -1 &&
+		      errno == EFAULT,
+	      "clock_gettime EFAULT");
+
+	printf("musl_hello passed!\n");
+	/* returning from main ends in exit_group */
+	return 0;
+}
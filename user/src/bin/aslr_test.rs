@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+use alloc::format;
+use user_lib::{close, exec, fork, pipe, read, shm_map, shm_open, waitpid, write, ShmFlags};
+
+const SHM_KEY: usize = 0x4153_4c52;
+
+/// Report the stack and mmap addresses of a fresh image through `fd`.
+fn report(fd: usize) -> i32 {
+    let local = 0usize;
+    let shm_id = shm_open(SHM_KEY, 4096, ShmFlags::CREATE);
+    assert!(shm_id >= 0);
+    let addrs = [
+        &local as *const usize as usize,
+        shm_map(shm_id as usize) as usize,
+    ];
+    let bytes = unsafe { core::slice::from_raw_parts(addrs.as_ptr() as *const u8, 16) };
+    assert_eq!(write(fd, bytes), 16);
+    0
+}
+
+fn spawn(pipe_fd: &[usize; 2]) -> [usize; 2] {
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        let fd = format!("{}\0", pipe_fd[1]);
+        exec(
+            "aslr_test\0",
+            &["aslr_test\0".as_ptr(), fd.as_ptr(), core::ptr::null::<u8>()],
+        );
+        panic!("exec failed");
+    }
+    let mut addrs = [0usize; 2];
+    let bytes = unsafe { core::slice::from_raw_parts_mut(addrs.as_mut_ptr() as *mut u8, 16) };
+    assert_eq!(read(pipe_fd[0], bytes), 16);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    addrs
+}
+
+/// Fails when booted with `norandmaps`.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 {
+        return report(argv[1].parse().unwrap());
+    }
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let first = spawn(&pipe_fd);
+    let second = spawn(&pipe_fd);
+    println!("stack {:#x} / {:#x}", first[0], second[0]);
+    println!("mmap  {:#x} / {:#x}", first[1], second[1]);
+    assert_ne!(first[0], second[0]);
+    assert_ne!(first[1], second[1]);
+    println!("aslr_test passed!");
+    0
+}
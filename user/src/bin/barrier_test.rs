@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::sync::Mutex;
+use user_lib::{barrier_create, barrier_wait, exit, thread_create, waittid, yield_, EINVAL};
+
+const THREADS: usize = 8;
+const ROUNDS: usize = 20;
+
+static BARRIER: AtomicUsize = AtomicUsize::new(0);
+static ROUND_OF: Mutex<[usize; THREADS]> = Mutex::new([0; THREADS]);
+static LAST_ARRIVALS: AtomicUsize = AtomicUsize::new(0);
+
+fn wait() {
+    if barrier_wait(BARRIER.load(Ordering::SeqCst)) == 1 {
+        LAST_ARRIVALS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Each round every thread publishes its round number, then checks that
+/// nobody is behind or ahead of it.
+fn worker(idx: usize) -> ! {
+    for round in 1..=ROUNDS {
+        // arrive at different times
+        for _ in 0..(idx + round) % THREADS {
+            yield_();
+        }
+        ROUND_OF.lock()[idx] = round;
+        wait();
+        assert!(ROUND_OF.lock().iter().all(|&round_of| round_of == round));
+        wait();
+    }
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(barrier_create(0), -EINVAL);
+    // a barrier for one never blocks
+    let single = barrier_create(1);
+    assert!(single >= 0);
+    assert_eq!(barrier_wait(single as usize), 1);
+
+    BARRIER.store(barrier_create(THREADS) as usize, Ordering::SeqCst);
+    let tids: [usize; THREADS] =
+        core::array::from_fn(|idx| thread_create(worker as usize, idx) as usize);
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(LAST_ARRIVALS.load(Ordering::SeqCst), 2 * ROUNDS);
+
+    println!("barrier_test passed!");
+    0
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    condvar_broadcast, condvar_create, condvar_wait, condvar_wait_timeout, exit,
+    mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, sleep, thread_create, waittid,
+    yield_,
+};
+
+const THREADS: usize = 8;
+/// Long enough for the started threads to wait.
+const SETTLE_MS: usize = 20;
+
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+static IN_CRITICAL: AtomicBool = AtomicBool::new(false);
+static LEFT: AtomicUsize = AtomicUsize::new(0);
+
+/// Waits for the shutdown, half of the threads with a timeout that must not
+/// expire, then checks it holds the mutex alone.
+fn waiter(timed: usize) -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    let condvar = CONDVAR.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        if timed != 0 {
+            assert_eq!(condvar_wait_timeout(condvar, mutex, 10_000), 0);
+        } else {
+            condvar_wait(condvar, mutex);
+        }
+    }
+    assert!(!IN_CRITICAL.swap(true, Ordering::SeqCst));
+    yield_();
+    LEFT.fetch_add(1, Ordering::SeqCst);
+    IN_CRITICAL.store(false, Ordering::SeqCst);
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn shut_down(mutex: usize) {
+    MUTEX.store(mutex, Ordering::SeqCst);
+    SHUTDOWN.store(false, Ordering::SeqCst);
+    LEFT.store(0, Ordering::SeqCst);
+    let condvar = CONDVAR.load(Ordering::SeqCst);
+    let tids: [usize; THREADS] =
+        core::array::from_fn(|idx| thread_create(waiter as usize, idx % 2) as usize);
+    sleep(SETTLE_MS);
+    mutex_lock(mutex);
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    condvar_broadcast(condvar);
+    // the waiters queue up for the mutex we hold
+    yield_();
+    assert_eq!(LEFT.load(Ordering::SeqCst), 0);
+    mutex_unlock(mutex);
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(LEFT.load(Ordering::SeqCst), THREADS);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let condvar = condvar_create() as usize;
+    CONDVAR.store(condvar, Ordering::SeqCst);
+    // nobody to wake
+    condvar_broadcast(condvar);
+
+    // waiters are moved onto a blocking mutex, a spin mutex only wakes them
+    shut_down(mutex_blocking_create() as usize);
+    shut_down(mutex_create() as usize);
+
+    println!("condvar_broadcast_test passed!");
+    0
+}
@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, exit, fork, getrlimit, open, read, setrlimit, waitpid, OpenFlags, RLimit, RLIMIT_CORE,
+    RLIM_INFINITY,
+};
+
+const SIGSEGV: i32 = 11;
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> usize {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+fn open_core(pid: usize) -> isize {
+    open(format!("core.{}\0", pid).as_str(), OpenFlags::RDONLY)
+}
+
+/// Fork a child that stores to address 0 and return its pid.
+fn crash(core_limit: usize) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        let rlim = RLimit {
+            rlim_cur: core_limit,
+            rlim_max: RLIM_INFINITY,
+        };
+        assert_eq!(setrlimit(RLIMIT_CORE, &rlim), 0);
+        unsafe {
+            core::ptr::null_mut::<u8>().write_volatile(0);
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    pid as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // off unless asked for, like `ulimit -c 0`
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_CORE, &mut rlim), 0);
+    assert_eq!(rlim.rlim_cur, 0);
+    let pid = crash(0);
+    assert_eq!(open_core(pid), -1);
+
+    // a limit too small for the image writes nothing either
+    let pid = crash(4096);
+    assert_eq!(open_core(pid), -1);
+
+    let pid = crash(RLIM_INFINITY);
+    let fd = open_core(pid);
+    assert!(fd > 0);
+    let mut header = [0u8; 1024];
+    let len = read(fd as usize, &mut header) as usize;
+    close(fd as usize);
+    assert_eq!(len, header.len());
+    assert_eq!(&header[..4], b"\x7fELF");
+    assert_eq!(u16_at(&header, 16), ET_CORE);
+    assert_eq!(u16_at(&header, 18), EM_RISCV);
+    // the notes come first, starting with the registers of the thread
+    let phoff = u64_at(&header, 32);
+    assert!(u16_at(&header, 56) > 1);
+    assert_eq!(u32_at(&header, phoff), PT_NOTE);
+    let notes = u64_at(&header, phoff + 8);
+    assert_eq!(u32_at(&header, notes + 8), NT_PRSTATUS);
+    // pr_cursig, after the 4 byte name size, desc size, type and "CORE\0"
+    assert_eq!(u16_at(&header, notes + 20 + 12) as i32, SIGSEGV);
+    println!("coredump_test passed!");
+    0
+}
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    enable_deadlock_detect, exit, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock,
+    semaphore_create, semaphore_destroy, semaphore_down, semaphore_up, thread_create, waittid,
+    yield_, EDEADLK,
+};
+
+static FIRST: AtomicUsize = AtomicUsize::new(0);
+static SECOND: AtomicUsize = AtomicUsize::new(0);
+static SECOND_HELD: AtomicBool = AtomicBool::new(false);
+
+/// Takes the mutexes in the opposite order of main, exits with 1 if it
+/// was the one told about the deadlock.
+fn opposite_order() -> ! {
+    let first = FIRST.load(Ordering::SeqCst);
+    let second = SECOND.load(Ordering::SeqCst);
+    assert_eq!(mutex_lock(second), 0);
+    SECOND_HELD.store(true, Ordering::SeqCst);
+    let ret = mutex_lock(first);
+    if ret == 0 {
+        mutex_unlock(first);
+    }
+    mutex_unlock(second);
+    exit((ret == -EDEADLK) as i32)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), 0);
+    assert_eq!(enable_deadlock_detect(2), -1);
+
+    // locking a mutex twice, blocking or not
+    for mutex in [mutex_blocking_create(), mutex_create()] {
+        let mutex = mutex as usize;
+        assert_eq!(mutex_lock(mutex), 0);
+        assert_eq!(mutex_lock(mutex), -EDEADLK);
+        mutex_unlock(mutex);
+        assert_eq!(mutex_lock(mutex), 0);
+        mutex_unlock(mutex);
+    }
+
+    // waiting for the only unit, held by ourselves
+    let sem = semaphore_create(1) as usize;
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_down(sem), -EDEADLK);
+    semaphore_up(sem);
+    assert_eq!(semaphore_down(sem), 0);
+    semaphore_up(sem);
+
+    // a destroyed id handed out again names a fresh object, not what the
+    // old one had left held
+    assert_eq!(semaphore_down(sem), 0);
+    assert_eq!(semaphore_destroy(sem), 0);
+    assert_eq!(semaphore_create(1) as usize, sem);
+    assert_eq!(semaphore_down(sem), 0);
+    semaphore_up(sem);
+
+    // two threads taking two mutexes in opposite orders, whichever of them
+    // closes the cycle is refused and backs off
+    let first = mutex_blocking_create() as usize;
+    let second = mutex_blocking_create() as usize;
+    FIRST.store(first, Ordering::SeqCst);
+    SECOND.store(second, Ordering::SeqCst);
+    assert_eq!(mutex_lock(first), 0);
+    let tid = thread_create(opposite_order as usize, 0) as usize;
+    while !SECOND_HELD.load(Ordering::SeqCst) {
+        yield_();
+    }
+    let ret = mutex_lock(second);
+    if ret == 0 {
+        mutex_unlock(second);
+    }
+    mutex_unlock(first);
+    let refused = (ret == -EDEADLK) as isize + waittid(tid);
+    assert_eq!(refused, 1);
+
+    println!("deadlock_test passed!");
+    0
+}
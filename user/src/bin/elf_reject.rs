@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exec, fork, open, read, waitpid, write, OpenFlags};
+
+const E_MACHINE: usize = 18;
+const E_PHOFF: usize = 32;
+const EM_X86_64: u8 = 62;
+
+/// Copy `src` to `dst`, at most `limit` bytes, letting `patch` change the
+/// first block, which holds the ELF header.
+fn copy_file(src: &str, dst: &str, limit: usize, patch: fn(&mut [u8])) {
+    let src_fd = open(src, OpenFlags::RDONLY);
+    let dst_fd = open(dst, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(src_fd > 0 && dst_fd > 0);
+    let mut buffer = [0u8; 512];
+    let mut copied = 0;
+    while copied < limit {
+        let len = read(src_fd as usize, &mut buffer) as usize;
+        if len == 0 {
+            break;
+        }
+        if copied == 0 {
+            patch(&mut buffer[..len]);
+        }
+        let len = len.min(limit - copied);
+        assert_eq!(write(dst_fd as usize, &buffer[..len]), len as isize);
+        copied += len;
+    }
+    close(src_fd as usize);
+    close(dst_fd as usize);
+}
+
+fn assert_rejected(path: &str) {
+    assert_eq!(exec(path, &[path.as_ptr(), core::ptr::null()]), -1);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("not_elf\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, b"#just some text\n");
+    close(fd as usize);
+    assert_rejected("not_elf\0");
+
+    // the program headers point past the end of the file
+    copy_file("hello_world\0", "truncated_elf\0", 512, |_| {});
+    assert_rejected("truncated_elf\0");
+
+    copy_file("hello_world\0", "x86_elf\0", usize::MAX, |header| {
+        header[E_MACHINE] = EM_X86_64;
+        header[E_MACHINE + 1] = 0;
+    });
+    assert_rejected("x86_elf\0");
+
+    copy_file("hello_world\0", "bad_phoff_elf\0", usize::MAX, |header| {
+        header[E_PHOFF..E_PHOFF + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    });
+    assert_rejected("bad_phoff_elf\0");
+
+    // an intact copy runs, so the copies above differ only by the patch
+    copy_file("hello_world\0", "hello_copy\0", usize::MAX, |_| {});
+    let pid = fork();
+    if pid == 0 {
+        exec("hello_copy\0", &[core::ptr::null()]);
+        panic!("intact copy rejected");
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("elf_reject passed!");
+    0
+}
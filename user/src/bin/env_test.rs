@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::env::{self, AT_PAGESZ, AT_RANDOM};
+use user_lib::{exec, execve, fork, waitpid};
+
+/// Check the environment a parent set up, see `main`.
+fn child(argv: &[&str]) -> i32 {
+    match argv[1] {
+        "inherited" => {
+            assert_eq!(env::var("GREETING").as_deref(), Some("hello"));
+            assert_eq!(env::var("EMPTY").as_deref(), Some(""));
+            assert_eq!(env::var("REMOVED"), None);
+        }
+        "explicit" => {
+            assert_eq!(env::vars().len(), 1);
+            assert_eq!(env::var("ONLY").as_deref(), Some("this"));
+        }
+        _ => unreachable!(),
+    }
+    0
+}
+
+fn run(args: &[*const u8], envs: Option<&[*const u8]>) {
+    let pid = fork();
+    if pid == 0 {
+        match envs {
+            Some(envs) => execve("env_test\0", args, envs),
+            None => exec("env_test\0", args),
+        };
+        panic!("exec failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return child(argv);
+    }
+    assert_eq!(env::auxv(AT_PAGESZ), Some(4096));
+    assert!(env::auxv(AT_RANDOM).is_some());
+
+    env::set_var("GREETING", "hi");
+    env::set_var("GREETING", "hello");
+    env::set_var("EMPTY", "");
+    env::set_var("REMOVED", "yes");
+    env::remove_var("REMOVED");
+    assert_eq!(env::var("GREETING").as_deref(), Some("hello"));
+    run(
+        &[
+            "env_test\0".as_ptr(),
+            "inherited\0".as_ptr(),
+            core::ptr::null(),
+        ],
+        None,
+    );
+    run(
+        &[
+            "env_test\0".as_ptr(),
+            "explicit\0".as_ptr(),
+            core::ptr::null(),
+        ],
+        Some(&["ONLY=this\0".as_ptr(), core::ptr::null()]),
+    );
+    println!("env_test passed!");
+    0
+}
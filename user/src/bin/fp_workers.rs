@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, waitpid, yield_};
+
+const WORKERS: usize = 4;
+const ITERATIONS: usize = 200000;
+
+/// Keeps its running sum in FP registers across the yields, which switch
+/// to the other workers on the same hart.
+fn series(scale: f64) -> f64 {
+    let mut sum = 0.0;
+    let mut x = scale;
+    for i in 1..=ITERATIONS {
+        sum += x / (i as f64 * i as f64);
+        x = -x;
+        if i % 1000 == 0 {
+            yield_();
+        }
+    }
+    sum
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut expected = [0u64; WORKERS];
+    for (i, bits) in expected.iter_mut().enumerate() {
+        *bits = series((i + 1) as f64).to_bits();
+    }
+    let mut pids = [0isize; WORKERS];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            let bits = series((i + 1) as f64).to_bits();
+            exit(if bits == expected[i] { 0 } else { 1 });
+        }
+        assert!(*pid > 0);
+    }
+    // the parent keeps computing while its children run
+    assert_eq!(series(1.0).to_bits(), expected[0]);
+    for pid in pids.iter() {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(*pid as usize, &mut exit_code), *pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("fp_workers passed!");
+    0
+}
@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::sync::{Condvar, Mutex, Once};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, get_time, shm_close, shm_map, shm_open, shm_unmap, sleep,
+    thread_create, waitpid, waittid, ShmFlags, EAGAIN, EFAULT, ETIMEDOUT,
+};
+
+const THREADS: usize = 8;
+const PER_THREAD: usize = 1000;
+const TIMEOUT: usize = 30;
+const SHM_KEY: usize = 0x4655_54;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static READY: Mutex<bool> = Mutex::new(false);
+static READY_CV: Condvar = Condvar::new();
+static INIT: Once = Once::new();
+static INIT_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn increment() -> ! {
+    for _ in 0..PER_THREAD {
+        *COUNTER.lock() += 1;
+    }
+    exit(0)
+}
+
+fn wait_ready() -> ! {
+    let mut ready = READY.lock();
+    while !*ready {
+        ready = READY_CV.wait(ready);
+    }
+    drop(ready);
+    exit(0)
+}
+
+fn init() -> ! {
+    INIT.call_once(|| {
+        sleep(TIMEOUT);
+        INIT_RUNS.fetch_add(1, Ordering::SeqCst);
+    });
+    // nobody returns before the call that ran has finished
+    assert_eq!(INIT_RUNS.load(Ordering::SeqCst), 1);
+    exit(0)
+}
+
+fn join(tids: &[usize]) {
+    for &tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the raw calls
+    let word = AtomicU32::new(1);
+    assert_eq!(futex_wait(&word, 0, 0), -EAGAIN);
+    let start = get_time() as usize;
+    assert_eq!(futex_wait(&word, 1, TIMEOUT), -ETIMEDOUT);
+    assert!(get_time() as usize - start >= TIMEOUT);
+    assert_eq!(futex_wake(&word, 1), 0);
+    let unmapped = unsafe { &*(0x10 as *const AtomicU32) };
+    assert_eq!(futex_wait(unmapped, 0, 0), -EFAULT);
+
+    // no increment is lost
+    let tids: [usize; THREADS] =
+        core::array::from_fn(|_| thread_create(increment as usize, 0) as usize);
+    join(&tids);
+    assert_eq!(*COUNTER.lock(), THREADS * PER_THREAD);
+    assert!(COUNTER.try_lock().is_some());
+    let held = COUNTER.lock();
+    assert!(COUNTER.try_lock().is_none());
+    drop(held);
+
+    // every waiter sees the flag set before a single notify_all
+    let tids: [usize; THREADS] =
+        core::array::from_fn(|_| thread_create(wait_ready as usize, 0) as usize);
+    sleep(TIMEOUT);
+    *READY.lock() = true;
+    READY_CV.notify_all();
+    join(&tids);
+
+    let tids: [usize; THREADS] = core::array::from_fn(|_| thread_create(init as usize, 0) as usize);
+    join(&tids);
+    assert!(INIT.is_completed());
+    assert_eq!(INIT_RUNS.load(Ordering::SeqCst), 1);
+
+    // a word in shared memory wakes waiters of another process
+    let shm_id = shm_open(SHM_KEY, 4096, ShmFlags::CREATE);
+    assert!(shm_id >= 0);
+    let addr = shm_map(shm_id as usize);
+    assert!(addr > 0);
+    let shared = unsafe { &*(addr as *const AtomicU32) };
+    let pid = fork();
+    if pid == 0 {
+        sleep(TIMEOUT);
+        shared.store(1, Ordering::SeqCst);
+        futex_wake(shared, 1);
+        exit(0);
+    }
+    while shared.load(Ordering::SeqCst) == 0 {
+        futex_wait(shared, 0, 0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(shm_unmap(addr as usize), 0);
+    assert_eq!(shm_close(shm_id as usize), 0);
+
+    println!("futex_test passed!");
+    0
+}
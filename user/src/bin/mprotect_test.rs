@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mprotect, shm_close, shm_map, shm_open, shm_unmap, waitpid, ProtFlags, ShmFlags,
+};
+
+const PAGE_SIZE: usize = 4096;
+const SHM_KEY: usize = 0x4d50_524f;
+
+#[repr(C, align(4096))]
+struct Page([u8; PAGE_SIZE]);
+
+static mut PAGE: Page = Page([0; PAGE_SIZE]);
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let addr = unsafe { PAGE.0.as_ptr() as usize };
+    unsafe {
+        PAGE.0[0] = 42;
+    }
+    // misaligned start or unmapped range
+    assert_eq!(mprotect(addr + 1, PAGE_SIZE, ProtFlags::READ), -1);
+    assert_eq!(mprotect(0x1000_0000, PAGE_SIZE, ProtFlags::READ), -1);
+
+    assert_eq!(mprotect(addr, PAGE_SIZE, ProtFlags::READ), 0);
+    assert_eq!(unsafe { core::ptr::read_volatile(&PAGE.0[0]) }, 42);
+    let pid = fork();
+    if pid == 0 {
+        // the page is read-only in the child too, this must fault
+        unsafe {
+            core::ptr::write_volatile(&mut PAGE.0[0], 0);
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -11);
+
+    assert_eq!(mprotect(addr, PAGE_SIZE, ProtFlags::READ | ProtFlags::WRITE), 0);
+    unsafe {
+        core::ptr::write_volatile(&mut PAGE.0[0], 43);
+    }
+
+    // PROT_NONE is kept across fork, even reading faults
+    assert_eq!(mprotect(addr, PAGE_SIZE, ProtFlags::empty()), 0);
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            core::ptr::read_volatile(&PAGE.0[0]);
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -11);
+    // write-only pages are readable too
+    assert_eq!(mprotect(addr, PAGE_SIZE, ProtFlags::WRITE), 0);
+    assert_eq!(unsafe { core::ptr::read_volatile(&PAGE.0[0]) }, 43);
+
+    // a PROT_NONE page can be unmapped
+    let shm = shm_open(SHM_KEY, 3 * PAGE_SIZE, ShmFlags::CREATE | ShmFlags::EXCL);
+    assert!(shm >= 0);
+    let shm_addr = shm_map(shm as usize);
+    assert!(shm_addr > 0);
+    let shm_addr = shm_addr as usize;
+    // split into three, unmapping takes every piece
+    let middle = shm_addr + PAGE_SIZE;
+    assert_eq!(mprotect(middle, PAGE_SIZE, ProtFlags::empty()), 0);
+    assert_eq!(shm_unmap(shm_addr), 0);
+    for page in [middle, middle + PAGE_SIZE] {
+        assert_eq!(mprotect(page, PAGE_SIZE, ProtFlags::READ), -1);
+    }
+    assert_eq!(shm_close(shm as usize), 0);
+    println!("mprotect_test passed!");
+    0
+}
@@ -0,0 +1,147 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mq_close, mq_open, mq_receive, mq_send, mq_timedreceive, mq_timedsend, mq_unlink,
+    read, waitpid, write, MqFlags, EAGAIN, EBADF, EEXIST, EFAULT, EINVAL, EMSGSIZE, ENOENT,
+    ETIMEDOUT, MQ_PRIO_MAX,
+};
+
+const NAME: &str = "mqueue_test\0";
+const MAX_MSGS: usize = 4;
+const MSG_SIZE: usize = 16;
+const PRODUCERS: usize = 3;
+const PER_PRODUCER: usize = 20;
+
+fn receive(fd: usize) -> ([u8; MSG_SIZE], usize, usize) {
+    let mut buf = [0u8; MSG_SIZE];
+    let mut priority = 0;
+    let len = mq_receive(fd, &mut buf, &mut priority);
+    assert!(len >= 0);
+    (buf, len as usize, priority)
+}
+
+/// Opens the queue by name and sends its messages, blocking whenever the
+/// small queue is full.
+fn producer(idx: usize) -> ! {
+    let fd = mq_open(NAME, MqFlags::WRONLY, 0, 0);
+    assert!(fd >= 0);
+    for seq in 0..PER_PRODUCER {
+        assert_eq!(mq_send(fd as usize, &[idx as u8, seq as u8], idx), 0);
+    }
+    mq_close(fd as usize);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let create = MqFlags::RDWR | MqFlags::CREATE | MqFlags::EXCL;
+    assert_eq!(mq_open(NAME, MqFlags::RDWR, 0, 0), -ENOENT);
+    assert_eq!(mq_open(NAME, create, 0, 1 << 20), -EINVAL);
+    assert_eq!(
+        mq_open(NAME, MqFlags::WRONLY | MqFlags::RDWR, 0, 0),
+        -EINVAL
+    );
+    let fd = mq_open(NAME, create, MAX_MSGS, MSG_SIZE);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(mq_open(NAME, create, MAX_MSGS, MSG_SIZE), -EEXIST);
+
+    // the highest priority first, in order of sending within one
+    let sent: [(&[u8], usize); MAX_MSGS] = [(b"low", 1), (b"high", 5), (b"mid", 3), (b"higher", 5)];
+    for (msg, priority) in sent.iter().copied() {
+        assert_eq!(mq_send(fd, msg, priority), 0);
+    }
+    let expected: [(&[u8], usize); MAX_MSGS] =
+        [(b"high", 5), (b"higher", 5), (b"mid", 3), (b"low", 1)];
+    for (msg, priority) in expected.iter().copied() {
+        let (buf, len, got_priority) = receive(fd);
+        assert_eq!(&buf[..len], msg);
+        assert_eq!(got_priority, priority);
+    }
+    assert_eq!(mq_send(fd, b"x", MQ_PRIO_MAX), -EINVAL);
+    assert_eq!(mq_send(fd, &[0; MSG_SIZE + 1], 0), -EMSGSIZE);
+    let mut priority = 0;
+    assert_eq!(
+        mq_receive(fd, &mut [0; MSG_SIZE - 1], &mut priority),
+        -EMSGSIZE
+    );
+
+    // a buffer or priority we may not write fails before the message is
+    // taken, code is read-only
+    assert_eq!(mq_send(fd, b"kept", 2), 0);
+    let code = main as usize & !(core::mem::size_of::<usize>() - 1);
+    let code_buf = unsafe { core::slice::from_raw_parts_mut(code as *mut u8, MSG_SIZE) };
+    assert_eq!(mq_receive(fd, code_buf, &mut priority), -EFAULT);
+    let code_priority = unsafe { &mut *(code as *mut usize) };
+    assert_eq!(mq_receive(fd, &mut [0; MSG_SIZE], code_priority), -EFAULT);
+    let (buf, len, got_priority) = receive(fd);
+    assert_eq!(&buf[..len], b"kept");
+    assert_eq!(got_priority, 2);
+
+    // full and empty, waiting or not
+    let nonblocking = mq_open(NAME, MqFlags::RDWR | MqFlags::NONBLOCK, 0, 0);
+    assert!(nonblocking >= 0);
+    let nonblocking = nonblocking as usize;
+    for _ in 0..MAX_MSGS {
+        assert_eq!(mq_send(nonblocking, b"fill", 0), 0);
+    }
+    assert_eq!(mq_send(nonblocking, b"fill", 0), -EAGAIN);
+    assert_eq!(mq_timedsend(fd, b"fill", 0, 20), -ETIMEDOUT);
+    for _ in 0..MAX_MSGS {
+        receive(fd);
+    }
+    let mut buf = [0u8; MSG_SIZE];
+    assert_eq!(mq_receive(nonblocking, &mut buf, &mut priority), -EAGAIN);
+    // not 0, which would be the end of file
+    assert_eq!(read(nonblocking, &mut buf), -1);
+    assert_eq!(mq_timedreceive(fd, &mut buf, &mut priority, 20), -ETIMEDOUT);
+    mq_close(nonblocking);
+
+    // an fd like any other
+    assert_eq!(write(fd, b"plain"), 5);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf[..5], b"plain");
+    let read_only = mq_open(NAME, MqFlags::RDONLY, 0, 0);
+    assert!(read_only >= 0);
+    assert_eq!(mq_send(read_only as usize, b"x", 0), -EBADF);
+    mq_close(read_only as usize);
+    assert_eq!(mq_send(1, b"x", 0), -EBADF);
+
+    // processes find the queue by name, each one's messages stay in order
+    let pids: [isize; PRODUCERS] = core::array::from_fn(|idx| {
+        let pid = fork();
+        if pid == 0 {
+            producer(idx);
+        }
+        pid
+    });
+    let mut next_seq = [0usize; PRODUCERS];
+    for _ in 0..PRODUCERS * PER_PRODUCER {
+        let (buf, len, priority) = receive(fd);
+        assert_eq!(len, 2);
+        let idx = buf[0] as usize;
+        assert_eq!(priority, idx);
+        assert_eq!(buf[1] as usize, next_seq[idx]);
+        next_seq[idx] += 1;
+    }
+    for pid in pids {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+
+    // the name goes, the open fd keeps working
+    assert_eq!(mq_unlink(NAME), 0);
+    assert_eq!(mq_unlink(NAME), -ENOENT);
+    assert_eq!(mq_open(NAME, MqFlags::RDWR, 0, 0), -ENOENT);
+    assert_eq!(mq_send(fd, b"still", 0), 0);
+    assert_eq!(receive(fd).1, 5);
+    assert_eq!(mq_close(fd), 0);
+
+    println!("mqueue_test passed!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mutex_close, mutex_lock, mutex_open, mutex_trylock, mutex_unlink, mutex_unlock,
+    semaphore_close, semaphore_down, semaphore_open, semaphore_unlink, semaphore_up, waitpid,
+    NamedFlags, EBUSY, EEXIST, EINVAL, ENOENT,
+};
+
+const CHILDREN: usize = 4;
+const SEM_NAME: &str = "named_sync_test_ready\0";
+const MUTEX_NAME: &str = "named_sync_test_lock\0";
+
+/// Finds both objects by name, checks the mutex is the one the parent
+/// holds, reports in and waits for the parent to let go of it.
+fn child() -> ! {
+    let sem = semaphore_open(SEM_NAME, NamedFlags::empty(), 0);
+    let mutex = mutex_open(MUTEX_NAME, NamedFlags::empty());
+    assert!(sem >= 0 && mutex >= 0);
+    let (sem, mutex) = (sem as usize, mutex as usize);
+    assert_eq!(mutex_trylock(mutex), -EBUSY);
+    semaphore_up(sem);
+    mutex_lock(mutex);
+    assert_eq!(mutex_close(mutex), -EBUSY);
+    mutex_unlock(mutex);
+    assert_eq!(mutex_close(mutex), 0);
+    assert_eq!(semaphore_close(sem), 0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let create = NamedFlags::CREATE | NamedFlags::EXCL;
+    assert_eq!(semaphore_open(SEM_NAME, NamedFlags::empty(), 0), -ENOENT);
+    assert_eq!(semaphore_open("\0", create, 0), -EINVAL);
+    assert_eq!(semaphore_unlink(SEM_NAME), -ENOENT);
+    let sem = semaphore_open(SEM_NAME, create, 0);
+    assert!(sem >= 0);
+    assert_eq!(semaphore_open(SEM_NAME, create, 0), -EEXIST);
+    // semaphores and mutexes do not share names
+    assert_eq!(mutex_open(SEM_NAME, NamedFlags::empty()), -ENOENT);
+    let mutex = mutex_open(MUTEX_NAME, create);
+    assert!(mutex >= 0);
+    let (sem, mutex) = (sem as usize, mutex as usize);
+
+    mutex_lock(mutex);
+    let pids: [isize; CHILDREN] = core::array::from_fn(|_| {
+        let pid = fork();
+        if pid == 0 {
+            child();
+        }
+        pid
+    });
+    for _ in 0..CHILDREN {
+        semaphore_down(sem);
+    }
+    // the names go, the objects stay with whoever opened them
+    assert_eq!(semaphore_unlink(SEM_NAME), 0);
+    assert_eq!(mutex_unlink(MUTEX_NAME), 0);
+    assert_eq!(mutex_open(MUTEX_NAME, NamedFlags::empty()), -ENOENT);
+    mutex_unlock(mutex);
+    for pid in pids {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(mutex_close(mutex), 0);
+    assert_eq!(semaphore_close(sem), 0);
+    assert_eq!(semaphore_close(sem), -EINVAL);
+
+    // a name used again is a new object
+    let mutex = mutex_open(MUTEX_NAME, create);
+    assert!(mutex >= 0);
+    assert_eq!(mutex_trylock(mutex as usize), 0);
+    mutex_unlock(mutex as usize);
+    assert_eq!(mutex_close(mutex as usize), 0);
+    assert_eq!(mutex_unlink(MUTEX_NAME), 0);
+
+    println!("named_sync_test passed!");
+    0
+}
@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    exit, fork, getrlimit, setrlimit, shm_map, shm_open, thread_create, waitpid, waittid, yield_,
+    RLimit, ShmFlags, RLIMIT_AS, RLIM_INFINITY,
+};
+
+const PAGE_SIZE: usize = 4096;
+const HOG_KEY_BASE: usize = 0x4f4f_4d00;
+const HOG_CHUNK: usize = 1024 * 1024;
+
+static LIMITED: AtomicBool = AtomicBool::new(false);
+
+fn wait_for(pid: isize) -> i32 {
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// Started before main lowers the limit, which binds it all the same.
+fn limited_thread(shm_id: usize) -> ! {
+    while !LIMITED.load(Ordering::SeqCst) {
+        yield_();
+    }
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_AS, &mut rlim), 0);
+    assert_eq!(rlim.rlim_cur, PAGE_SIZE);
+    assert_eq!(shm_map(shm_id), -1);
+    exit(0)
+}
+
+fn limit_test() {
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_AS, &mut rlim), 0);
+    assert_eq!(rlim.rlim_cur, RLIM_INFINITY);
+    assert_eq!(getrlimit(0, &mut rlim), -1);
+
+    // far below the current usage, nothing may grow any more, for no
+    // thread of the process
+    let shm_id = shm_open(HOG_KEY_BASE - 1, PAGE_SIZE, ShmFlags::CREATE);
+    assert!(shm_id >= 0);
+    let tid = thread_create(limited_thread as usize, shm_id as usize);
+    assert!(tid > 0);
+    let low = RLimit {
+        rlim_cur: PAGE_SIZE,
+        rlim_max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_AS, &low), 0);
+    LIMITED.store(true, Ordering::SeqCst);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(fork(), -1);
+    assert_eq!(thread_create(limited_thread as usize, shm_id as usize), -1);
+    assert_eq!(shm_map(shm_id as usize), -1);
+
+    // the soft limit can be raised up to the hard one
+    let unlimited = RLimit {
+        rlim_cur: RLIM_INFINITY,
+        rlim_max: RLIM_INFINITY,
+    };
+    assert_eq!(setrlimit(RLIMIT_AS, &unlimited), 0);
+    assert!(shm_map(shm_id as usize) > 0);
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert_eq!(wait_for(pid), 0);
+
+    // the hard limit can only be lowered
+    assert_eq!(setrlimit(RLIMIT_AS, &low), 0);
+    let raise = RLimit {
+        rlim_cur: PAGE_SIZE,
+        rlim_max: PAGE_SIZE * 2,
+    };
+    assert_eq!(setrlimit(RLIMIT_AS, &raise), -1);
+}
+
+/// Map ever more shared memory, the OOM killer must pick this process.
+fn hog() -> ! {
+    let mut i = 0;
+    loop {
+        let shm_id = shm_open(HOG_KEY_BASE + i, HOG_CHUNK, ShmFlags::CREATE);
+        if shm_id >= 0 {
+            shm_map(shm_id as usize);
+        }
+        i += 1;
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        limit_test();
+        exit(0);
+    }
+    assert_eq!(wait_for(pid), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        hog();
+    }
+    // killed by SIGKILL
+    assert_eq!(wait_for(pid), -9);
+
+    // all of its memory came back
+    let pid = fork();
+    if pid == 0 {
+        let shm_id = shm_open(HOG_KEY_BASE, HOG_CHUNK * 8, ShmFlags::CREATE);
+        assert!(shm_id >= 0);
+        assert!(shm_map(shm_id as usize) > 0);
+        exit(0);
+    }
+    assert_eq!(wait_for(pid), 0);
+    println!("oom_test passed!");
+    0
+}
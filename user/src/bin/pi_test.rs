@@ -0,0 +1,197 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    exit, get_priority, mutex_blocking_create, mutex_create, mutex_lock, mutex_trylock,
+    mutex_unlock, semaphore_create, semaphore_down, semaphore_up, set_priority, sleep,
+    thread_create, waittid, yield_, EBUSY, EPERM, PRIORITY_DEFAULT, PRIORITY_MAX,
+};
+
+const LOW: usize = 4;
+const MIDDLE: usize = 6;
+const MEDIUM: usize = 8;
+const HIGH: usize = 12;
+/// Twice the most harts the kernel runs on, so that they leave none to LOW.
+const MEDIUMS: usize = 16;
+
+static USE_MUTEX: AtomicBool = AtomicBool::new(false);
+static LOCK: AtomicUsize = AtomicUsize::new(0);
+static OUTER: AtomicUsize = AtomicUsize::new(0);
+static LOW_HOLDS: AtomicBool = AtomicBool::new(false);
+static HIGH_WAITS: AtomicBool = AtomicBool::new(false);
+static HIGH_DONE: AtomicBool = AtomicBool::new(false);
+static MIDDLE_HOLDS: AtomicBool = AtomicBool::new(false);
+static HIGH_GO: AtomicBool = AtomicBool::new(false);
+
+fn acquire(lock: usize) {
+    if USE_MUTEX.load(Ordering::SeqCst) {
+        mutex_lock(lock);
+    } else {
+        semaphore_down(lock);
+    }
+}
+
+fn release(lock: usize) {
+    if USE_MUTEX.load(Ordering::SeqCst) {
+        mutex_unlock(lock);
+    } else {
+        semaphore_up(lock);
+    }
+}
+
+fn low() -> ! {
+    assert_eq!(set_priority(LOW), LOW as isize);
+    let lock = LOCK.load(Ordering::SeqCst);
+    acquire(lock);
+    LOW_HOLDS.store(true, Ordering::SeqCst);
+    if USE_MUTEX.load(Ordering::SeqCst) {
+        // once HIGH waits, the mutex lends LOW its priority
+        while get_priority() != HIGH as isize {
+            yield_();
+        }
+    } else {
+        // a semaphore has no owner to boost, only aging gets LOW past the
+        // MEDIUMs
+        while !HIGH_WAITS.load(Ordering::SeqCst) {
+            yield_();
+        }
+        assert_eq!(get_priority(), LOW as isize);
+    }
+    release(lock);
+    // the boost ends with the unlock
+    assert_eq!(get_priority(), LOW as isize);
+    exit(0)
+}
+
+fn medium() -> ! {
+    set_priority(MEDIUM);
+    while !HIGH_DONE.load(Ordering::SeqCst) {}
+    exit(0)
+}
+
+fn high() -> ! {
+    set_priority(HIGH);
+    let lock = LOCK.load(Ordering::SeqCst);
+    HIGH_WAITS.store(true, Ordering::SeqCst);
+    acquire(lock);
+    HIGH_DONE.store(true, Ordering::SeqCst);
+    release(lock);
+    exit(0)
+}
+
+/// LOW holds the lock HIGH waits for while MEDIUMS busy threads, spinning
+/// until HIGH got it, keep every hart.
+fn invert(use_mutex: bool) {
+    USE_MUTEX.store(use_mutex, Ordering::SeqCst);
+    LOW_HOLDS.store(false, Ordering::SeqCst);
+    HIGH_WAITS.store(false, Ordering::SeqCst);
+    HIGH_DONE.store(false, Ordering::SeqCst);
+    let lock = if use_mutex {
+        mutex_blocking_create()
+    } else {
+        semaphore_create(1)
+    };
+    LOCK.store(lock as usize, Ordering::SeqCst);
+
+    let low_tid = thread_create(low as usize, 0) as usize;
+    while !LOW_HOLDS.load(Ordering::SeqCst) {
+        sleep(1);
+    }
+    // above them all, we are not kept from creating HIGH
+    let mediums: [usize; MEDIUMS] =
+        core::array::from_fn(|_| thread_create(medium as usize, 0) as usize);
+    let high_tid = thread_create(high as usize, 0) as usize;
+
+    for tid in mediums.iter().copied().chain([high_tid, low_tid]) {
+        assert_eq!(waittid(tid), 0);
+    }
+}
+
+/// Holds OUTER and waits for LOCK, which main holds.
+fn middle() -> ! {
+    set_priority(MIDDLE);
+    let outer = OUTER.load(Ordering::SeqCst);
+    mutex_lock(outer);
+    MIDDLE_HOLDS.store(true, Ordering::SeqCst);
+    let lock = LOCK.load(Ordering::SeqCst);
+    mutex_lock(lock);
+    mutex_unlock(lock);
+    mutex_unlock(outer);
+    exit(0)
+}
+
+fn high_outer() -> ! {
+    set_priority(HIGH);
+    while !HIGH_GO.load(Ordering::SeqCst) {
+        sleep(1);
+    }
+    let outer = OUTER.load(Ordering::SeqCst);
+    mutex_lock(outer);
+    mutex_unlock(outer);
+    exit(0)
+}
+
+/// Tries to unlock LOCK, which main holds, exits with 1 if it was refused.
+fn foreign_unlock() -> ! {
+    let lock = LOCK.load(Ordering::SeqCst);
+    let refused = mutex_unlock(lock) == -EPERM && mutex_trylock(lock) == -EBUSY;
+    exit(refused as i32)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(get_priority(), PRIORITY_DEFAULT as isize);
+    // no thread goes above the priority it started with
+    assert_eq!(set_priority(PRIORITY_DEFAULT + 1), -1);
+    assert_eq!(set_priority(PRIORITY_MAX + 1), -1);
+
+    invert(false);
+    invert(true);
+
+    // HIGH waits for OUTER, held by MIDDLE, which waits for LOCK held by
+    // us, the boost reaches the end of the chain. Both are created while
+    // we are at the default, lowered they could not go above LOW.
+    let lock = mutex_blocking_create() as usize;
+    let outer = mutex_blocking_create() as usize;
+    LOCK.store(lock, Ordering::SeqCst);
+    OUTER.store(outer, Ordering::SeqCst);
+    mutex_lock(lock);
+    let middle_tid = thread_create(middle as usize, 0) as usize;
+    let high_tid = thread_create(high_outer as usize, 0) as usize;
+    while !MIDDLE_HOLDS.load(Ordering::SeqCst) {
+        sleep(1);
+    }
+    assert_eq!(set_priority(LOW), LOW as isize);
+    while get_priority() != MIDDLE as isize {
+        sleep(1);
+    }
+    HIGH_GO.store(true, Ordering::SeqCst);
+    while get_priority() != HIGH as isize {
+        sleep(1);
+    }
+    mutex_unlock(lock);
+    assert_eq!(get_priority(), LOW as isize);
+    assert_eq!(waittid(middle_tid), 0);
+    assert_eq!(waittid(high_tid), 0);
+    // lowered, a thread may go back up to where it started
+    assert_eq!(set_priority(PRIORITY_DEFAULT), PRIORITY_DEFAULT as isize);
+
+    // only the owner unlocks, a mutex is not a semaphore
+    for lock in [mutex_blocking_create(), mutex_create()] {
+        let lock = lock as usize;
+        assert_eq!(mutex_unlock(lock), -EPERM);
+        LOCK.store(lock, Ordering::SeqCst);
+        mutex_lock(lock);
+        let tid = thread_create(foreign_unlock as usize, 0) as usize;
+        assert_eq!(waittid(tid), 1);
+        assert_eq!(mutex_unlock(lock), 0);
+        assert_eq!(mutex_unlock(lock), -EPERM);
+    }
+
+    println!("pi_test passed!");
+    0
+}
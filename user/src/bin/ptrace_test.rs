@@ -0,0 +1,150 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exec, exit, fork, getpid, kill, ptrace, ptrace_getregs, ptrace_peek, ptrace_setregs,
+    waitpid_traced, UserRegs, WaitStatus, EFAULT, EIO, ESRCH, PTRACE_CONT, PTRACE_GETREGS,
+    PTRACE_KILL, PTRACE_PEEKDATA, PTRACE_POKEDATA, PTRACE_SETREGS, PTRACE_SINGLESTEP,
+    PTRACE_SYSCALL, PTRACE_TRACEME, SIGTRAP_SYSCALL,
+};
+
+const SIGINT: i32 = 2;
+const SIGTRAP: i32 = 5;
+const SIGKILL: i32 = 9;
+const SIGSEGV: i32 = 11;
+const SYSCALL_WRITE: usize = 64;
+const C_EBREAK: usize = 0x9002;
+
+fn wait_stop(pid: usize) -> WaitStatus {
+    let mut status = WaitStatus::Exited(0);
+    assert_eq!(waitpid_traced(pid as isize, &mut status), pid as isize);
+    status
+}
+
+/// Fork a child that is traced by us and stops before running `f`.
+fn spawn_traced(f: fn() -> i32) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(ptrace(PTRACE_TRACEME, 0, 0, 0), 0);
+        kill(getpid() as usize, SIGTRAP);
+        exit(f());
+    }
+    let pid = pid as usize;
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGTRAP));
+    pid
+}
+
+#[inline(never)]
+fn add_one(x: usize) -> usize {
+    x + 1
+}
+
+/// Stops after exec, then at every syscall entry and exit.
+fn syscall_test() {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(ptrace(PTRACE_TRACEME, 0, 0, 0), 0);
+        exec("hello_world\0", &[core::ptr::null::<u8>()]);
+        exit(-1);
+    }
+    let pid = pid as usize;
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGTRAP));
+    let mut regs = UserRegs::default();
+    assert_eq!(ptrace_getregs(pid, &mut regs), 0);
+    assert!(ptrace_peek(pid, regs[0]).is_some());
+    assert!(ptrace_peek(pid, 0).is_none());
+    // bad tracee addresses are the tracee's problem, bad buffers ours
+    let mut word = 0usize;
+    let word_addr = &mut word as *mut usize as usize;
+    assert_eq!(ptrace(PTRACE_PEEKDATA, pid, 0, word_addr), -EIO);
+    assert_eq!(ptrace(PTRACE_PEEKDATA, pid, regs[0], 0), -EFAULT);
+    assert_eq!(
+        ptrace(PTRACE_PEEKDATA, pid, regs[0], add_one as usize),
+        -EFAULT
+    );
+    assert_eq!(ptrace(PTRACE_GETREGS, pid, 0, 0), -EFAULT);
+    assert_eq!(ptrace(PTRACE_SETREGS, pid, 0, 0), -EFAULT);
+    let mut entry = true;
+    let mut writes = 0;
+    loop {
+        assert_eq!(ptrace(PTRACE_SYSCALL, pid, 0, 0), 0);
+        match wait_stop(pid) {
+            WaitStatus::Stopped(SIGTRAP_SYSCALL) => {
+                assert_eq!(ptrace_getregs(pid, &mut regs), 0);
+                if entry && regs[17] == SYSCALL_WRITE {
+                    writes += 1;
+                }
+                entry = !entry;
+            }
+            WaitStatus::Exited(code) => {
+                assert_eq!(code, 0);
+                break;
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert!(writes > 0);
+}
+
+/// A breakpoint on `add_one`, a changed argument and a single step.
+fn breakpoint_test() {
+    let pid = spawn_traced(|| add_one(unsafe { core::ptr::read_volatile(&1) }) as i32);
+    let addr = add_one as usize;
+    let word = ptrace_peek(pid, addr).unwrap();
+    assert_eq!(
+        ptrace(PTRACE_POKEDATA, pid, addr, word & !0xffff | C_EBREAK),
+        0
+    );
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGTRAP));
+    let mut regs = UserRegs::default();
+    assert_eq!(ptrace_getregs(pid, &mut regs), 0);
+    assert_eq!(regs[0], addr);
+    regs[10] = 41;
+    assert_eq!(ptrace_setregs(pid, &regs), 0);
+    assert_eq!(ptrace(PTRACE_POKEDATA, pid, addr, word), 0);
+    assert_eq!(ptrace(PTRACE_SINGLESTEP, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGTRAP));
+    assert_eq!(ptrace_getregs(pid, &mut regs), 0);
+    assert_ne!(regs[0], addr);
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Exited(42));
+}
+
+/// The tracer decides what becomes of fatal signals.
+fn signal_test() {
+    let pid = spawn_traced(|| {
+        kill(getpid() as usize, SIGINT);
+        7
+    });
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGINT));
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Exited(7));
+
+    let pid = spawn_traced(|| {
+        unsafe {
+            core::ptr::null_mut::<u8>().write_volatile(0);
+        }
+        0
+    });
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Stopped(SIGSEGV));
+    assert_eq!(ptrace(PTRACE_KILL, pid, 0, 0), 0);
+    assert_eq!(wait_stop(pid), WaitStatus::Signaled(SIGKILL));
+
+    // only stopped tracees of ours take requests
+    assert_eq!(ptrace(PTRACE_CONT, 1, 0, 0), -ESRCH);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    syscall_test();
+    breakpoint_test();
+    signal_test();
+    println!("ptrace_test passed!");
+    0
+}
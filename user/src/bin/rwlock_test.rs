@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    exit, rwlock_create, rwlock_downgrade, rwlock_read, rwlock_unlock, rwlock_upgrade,
+    rwlock_write, sleep, thread_create, waittid, yield_, EDEADLK, EPERM,
+};
+
+const WRITERS: usize = 8;
+const PER_WRITER: usize = 100;
+/// Long enough for a thread that is not blocked to get through.
+const SETTLE_MS: usize = 20;
+
+static RWLOCK: AtomicUsize = AtomicUsize::new(0);
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static SEQ: AtomicUsize = AtomicUsize::new(0);
+static WRITER_SEQ: AtomicUsize = AtomicUsize::new(0);
+static READER_SEQ: AtomicUsize = AtomicUsize::new(0);
+static UPGRADER_READS: AtomicBool = AtomicBool::new(false);
+
+fn rwlock() -> usize {
+    RWLOCK.load(Ordering::SeqCst)
+}
+
+fn reader() -> ! {
+    rwlock_read(rwlock());
+    READER_SEQ.store(SEQ.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+    exit(0)
+}
+
+fn writer() -> ! {
+    rwlock_write(rwlock());
+    WRITER_SEQ.store(SEQ.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+    exit(0)
+}
+
+/// A lost update shows if two writers ever hold the lock together.
+fn incrementer() -> ! {
+    for _ in 0..PER_WRITER {
+        rwlock_write(rwlock());
+        let value = COUNTER.load(Ordering::SeqCst);
+        yield_();
+        COUNTER.store(value + 1, Ordering::SeqCst);
+        assert_eq!(rwlock_unlock(rwlock()), 0);
+    }
+    exit(0)
+}
+
+fn upgrader() -> ! {
+    rwlock_read(rwlock());
+    UPGRADER_READS.store(true, Ordering::SeqCst);
+    assert_eq!(rwlock_upgrade(rwlock()), 0);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    RWLOCK.store(rwlock_create() as usize, Ordering::SeqCst);
+    assert_eq!(rwlock_unlock(rwlock()), -EPERM);
+    assert_eq!(rwlock_upgrade(rwlock()), -EPERM);
+    assert_eq!(rwlock_downgrade(rwlock()), -EPERM);
+
+    // readers share the lock
+    rwlock_read(rwlock());
+    assert_eq!(waittid(thread_create(reader as usize, 0) as usize), 0);
+
+    // a waiting writer goes before readers that come after it
+    let writer_tid = thread_create(writer as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    let reader_tid = thread_create(reader as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    let before = SEQ.load(Ordering::SeqCst);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+    assert_eq!(waittid(writer_tid), 0);
+    assert_eq!(waittid(reader_tid), 0);
+    assert_eq!(WRITER_SEQ.load(Ordering::SeqCst), before);
+    assert_eq!(READER_SEQ.load(Ordering::SeqCst), before + 1);
+
+    // the only reader upgrades at once, readers wait until it downgrades
+    rwlock_read(rwlock());
+    assert_eq!(rwlock_downgrade(rwlock()), -EPERM);
+    assert_eq!(rwlock_upgrade(rwlock()), 0);
+    let reader_tid = thread_create(reader as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    let before = SEQ.load(Ordering::SeqCst);
+    assert_eq!(rwlock_downgrade(rwlock()), 0);
+    assert_eq!(waittid(reader_tid), 0);
+    assert_eq!(READER_SEQ.load(Ordering::SeqCst), before);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+
+    // two readers upgrading would wait for each other forever
+    rwlock_read(rwlock());
+    let upgrader_tid = thread_create(upgrader as usize, 0) as usize;
+    while !UPGRADER_READS.load(Ordering::SeqCst) {
+        sleep(1);
+    }
+    sleep(SETTLE_MS);
+    assert_eq!(rwlock_upgrade(rwlock()), -EDEADLK);
+    assert_eq!(rwlock_unlock(rwlock()), 0);
+    assert_eq!(waittid(upgrader_tid), 0);
+
+    // writers exclude each other
+    let tids: [usize; WRITERS] =
+        core::array::from_fn(|_| thread_create(incrementer as usize, 0) as usize);
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(COUNTER.load(Ordering::SeqCst), WRITERS * PER_WRITER);
+    assert_eq!(rwlock_unlock(rwlock()), -EPERM);
+
+    println!("rwlock_test passed!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exec, fork, open, waitpid, write, OpenFlags};
+
+fn write_script(path: &str, text: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, text.as_bytes());
+    close(fd as usize);
+}
+
+fn run(path: &str, arg: &str) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exec(path, &[path.as_ptr(), arg.as_ptr(), core::ptr::null()]);
+        panic!("exec {} failed", path);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // run by the kernel as the interpreter of `args_script`
+    if argc > 1 {
+        assert_eq!(argv, ["/shebang_test", "-x  y", "args_script", "extra"]);
+        return 7;
+    }
+    // argv[0] is dropped, the one argument keeps its inner blanks
+    write_script("args_script\0", "#!/shebang_test   -x  y \nignored\n");
+    assert_eq!(run("args_script\0", "extra\0"), 7);
+
+    // a script may name a script as its interpreter, but not endlessly
+    write_script("loop_script\0", "#!loop_script\n");
+    assert_eq!(exec("loop_script\0", &[core::ptr::null()]), -1);
+    write_script("no_interp\0", "#!missing_interpreter\n");
+    assert_eq!(exec("no_interp\0", &[core::ptr::null()]), -1);
+
+    // a shell script, run line by line
+    write_script(
+        "shell_script\0",
+        "#!/user_shell\n# comment\n\nexit\nhello_world\n",
+    );
+    assert_eq!(run("shell_script\0", "\0"), 0);
+    println!("shebang_test passed!");
+    0
+}
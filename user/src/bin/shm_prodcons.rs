@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, semaphore_create, semaphore_down, semaphore_up, shm_close, shm_map,
+    shm_open, shm_unmap, sync_export, sync_import, waitpid, ShmFlags, SYNC_SEMAPHORE,
+};
+
+const SHM_KEY: usize = 0x5348_4d;
+const BUFFER_SIZE: usize = 8;
+const ITEMS: usize = 1000;
+const MAGIC: usize = 0xdead_beef;
+
+#[repr(C)]
+struct Ring {
+    magic: usize,
+    head: usize,
+    tail: usize,
+    buffer: [usize; BUFFER_SIZE],
+}
+
+/// An fd for the semaphore `id`, which a child inherits and imports.
+fn export(id: usize) -> usize {
+    let fd = sync_export(SYNC_SEMAPHORE, id);
+    assert!(fd >= 0);
+    fd as usize
+}
+
+fn import(fd: usize) -> usize {
+    let id = sync_import(fd);
+    assert!(id >= 0);
+    id as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mutex = semaphore_create(1) as usize;
+    let empty = semaphore_create(BUFFER_SIZE) as usize;
+    let full = semaphore_create(0) as usize;
+    let fds = [export(mutex), export(empty), export(full)];
+
+    let shm_id = shm_open(SHM_KEY, core::mem::size_of::<Ring>(), ShmFlags::CREATE);
+    assert!(shm_id >= 0);
+    assert_eq!(
+        shm_open(SHM_KEY, 4096, ShmFlags::CREATE | ShmFlags::EXCL),
+        -1
+    );
+    let addr = shm_map(shm_id as usize);
+    assert!(addr > 0);
+    let ring = unsafe { &mut *(addr as *mut Ring) };
+    ring.magic = MAGIC;
+
+    let producer = fork();
+    if producer == 0 {
+        // the semaphores only through their fds, the mapping is inherited
+        // and refers to the same frames
+        let [mutex, empty, full] = fds.map(import);
+        for i in 1..=ITEMS {
+            semaphore_down(empty);
+            semaphore_down(mutex);
+            unsafe {
+                core::ptr::write_volatile(&mut ring.buffer[ring.tail], i);
+            }
+            ring.tail = (ring.tail + 1) % BUFFER_SIZE;
+            semaphore_up(mutex);
+            semaphore_up(full);
+        }
+        exit(0);
+    }
+
+    let mut sum = 0;
+    for _ in 0..ITEMS {
+        semaphore_down(full);
+        semaphore_down(mutex);
+        sum += unsafe { core::ptr::read_volatile(&ring.buffer[ring.head]) };
+        ring.head = (ring.head + 1) % BUFFER_SIZE;
+        semaphore_up(mutex);
+        semaphore_up(empty);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(producer as usize, &mut exit_code), producer);
+    assert_eq!(exit_code, 0);
+    assert_eq!(sum, ITEMS * (ITEMS + 1) / 2);
+    for fd in fds {
+        close(fd);
+    }
+
+    let reader = fork();
+    if reader == 0 {
+        // open by key and map it a second time
+        let id = shm_open(SHM_KEY, 0, ShmFlags::empty());
+        assert!(id >= 0);
+        let other = shm_map(id as usize);
+        assert!(other > 0 && other != addr);
+        let magic = unsafe { core::ptr::read_volatile(other as *const usize) };
+        exit(if magic == MAGIC { 0 } else { 1 });
+    }
+    assert_eq!(waitpid(reader as usize, &mut exit_code), reader);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(shm_unmap(addr as usize), 0);
+    assert_eq!(shm_unmap(addr as usize), -1);
+    assert_eq!(shm_close(shm_id as usize), 0);
+    assert_eq!(shm_close(shm_id as usize), -1);
+    // the last reference is gone, so is the key
+    assert_eq!(shm_open(SHM_KEY, 0, ShmFlags::empty()), -1);
+    println!("shm_prodcons passed!");
+    0
+}
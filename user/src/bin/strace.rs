@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{exec, exit, fork, strace, strace_read, waitpid_nb, write, yield_};
+
+/// Print the trace lines the kernel logged since `seq`.
+fn drain(seq: &mut usize) {
+    let mut buf = [0u8; 512];
+    loop {
+        let len = strace_read(seq, &mut buf);
+        if len <= 0 {
+            return;
+        }
+        write(1, &buf[..len as usize]);
+    }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: strace <program> [args...]");
+        return -1;
+    }
+    let args: Vec<String> = argv[1..].iter().map(|arg| format!("{}\0", arg)).collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null());
+    // only what the program logs from now on
+    let mut seq = usize::MAX;
+    strace_read(&mut seq, &mut []);
+    let pid = fork();
+    if pid == 0 {
+        strace(0, true);
+        exec(args[0].as_str(), &args_addr);
+        println!("strace: cannot run {}", argv[1]);
+        exit(-1);
+    }
+    let mut exit_code = 0;
+    loop {
+        // drained after the exit is seen, so the last calls are not lost
+        let exited = waitpid_nb(pid as usize, &mut exit_code) == pid;
+        drain(&mut seq);
+        if exited {
+            println!("+++ exited with {} +++", exit_code);
+            return 0;
+        }
+        yield_();
+    }
+}
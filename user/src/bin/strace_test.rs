@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{exit, fork, getpid, strace, strace_read, waitpid, write};
+
+/// Run `f` traced in a child and return its pid and trace.
+fn traced(f: fn() -> i32) -> (usize, String) {
+    let mut seq = usize::MAX;
+    strace_read(&mut seq, &mut []);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(strace(0, true), 0);
+        exit(f());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let mut log = String::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = strace_read(&mut seq, &mut buf);
+        if len == 0 {
+            break;
+        }
+        log.push_str(core::str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    (pid as usize, log)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (pid, log) = traced(|| {
+        getpid();
+        write(1, b"traced\n");
+        3
+    });
+    assert!(log.contains(&format!("[{}] getpid() = {} <", pid, pid)));
+    assert!(log.contains(r#"write(1, "traced\n", 7) = 7 <"#));
+    assert!(log.contains(&format!("[{}] exit(3) = ?", pid)));
+    // only the child is traced
+    assert!(!log.contains(&format!("[{}] ", getpid())));
+
+    // turned off, the call that does it is the last one logged
+    let (pid, log) = traced(|| {
+        strace(0, false);
+        getpid();
+        0
+    });
+    assert!(log.contains(&format!("[{}] strace(0, 0) = 0 <", pid)));
+    assert!(!log.contains("getpid"));
+
+    // a reader that falls behind is told how much it missed
+    let (_, log) = traced(|| {
+        for _ in 0..1000 {
+            getpid();
+        }
+        0
+    });
+    assert!(log.starts_with('('));
+    assert!(log.lines().next().unwrap().ends_with("lines lost)"));
+
+    assert_eq!(strace(usize::MAX, true), -1);
+    println!("strace_test passed!");
+    0
+}
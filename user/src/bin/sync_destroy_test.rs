@@ -0,0 +1,144 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    barrier_create, barrier_destroy, barrier_wait, close, condvar_create, condvar_destroy,
+    condvar_signal, condvar_wait, exit, fork, mutex_blocking_create, mutex_destroy, mutex_lock,
+    mutex_trylock, mutex_unlock, rwlock_create, rwlock_destroy, rwlock_read, rwlock_unlock,
+    semaphore_create, semaphore_destroy, semaphore_down, semaphore_up, sleep, sync_export,
+    sync_import, thread_create, waitpid, waittid, EBADF, EBUSY, EINVAL, SYNC_BARRIER, SYNC_MUTEX,
+};
+
+/// Long enough for a started thread to block.
+const SETTLE_MS: usize = 20;
+
+static SEM: AtomicUsize = AtomicUsize::new(0);
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+static BARRIER: AtomicUsize = AtomicUsize::new(0);
+
+fn create() -> ! {
+    SEM.store(semaphore_create(1) as usize, Ordering::SeqCst);
+    exit(0)
+}
+
+fn down() -> ! {
+    semaphore_down(SEM.load(Ordering::SeqCst));
+    exit(0)
+}
+
+fn wait() -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    condvar_wait(CONDVAR.load(Ordering::SeqCst), mutex);
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn arrive() -> ! {
+    barrier_wait(BARRIER.load(Ordering::SeqCst));
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // destroyed ids are reused, so creating and destroying does not leak
+    let mutex = mutex_blocking_create();
+    assert_eq!(mutex_destroy(mutex as usize), 0);
+    assert_eq!(mutex_destroy(mutex as usize), -EINVAL);
+    assert_eq!(mutex_lock(mutex as usize), -EINVAL);
+    for _ in 0..1000 {
+        let first = semaphore_create(1);
+        let second = semaphore_create(1);
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(semaphore_destroy(second as usize), 0);
+        assert_eq!(semaphore_destroy(first as usize), 0);
+    }
+    assert_eq!(mutex_blocking_create(), mutex);
+
+    // the threads share the ids, one's object is the others' to destroy
+    let tid = thread_create(create as usize, 0) as usize;
+    assert_eq!(waittid(tid), 0);
+    let sem = SEM.load(Ordering::SeqCst);
+    assert_eq!(semaphore_down(sem), 0);
+    semaphore_up(sem);
+    assert_eq!(semaphore_destroy(sem), 0);
+    assert_eq!(semaphore_down(sem), -EINVAL);
+
+    // nothing in use can be destroyed
+    let mutex = mutex as usize;
+    MUTEX.store(mutex, Ordering::SeqCst);
+    mutex_lock(mutex);
+    assert_eq!(mutex_destroy(mutex), -EBUSY);
+    mutex_unlock(mutex);
+
+    let sem = semaphore_create(0) as usize;
+    SEM.store(sem, Ordering::SeqCst);
+    let tid = thread_create(down as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    assert_eq!(semaphore_destroy(sem), -EBUSY);
+    semaphore_up(sem);
+    assert_eq!(waittid(tid), 0);
+    assert_eq!(semaphore_destroy(sem), 0);
+
+    let condvar = condvar_create() as usize;
+    CONDVAR.store(condvar, Ordering::SeqCst);
+    let tid = thread_create(wait as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    assert_eq!(condvar_destroy(condvar), -EBUSY);
+    mutex_lock(mutex);
+    condvar_signal(condvar);
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid), 0);
+    assert_eq!(condvar_destroy(condvar), 0);
+
+    let rwlock = rwlock_create() as usize;
+    rwlock_read(rwlock);
+    assert_eq!(rwlock_destroy(rwlock), -EBUSY);
+    rwlock_unlock(rwlock);
+    assert_eq!(rwlock_destroy(rwlock), 0);
+
+    let barrier = barrier_create(2) as usize;
+    BARRIER.store(barrier, Ordering::SeqCst);
+    let tid = thread_create(arrive as usize, 0) as usize;
+    sleep(SETTLE_MS);
+    assert_eq!(barrier_destroy(barrier), -EBUSY);
+    barrier_wait(barrier);
+    assert_eq!(waittid(tid), 0);
+    assert_eq!(barrier_destroy(barrier), 0);
+
+    // a child reaches our mutex through an inherited fd
+    assert_eq!(sync_export(99, mutex), -EINVAL);
+    assert_eq!(sync_export(SYNC_BARRIER, barrier), -EINVAL);
+    assert_eq!(sync_import(99), -EBADF);
+    assert_eq!(sync_import(1), -EINVAL);
+    let fd = sync_export(SYNC_MUTEX, mutex);
+    assert!(fd >= 0);
+    mutex_lock(mutex);
+    let pid = fork();
+    if pid == 0 {
+        let id = sync_import(fd as usize);
+        assert!(id >= 0);
+        let id = id as usize;
+        assert_eq!(mutex_trylock(id), -EBUSY);
+        mutex_lock(id);
+        mutex_unlock(id);
+        assert_eq!(mutex_destroy(id), 0);
+        close(fd as usize);
+        exit(0);
+    }
+    sleep(SETTLE_MS);
+    mutex_unlock(mutex);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(mutex_destroy(mutex), 0);
+    close(fd as usize);
+
+    println!("sync_destroy_test passed!");
+    0
+}
@@ -0,0 +1,261 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::println_empty_string)]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::console::getchar;
+use user_lib::{
+    exec, exit, fork, ptrace, ptrace_getregs, ptrace_peek, waitpid_traced, UserRegs, WaitStatus,
+    PTRACE_CONT, PTRACE_KILL, PTRACE_POKEDATA, PTRACE_SINGLESTEP, PTRACE_TRACEME,
+};
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+const SIGTRAP: i32 = 5;
+const C_EBREAK: usize = 0x9002;
+const REG_NAMES: [&str; 32] = [
+    "pc", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5",
+    "t6",
+];
+const HELP: &str = "b <addr>  set a breakpoint
+d <addr>  delete a breakpoint
+c         continue
+s         step one instruction
+r         show the registers
+x <addr>  show the word at addr
+q         kill the program and quit
+addresses are hex";
+
+/// A tiny debugger: breakpoints are c.ebreak written over the first
+/// halfword of an instruction, the original halfwords are kept here.
+struct Debugger {
+    pid: usize,
+    breakpoints: BTreeMap<usize, usize>,
+    /// the signal the program stopped with, delivered when it resumes
+    signal: usize,
+}
+
+impl Debugger {
+    fn pc(&self) -> usize {
+        let mut regs = UserRegs::default();
+        ptrace_getregs(self.pid, &mut regs);
+        regs[0]
+    }
+
+    fn write_half(&self, addr: usize, half: usize) -> bool {
+        match ptrace_peek(self.pid, addr) {
+            Some(word) => ptrace(PTRACE_POKEDATA, self.pid, addr, word & !0xffff | half) == 0,
+            None => false,
+        }
+    }
+
+    fn set_breakpoint(&mut self, addr: usize) {
+        if self.breakpoints.contains_key(&addr) {
+            return;
+        }
+        match ptrace_peek(self.pid, addr) {
+            Some(word) if self.write_half(addr, C_EBREAK) => {
+                self.breakpoints.insert(addr, word & 0xffff);
+                println!("breakpoint at {:#x}", addr);
+            }
+            _ => println!("cannot access {:#x}", addr),
+        }
+    }
+
+    fn delete_breakpoint(&mut self, addr: usize) {
+        match self.breakpoints.remove(&addr) {
+            Some(half) => {
+                self.write_half(addr, half);
+            }
+            None => println!("no breakpoint at {:#x}", addr),
+        }
+    }
+
+    /// Resume with `request`, false once the program is gone.
+    fn resume(&mut self, request: usize) -> bool {
+        // step over a breakpoint we are stopped at with the original code
+        let pc = self.pc();
+        if let Some(&half) = self.breakpoints.get(&pc) {
+            self.write_half(pc, half);
+            ptrace(PTRACE_SINGLESTEP, self.pid, 0, 0);
+            let alive = self.wait();
+            if alive {
+                self.write_half(pc, C_EBREAK);
+            }
+            if !alive || request == PTRACE_SINGLESTEP {
+                return alive;
+            }
+        }
+        ptrace(request, self.pid, 0, self.signal);
+        self.wait()
+    }
+
+    fn wait(&mut self) -> bool {
+        let mut status = WaitStatus::Exited(0);
+        waitpid_traced(self.pid as isize, &mut status);
+        self.signal = 0;
+        match status {
+            WaitStatus::Stopped(SIGTRAP) => {
+                let pc = self.pc();
+                if self.breakpoints.contains_key(&pc) {
+                    println!("breakpoint hit at {:#x}", pc);
+                } else {
+                    println!("stopped at {:#x}", pc);
+                }
+                true
+            }
+            WaitStatus::Stopped(sig) => {
+                println!("signal {} at {:#x}", sig, self.pc());
+                self.signal = sig as usize;
+                true
+            }
+            WaitStatus::Exited(code) => {
+                println!("program exited with {}", code);
+                false
+            }
+            WaitStatus::Signaled(sig) => {
+                println!("program killed by signal {}", sig);
+                false
+            }
+        }
+    }
+
+    fn show_registers(&self) {
+        let mut regs = UserRegs::default();
+        ptrace_getregs(self.pid, &mut regs);
+        for (i, name) in REG_NAMES.iter().enumerate() {
+            print!("{:>4} {:#018x}", name, regs[i]);
+            if i % 4 == 3 {
+                println!("");
+            } else {
+                print!("  ");
+            }
+        }
+    }
+
+    fn show_word(&self, addr: usize) {
+        match ptrace_peek(self.pid, addr) {
+            Some(word) => {
+                // show the program's code, not our breakpoints
+                let word = match self.breakpoints.get(&addr) {
+                    Some(&half) => word & !0xffff | half,
+                    None => word,
+                };
+                println!("{:#x}: {:#018x}", addr, word)
+            }
+            None => println!("cannot access {:#x}", addr),
+        }
+    }
+}
+
+fn read_line() -> String {
+    let mut line = String::new();
+    loop {
+        let c = getchar();
+        match c {
+            LF | CR => {
+                println!("");
+                return line;
+            }
+            BS | DL => {
+                if !line.is_empty() {
+                    print!("{} {}", BS as char, BS as char);
+                    line.pop();
+                }
+            }
+            _ => {
+                print!("{}", c as char);
+                line.push(c as char);
+            }
+        }
+    }
+}
+
+fn parse_addr(arg: Option<&str>) -> Option<usize> {
+    let arg = arg?;
+    usize::from_str_radix(arg.trim_start_matches("0x"), 16).ok()
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: tdb <program> [args...]");
+        return -1;
+    }
+    let args: Vec<String> = argv[1..].iter().map(|arg| format!("{}\0", arg)).collect();
+    let mut args_addr: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    args_addr.push(core::ptr::null());
+    let pid = fork();
+    if pid == 0 {
+        ptrace(PTRACE_TRACEME, 0, 0, 0);
+        exec(args[0].as_str(), &args_addr);
+        println!("tdb: cannot run {}", argv[1]);
+        exit(-1);
+    }
+    let mut debugger = Debugger {
+        pid: pid as usize,
+        breakpoints: BTreeMap::new(),
+        signal: 0,
+    };
+    // the first stop is right after exec, at the entry point
+    if !debugger.wait() {
+        return -1;
+    }
+    println!("type h for help");
+    loop {
+        print!("(tdb) ");
+        let line = read_line();
+        let mut words = line.split_whitespace();
+        let alive = match words.next() {
+            Some("b") => {
+                match parse_addr(words.next()) {
+                    Some(addr) => debugger.set_breakpoint(addr),
+                    None => println!("b <addr>"),
+                }
+                true
+            }
+            Some("d") => {
+                match parse_addr(words.next()) {
+                    Some(addr) => debugger.delete_breakpoint(addr),
+                    None => println!("d <addr>"),
+                }
+                true
+            }
+            Some("c") => debugger.resume(PTRACE_CONT),
+            Some("s") => debugger.resume(PTRACE_SINGLESTEP),
+            Some("r") => {
+                debugger.show_registers();
+                true
+            }
+            Some("x") => {
+                match parse_addr(words.next()) {
+                    Some(addr) => debugger.show_word(addr),
+                    None => println!("x <addr>"),
+                }
+                true
+            }
+            Some("q") => {
+                ptrace(PTRACE_KILL, debugger.pid, 0, 0);
+                debugger.wait()
+            }
+            Some(_) => {
+                println!("{}", HELP);
+                true
+            }
+            None => true,
+        };
+        if !alive {
+            return 0;
+        }
+    }
+}
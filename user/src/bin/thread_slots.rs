@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{exit, shm_close, shm_map, shm_open, shm_unmap, thread_create, waittid, ShmFlags};
+
+/// The kernel's MAX_THREADS, the main thread holds one of them.
+const MAX_THREADS: usize = 256;
+const SHM_KEY: usize = 0x5453_4c54;
+
+static MAPPED: AtomicUsize = AtomicUsize::new(0);
+
+pub fn thread_exit_with_arg(arg: usize) -> ! {
+    exit(arg as i32)
+}
+
+fn map_shm(shm_id: usize) -> ! {
+    let addr = shm_map(shm_id);
+    assert!(addr > 0);
+    unsafe {
+        *(addr as *mut usize) = 42;
+    }
+    MAPPED.store(addr as usize, Ordering::SeqCst);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // joined threads give their slot back, so this never runs out
+    for i in 0..2 * MAX_THREADS {
+        let tid = thread_create(thread_exit_with_arg as usize, i % 100);
+        assert!(tid > 0);
+        assert_eq!(waittid(tid as usize), (i % 100) as isize);
+        // a thread can be joined only once
+        assert_eq!(waittid(tid as usize), -1);
+    }
+    println!("sequential threads passed");
+
+    // exited threads keep their slot until they are joined
+    let mut tids = Vec::new();
+    loop {
+        let tid = thread_create(thread_exit_with_arg as usize, 7);
+        if tid < 0 {
+            break;
+        }
+        tids.push(tid as usize);
+    }
+    assert_eq!(tids.len(), MAX_THREADS - 1);
+    for tid in tids.iter() {
+        assert_eq!(waittid(*tid), 7);
+    }
+    let tid = thread_create(thread_exit_with_arg as usize, 8);
+    assert!(tid > 0);
+    assert_eq!(waittid(tid as usize), 8);
+
+    // a mapping made by one thread is seen by the others and not mapped
+    // over by their next one
+    let shm_id = shm_open(SHM_KEY, 4096, ShmFlags::CREATE);
+    assert!(shm_id >= 0);
+    let tid = thread_create(map_shm as usize, shm_id as usize);
+    assert_eq!(waittid(tid as usize), 0);
+    let theirs = MAPPED.load(Ordering::SeqCst);
+    let ours = shm_map(shm_id as usize);
+    assert!(ours > 0 && ours as usize != theirs);
+    assert_eq!(unsafe { *(ours as *const usize) }, 42);
+    assert_eq!(shm_unmap(theirs), 0);
+    assert_eq!(shm_unmap(ours as usize), 0);
+    assert_eq!(shm_close(shm_id as usize), 0);
+    println!("thread_slots passed!");
+    0
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait_timeout, exit, get_time, mutex_blocking_create,
+    mutex_create, mutex_lock, mutex_lock_timeout, mutex_trylock, mutex_unlock, semaphore_create,
+    semaphore_down_timeout, semaphore_trydown, semaphore_up, sleep, thread_create, waittid, EAGAIN,
+    EBUSY, ETIMEDOUT,
+};
+
+const TIMEOUT: usize = 30;
+
+static MUTEX: AtomicUsize = AtomicUsize::new(0);
+static CONDVAR: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns how long `f` took, in milliseconds.
+fn timed(f: impl FnOnce() -> isize, expected: isize) -> usize {
+    let start = get_time() as usize;
+    assert_eq!(f(), expected);
+    get_time() as usize - start
+}
+
+/// Waits for MUTEX, which main unlocks long before the timeout.
+fn lock_in_time() -> ! {
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    assert_eq!(mutex_lock_timeout(mutex, 5000), 0);
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+fn signal_in_time() -> ! {
+    sleep(TIMEOUT / 2);
+    let mutex = MUTEX.load(Ordering::SeqCst);
+    mutex_lock(mutex);
+    condvar_signal(CONDVAR.load(Ordering::SeqCst));
+    mutex_unlock(mutex);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // mutexes held by ourselves never become free
+    for mutex in [mutex_blocking_create(), mutex_create()] {
+        let mutex = mutex as usize;
+        assert_eq!(mutex_trylock(mutex), 0);
+        assert_eq!(mutex_trylock(mutex), -EBUSY);
+        assert!(timed(|| mutex_lock_timeout(mutex, TIMEOUT), -ETIMEDOUT) >= TIMEOUT);
+        mutex_unlock(mutex);
+        assert_eq!(mutex_lock_timeout(mutex, TIMEOUT), 0);
+        mutex_unlock(mutex);
+    }
+
+    // a released mutex wakes the waiter before its timer does
+    let mutex = mutex_blocking_create() as usize;
+    MUTEX.store(mutex, Ordering::SeqCst);
+    mutex_lock(mutex);
+    let tid = thread_create(lock_in_time as usize, 0) as usize;
+    sleep(TIMEOUT);
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid), 0);
+
+    // a timed out down gives back the unit it was waiting for
+    let sem = semaphore_create(0) as usize;
+    assert_eq!(semaphore_trydown(sem), -EAGAIN);
+    assert!(timed(|| semaphore_down_timeout(sem, TIMEOUT), -ETIMEDOUT) >= TIMEOUT);
+    semaphore_up(sem);
+    assert_eq!(semaphore_trydown(sem), 0);
+
+    // the mutex is held again after a condvar wait, timed out or not
+    let condvar = condvar_create() as usize;
+    CONDVAR.store(condvar, Ordering::SeqCst);
+    mutex_lock(mutex);
+    assert!(timed(|| condvar_wait_timeout(condvar, mutex, TIMEOUT), -ETIMEDOUT) >= TIMEOUT);
+    assert_eq!(mutex_trylock(mutex), -EBUSY);
+    let tid = thread_create(signal_in_time as usize, 0) as usize;
+    assert!(timed(|| condvar_wait_timeout(condvar, mutex, 5000), 0) < 5000);
+    assert_eq!(mutex_trylock(mutex), -EBUSY);
+    mutex_unlock(mutex);
+    assert_eq!(waittid(tid), 0);
+
+    println!("timeout_test passed!");
+    0
+}
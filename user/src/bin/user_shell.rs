@@ -16,7 +16,7 @@ const LINE_START: &str = ">> ";
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{close, dup, env, exec, exit, fork, open, pipe, read, waitpid, OpenFlags};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -74,8 +74,182 @@ impl ProcessArguments {
     }
 }
 
+/// `export` lists the environment, `export KEY=VALUE` sets a variable
+/// that every later command inherits.
+fn export(args: &[&str]) {
+    if args.is_empty() {
+        for (key, value) in env::vars() {
+            println!("{}={}", key, value);
+        }
+    }
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if !key.is_empty() => env::set_var(key, value),
+            _ => println!("export: invalid assignment {}", arg),
+        }
+    }
+}
+
+/// Commands that change the shell itself, returns false for any other.
+fn run_builtin(line: &str) -> bool {
+    let words: Vec<_> = line.split(' ').filter(|word| !word.is_empty()).collect();
+    match words.first() {
+        Some(&"export") => export(&words[1..]),
+        _ => return false,
+    }
+    true
+}
+
+/// Run a command line, a pipeline of programs, and return the exit code
+/// of its last program, -1 if it could not be started.
+fn run_line(line: &str) -> i32 {
+    let splited: Vec<_> = line.split('|').collect();
+    let process_arguments_list: Vec<_> = splited
+        .iter()
+        .map(|&cmd| ProcessArguments::new(cmd))
+        .collect();
+    let mut valid = true;
+    for (i, process_args) in process_arguments_list.iter().enumerate() {
+        if i == 0 {
+            if !process_args.output.is_empty() {
+                valid = false;
+            }
+        } else if i == process_arguments_list.len() - 1 {
+            if !process_args.input.is_empty() {
+                valid = false;
+            }
+        } else if !process_args.output.is_empty() || !process_args.input.is_empty() {
+            valid = false;
+        }
+    }
+    if process_arguments_list.len() == 1 {
+        valid = true;
+    }
+    let mut exit_code: i32 = -1;
+    if !valid {
+        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
+    } else {
+        // create pipes
+        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+        if !process_arguments_list.is_empty() {
+            for _ in 0..process_arguments_list.len() - 1 {
+                let mut pipe_fd = [0usize; 2];
+                pipe(&mut pipe_fd);
+                pipes_fd.push(pipe_fd);
+            }
+        }
+        let mut children: Vec<_> = Vec::new();
+        for (i, process_argument) in process_arguments_list.iter().enumerate() {
+            let pid = fork();
+            if pid == 0 {
+                let input = &process_argument.input;
+                let output = &process_argument.output;
+                let args_copy = &process_argument.args_copy;
+                let args_addr = &process_argument.args_addr;
+                // redirect input
+                if !input.is_empty() {
+                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
+                    if input_fd == -1 {
+                        println!("Error when opening file {}", input);
+                        exit(-4);
+                    }
+                    let input_fd = input_fd as usize;
+                    close(0);
+                    assert_eq!(dup(input_fd), 0);
+                    close(input_fd);
+                }
+                // redirect output
+                if !output.is_empty() {
+                    let output_fd = open(output.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+                    if output_fd == -1 {
+                        println!("Error when opening file {}", output);
+                        exit(-4);
+                    }
+                    let output_fd = output_fd as usize;
+                    close(1);
+                    assert_eq!(dup(output_fd), 1);
+                    close(output_fd);
+                }
+                // receive input from the previous process
+                if i > 0 {
+                    close(0);
+                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
+                    assert_eq!(dup(read_end), 0);
+                }
+                // send output to the next process
+                if i < process_arguments_list.len() - 1 {
+                    close(1);
+                    let write_end = pipes_fd.get(i).unwrap()[1];
+                    assert_eq!(dup(write_end), 1);
+                }
+                // close all pipe ends inherited from the parent process
+                for pipe_fd in pipes_fd.iter() {
+                    close(pipe_fd[0]);
+                    close(pipe_fd[1]);
+                }
+                // execute new application
+                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
+                    println!("Error when executing!");
+                    exit(-4);
+                }
+                unreachable!();
+            } else {
+                children.push(pid);
+            }
+        }
+        for pipe_fd in pipes_fd.iter() {
+            close(pipe_fd[0]);
+            close(pipe_fd[1]);
+        }
+        for pid in children.into_iter() {
+            let exit_pid = waitpid(pid as usize, &mut exit_code);
+            assert_eq!(pid, exit_pid);
+            //println!("Shell: Process {} exited with code {}", pid, exit_code);
+        }
+    }
+    exit_code
+}
+
+/// Run the lines of a script, for `#!/user_shell` scripts. Lines starting
+/// with `#` are comments. Return the exit code of the last command.
+fn run_script(path: &str) -> i32 {
+    let fd = open(alloc::format!("{}\0", path).as_str(), OpenFlags::RDONLY);
+    if fd == -1 {
+        println!("Error when opening script {}", path);
+        return -1;
+    }
+    let mut script = Vec::new();
+    let mut buffer = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buffer);
+        if len <= 0 {
+            break;
+        }
+        script.extend_from_slice(&buffer[..len as usize]);
+    }
+    close(fd as usize);
+    let script = match core::str::from_utf8(&script) {
+        Ok(script) => script,
+        Err(_) => {
+            println!("Script {} is not text", path);
+            return -1;
+        }
+    };
+    let mut exit_code = 0;
+    for line in script.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || run_builtin(line) {
+            continue;
+        }
+        exit_code = run_line(line);
+    }
+    exit_code
+}
+
 #[no_mangle]
-pub fn main() -> i32 {
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return run_script(argv[1]);
+    }
     println!("Rust user shell");
     let mut line: String = String::new();
     print!("{}", LINE_START);
@@ -84,117 +258,10 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
-                    let process_arguments_list: Vec<_> = splited
-                        .iter()
-                        .map(|&cmd| ProcessArguments::new(cmd))
-                        .collect();
-                    let mut valid = true;
-                    for (i, process_args) in process_arguments_list.iter().enumerate() {
-                        if i == 0 {
-                            if !process_args.output.is_empty() {
-                                valid = false;
-                            }
-                        } else if i == process_arguments_list.len() - 1 {
-                            if !process_args.input.is_empty() {
-                                valid = false;
-                            }
-                        } else if !process_args.output.is_empty() || !process_args.input.is_empty()
-                        {
-                            valid = false;
-                        }
-                    }
-                    if process_arguments_list.len() == 1 {
-                        valid = true;
-                    }
-                    if !valid {
-                        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
-                    } else {
-                        // create pipes
-                        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
-                        if !process_arguments_list.is_empty() {
-                            for _ in 0..process_arguments_list.len() - 1 {
-                                let mut pipe_fd = [0usize; 2];
-                                pipe(&mut pipe_fd);
-                                pipes_fd.push(pipe_fd);
-                            }
-                        }
-                        let mut children: Vec<_> = Vec::new();
-                        for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
-                            if pid == 0 {
-                                let input = &process_argument.input;
-                                let output = &process_argument.output;
-                                let args_copy = &process_argument.args_copy;
-                                let args_addr = &process_argument.args_addr;
-                                // redirect input
-                                if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
-                                        return -4;
-                                    }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
-                                }
-                                // redirect output
-                                if !output.is_empty() {
-                                    let output_fd = open(
-                                        output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
-                                    );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
-                                        return -4;
-                                    }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
-                                }
-                                // receive input from the previous process
-                                if i > 0 {
-                                    close(0);
-                                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
-                                }
-                                // send output to the next process
-                                if i < process_arguments_list.len() - 1 {
-                                    close(1);
-                                    let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
-                                }
-                                // close all pipe ends inherited from the parent process
-                                for pipe_fd in pipes_fd.iter() {
-                                    close(pipe_fd[0]);
-                                    close(pipe_fd[1]);
-                                }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
-                                    return -4;
-                                }
-                                unreachable!();
-                            } else {
-                                children.push(pid);
-                            }
-                        }
-                        for pipe_fd in pipes_fd.iter() {
-                            close(pipe_fd[0]);
-                            close(pipe_fd[1]);
-                        }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
-                        }
-                    }
-                    line.clear();
+                if !line.is_empty() && !run_builtin(line.as_str()) {
+                    run_line(line.as_str());
                 }
+                line.clear();
                 print!("{}", LINE_START);
             }
             BS | DL => {
@@ -5,16 +5,39 @@
 extern crate user_lib;
 
 static TESTS: &[&str] = &[
+    "aslr_test\0",
+    "barrier_test\0",
+    "condvar_broadcast_test\0",
+    "coredump_test\0",
+    "deadlock_test\0",
+    "elf_reject\0",
+    "env_test\0",
     "exit\0",
     "fantastic_text\0",
     "forktest\0",
     "forktest2\0",
     "forktest_simple\0",
+    "fp_workers\0",
+    "futex_test\0",
     "hello_world\0",
     "matrix\0",
+    "mprotect_test\0",
+    "mqueue_test\0",
+    "musl_hello\0",
+    "named_sync_test\0",
+    "oom_test\0",
+    "pi_test\0",
+    "ptrace_test\0",
+    "rwlock_test\0",
+    "shebang_test\0",
+    "shm_prodcons\0",
     "sleep\0",
     "sleep_simple\0",
     "stack_overflow\0",
+    "strace_test\0",
+    "sync_destroy_test\0",
+    "thread_slots\0",
+    "timeout_test\0",
     "yield\0",
 ];
 
@@ -0,0 +1,102 @@
+//! Environment variables, read from the initial stack at startup and
+//! passed on by `exec`. Like `std::env`, changing them while other threads
+//! read them is undefined.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// `KEY=VALUE` strings.
+static mut ENVS: Vec<String> = Vec::new();
+
+/// Where the kernel placed envp, the auxv follows it.
+static mut ENVP: usize = 0;
+
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_HWCAP: usize = 16;
+pub const AT_RANDOM: usize = 25;
+
+/// Read a null terminated C string.
+pub(crate) fn c_str(start: usize) -> &'static str {
+    let len = (0usize..)
+        .find(|i| unsafe { ((start + *i) as *const u8).read_volatile() == 0 })
+        .unwrap();
+    core::str::from_utf8(unsafe { core::slice::from_raw_parts(start as *const u8, len) }).unwrap()
+}
+
+/// `envp` is the null terminated array the kernel placed after argv.
+pub(crate) fn init(envp: usize) {
+    let mut envs = Vec::new();
+    for i in 0.. {
+        let str_start =
+            unsafe { ((envp + i * core::mem::size_of::<usize>()) as *const usize).read_volatile() };
+        if str_start == 0 {
+            break;
+        }
+        envs.push(c_str(str_start).to_string());
+    }
+    unsafe {
+        ENVS = envs;
+        ENVP = envp;
+    }
+}
+
+/// The value of an auxv entry from the initial stack, like `getauxval`.
+pub fn auxv(key: usize) -> Option<usize> {
+    let mut entry = unsafe { ENVP } as *const usize;
+    unsafe {
+        while entry.read_volatile() != 0 {
+            entry = entry.add(1);
+        }
+        entry = entry.add(1);
+        while entry.read_volatile() != AT_NULL {
+            if entry.read_volatile() == key {
+                return Some(entry.add(1).read_volatile());
+            }
+            entry = entry.add(2);
+        }
+    }
+    None
+}
+
+fn split(env: &str) -> (&str, &str) {
+    env.split_once('=').unwrap_or((env, ""))
+}
+
+pub fn var(key: &str) -> Option<String> {
+    vars()
+        .into_iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value)
+}
+
+pub fn vars() -> Vec<(String, String)> {
+    unsafe { ENVS.iter() }
+        .map(|env| {
+            let (key, value) = split(env);
+            (key.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+pub fn set_var(key: &str, value: &str) {
+    remove_var(key);
+    unsafe {
+        ENVS.push(alloc::format!("{}={}", key, value));
+    }
+}
+
+pub fn remove_var(key: &str) {
+    unsafe {
+        ENVS.retain(|env| split(env).0 != key);
+    }
+}
+
+/// The environment as null terminated strings, for `exec`.
+pub(crate) fn c_strings() -> Vec<String> {
+    unsafe { ENVS.iter() }
+        .map(|env| alloc::format!("{}\0", env))
+        .collect()
+}
@@ -5,7 +5,9 @@
 
 #[macro_use]
 pub mod console;
+pub mod env;
 mod lang_items;
+pub mod sync;
 mod syscall;
 
 extern crate alloc;
@@ -14,6 +16,7 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::sync::atomic::AtomicU32;
 use syscall::*;
 
 const USER_HEAP_SIZE: usize = 32768;
@@ -28,6 +31,27 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
+/// ELF note telling the kernel that this program uses the native syscall
+/// table; binaries without it get the Linux one.
+#[repr(C, align(4))]
+struct AbiNote {
+    namesz: u32,
+    descsz: u32,
+    note_type: u32,
+    name: [u8; 8],
+    desc: u32,
+}
+
+#[used]
+#[link_section = ".note.rcore.abi"]
+static ABI_NOTE: AbiNote = AbiNote {
+    namesz: 6,
+    descsz: 4,
+    note_type: 1,
+    name: *b"rCore\0\0\0",
+    desc: 1,
+};
+
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
@@ -39,16 +63,10 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     for i in 0..argc {
         let str_start =
             unsafe { ((argv + i * core::mem::size_of::<usize>()) as *const usize).read_volatile() };
-        let len = (0usize..)
-            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
-            .unwrap();
-        v.push(
-            core::str::from_utf8(unsafe {
-                core::slice::from_raw_parts(str_start as *const u8, len)
-            })
-            .unwrap(),
-        );
+        v.push(env::c_str(str_start));
     }
+    // envp follows the null ending argv
+    env::init(argv + (argc + 1) * core::mem::size_of::<usize>());
     exit(main(argc, v.as_slice()));
 }
 
@@ -98,15 +116,146 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+pub const PRIORITY_MAX: usize = 31;
+pub const PRIORITY_DEFAULT: usize = 16;
+/// Set the priority of the calling thread, higher runs first and lower
+/// runs when no higher is ready or once it has waited long. Threads start
+/// with their creator's and may not go above it, -1 if they try.
+pub fn set_priority(prio: usize) -> isize {
+    sys_set_priority(prio)
+}
+/// The priority the calling thread runs at, raised above the one it set
+/// while it holds a blocking mutex a higher priority thread waits for.
+pub fn get_priority() -> isize {
+    sys_get_priority()
+}
 pub fn fork() -> isize {
     sys_fork()
 }
+/// Run `path` with the environment of this process.
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+    let envs = env::c_strings();
+    let mut envp: Vec<*const u8> = envs.iter().map(|env| env.as_ptr()).collect();
+    envp.push(core::ptr::null());
+    sys_exec(path, args, envp.as_slice())
+}
+/// Like `exec`, but `envs` are null terminated `KEY=VALUE` strings, ended
+/// by a null pointer like `args`.
+pub fn execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    sys_exec(path, args, envs)
+}
+
+bitflags! {
+    pub struct ProtFlags: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+pub fn mprotect(addr: usize, len: usize, prot: ProtFlags) -> isize {
+    sys_mprotect(addr, len, prot.bits)
+}
+
+pub const RLIMIT_CORE: usize = 4;
+pub const RLIMIT_RSS: usize = 5;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// Soft and hard limit in bytes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RLimit {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+pub fn getrlimit(resource: usize, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim as *mut _)
+}
+pub fn setrlimit(resource: usize, rlim: &RLimit) -> isize {
+    sys_setrlimit(resource, rlim as *const _)
+}
+
+bitflags! {
+    pub struct ShmFlags: u32 {
+        const CREATE = 1 << 0;
+        const EXCL = 1 << 1;
+    }
+}
+
+pub fn shm_open(key: usize, size: usize, flags: ShmFlags) -> isize {
+    sys_shm_open(key, size, flags.bits)
+}
+pub fn shm_map(shm_id: usize) -> isize {
+    sys_shm_map(shm_id)
 }
+pub fn shm_unmap(addr: usize) -> isize {
+    sys_shm_unmap(addr)
+}
+pub fn shm_close(shm_id: usize) -> isize {
+    sys_shm_close(shm_id)
+}
+/// Log the syscalls of process `pid`, 0 for this one, to the kernel's
+/// trace buffer. Children forked later are traced too.
+pub fn strace(pid: usize, enable: bool) -> isize {
+    sys_strace(pid, enable as usize)
+}
+/// Read trace lines from line `*seq` on, see `strace`. Start with
+/// `usize::MAX` to skip what is in the buffer already.
+pub fn strace_read(seq: &mut usize, buf: &mut [u8]) -> isize {
+    sys_strace_read(seq, buf)
+}
+
+bitflags! {
+    pub struct MqFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const EXCL = 1 << 7;
+        const CREATE = 1 << 9;
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+/// Open the message queue called `name`, which ends with a NUL, and return
+/// an fd for it. A queue created here holds `max_msgs` messages of up to
+/// `msg_size` bytes, 0 takes the default. -ENOENT if there is none and
+/// CREATE is not given, -EEXIST if there is one but CREATE | EXCL is.
+pub fn mq_open(name: &str, flags: MqFlags, max_msgs: usize, msg_size: usize) -> isize {
+    sys_mq_open(name, flags.bits, max_msgs, msg_size)
+}
+/// Higher priorities, below `MQ_PRIO_MAX`, are received first. -EAGAIN if
+/// the queue is full and `fd` was opened NONBLOCK.
+pub fn mq_send(fd: usize, msg: &[u8], priority: usize) -> isize {
+    sys_mq_send(fd, msg, priority, 0)
+}
+/// -ETIMEDOUT if the queue is still full after `ms` milliseconds.
+pub fn mq_timedsend(fd: usize, msg: &[u8], priority: usize, ms: usize) -> isize {
+    sys_mq_send(fd, msg, priority, ms)
+}
+/// The length of the message received into `buf`, which must hold the
+/// largest message the queue takes, or -EMSGSIZE. Its priority goes to
+/// `priority`. -EAGAIN if the queue is empty and `fd` was opened NONBLOCK,
+/// -EFAULT if `buf` or `priority` cannot be written, the message stays.
+pub fn mq_receive(fd: usize, buf: &mut [u8], priority: &mut usize) -> isize {
+    sys_mq_receive(fd, buf, priority, 0)
+}
+/// -ETIMEDOUT if the queue is still empty after `ms` milliseconds.
+pub fn mq_timedreceive(fd: usize, buf: &mut [u8], priority: &mut usize, ms: usize) -> isize {
+    sys_mq_receive(fd, buf, priority, ms)
+}
+pub fn mq_close(fd: usize) -> isize {
+    sys_close(fd)
+}
+/// Free the name, fds open on the queue keep working.
+pub fn mq_unlink(name: &str) -> isize {
+    sys_mq_unlink(name)
+}
+pub const MQ_PRIO_MAX: usize = 32;
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
+        match sys_waitpid(-1, exit_code as *mut _, 0) {
             -2 => {
                 yield_();
             }
@@ -118,7 +267,7 @@ pub fn wait(exit_code: &mut i32) -> isize {
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
+        match sys_waitpid(pid as isize, exit_code as *mut _, 0) {
             -2 => {
                 yield_();
             }
@@ -129,13 +278,56 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
 }
 
 pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+const WUNTRACED: usize = 2;
+
+/// What `waitpid_traced` found, exit codes are truncated to 8 bits and
+/// negative ones read as signals.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(i32),
+    Signaled(i32),
+    Stopped(i32),
+}
+
+impl WaitStatus {
+    /// Decode a Linux style wait status.
+    pub fn from_raw(status: i32) -> Self {
+        if status & 0x7f == 0 {
+            WaitStatus::Exited((status >> 8) & 0xff)
+        } else if status & 0xff == 0x7f {
+            WaitStatus::Stopped((status >> 8) & 0xff)
+        } else {
+            WaitStatus::Signaled(status & 0x7f)
+        }
+    }
+}
+
+/// Like `waitpid`, but also reports stops of the processes we trace,
+/// `pid` -1 means any.
+pub fn waitpid_traced(pid: isize, status: &mut WaitStatus) -> isize {
+    let mut raw: i32 = 0;
+    loop {
+        match sys_waitpid(pid, &mut raw as *mut _, WUNTRACED) {
+            -2 => {
+                yield_();
+            }
+            -1 => return -1,
+            found_pid => {
+                *status = WaitStatus::from_raw(raw);
+                return found_pid;
+            }
+        }
+    }
 }
 
 bitflags! {
     pub struct SignalFlags: i32 {
         const SIGINT    = 1 << 2;
         const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
         const SIGABRT   = 1 << 6;
         const SIGFPE    = 1 << 8;
         const SIGSEGV   = 1 << 11;
@@ -146,6 +338,44 @@ pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
+pub const PTRACE_TRACEME: usize = 0;
+pub const PTRACE_PEEKDATA: usize = 2;
+pub const PTRACE_POKEDATA: usize = 5;
+pub const PTRACE_CONT: usize = 7;
+pub const PTRACE_KILL: usize = 8;
+pub const PTRACE_SINGLESTEP: usize = 9;
+pub const PTRACE_GETREGS: usize = 12;
+pub const PTRACE_SETREGS: usize = 13;
+pub const PTRACE_ATTACH: usize = 16;
+pub const PTRACE_DETACH: usize = 17;
+pub const PTRACE_SYSCALL: usize = 24;
+/// A tracee stopped at a syscall entry or exit, not by a real SIGTRAP.
+pub const SIGTRAP_SYSCALL: i32 = 5 | 0x80;
+
+/// Registers of a stopped tracee, pc takes the place of x0.
+pub type UserRegs = [usize; 32];
+
+/// `data` is the signal to deliver for the resuming requests and the
+/// word to store for POKEDATA, see the helpers below for the others.
+/// Failures return the negated errno: -ESRCH for no stopped tracee of
+/// ours, -EIO for a bad request or tracee address, -EFAULT for bad `data`.
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    sys_ptrace(request, pid, addr, data)
+}
+pub fn ptrace_peek(pid: usize, addr: usize) -> Option<usize> {
+    let mut word = 0usize;
+    match sys_ptrace(PTRACE_PEEKDATA, pid, addr, &mut word as *mut _ as usize) {
+        0 => Some(word),
+        _ => None,
+    }
+}
+pub fn ptrace_getregs(pid: usize, regs: &mut UserRegs) -> isize {
+    sys_ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize)
+}
+pub fn ptrace_setregs(pid: usize, regs: &UserRegs) -> isize {
+    sys_ptrace(PTRACE_SETREGS, pid, 0, regs.as_ptr() as usize)
+}
+
 pub fn sleep(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
@@ -173,11 +403,42 @@ pub fn mutex_create() -> isize {
 pub fn mutex_blocking_create() -> isize {
     sys_mutex_create(true)
 }
-pub fn mutex_lock(mutex_id: usize) {
-    sys_mutex_lock(mutex_id);
+/// -EDEADLK instead of blocking forever once `enable_deadlock_detect`
+/// is on.
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
+}
+/// -EPERM unless the calling thread holds the mutex.
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+/// -EBUSY if the mutex is held.
+pub fn mutex_trylock(mutex_id: usize) -> isize {
+    sys_mutex_trylock(mutex_id)
+}
+/// -ETIMEDOUT if the mutex is still held after `ms` milliseconds.
+pub fn mutex_lock_timeout(mutex_id: usize, ms: usize) -> isize {
+    sys_mutex_lock_timeout(mutex_id, ms)
+}
+/// Free the id for reuse, -EBUSY while the mutex is held or waited for.
+/// Calls on a destroyed id fail with -EINVAL, like on one never created.
+pub fn mutex_destroy(mutex_id: usize) -> isize {
+    sys_mutex_destroy(mutex_id)
+}
+/// Open the blocking mutex called `name`, which ends with a NUL, in any
+/// process. -ENOENT if there is none and CREATE is not given, -EEXIST if
+/// there is one but CREATE | EXCL is.
+pub fn mutex_open(name: &str, flags: NamedFlags) -> isize {
+    sys_mutex_open(name, flags.bits)
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+/// Free the name, processes that opened the mutex keep using it.
+pub fn mutex_unlink(name: &str) -> isize {
+    sys_mutex_unlink(name)
+}
+/// Let go of an opened mutex, whoever else uses it. -EBUSY if the caller
+/// holds it.
+pub fn mutex_close(mutex_id: usize) -> isize {
+    sys_sync_close(SYNC_MUTEX, mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
@@ -185,8 +446,32 @@ pub fn semaphore_create(res_count: usize) -> isize {
 pub fn semaphore_up(sem_id: usize) {
     sys_semaphore_up(sem_id);
 }
-pub fn semaphore_down(sem_id: usize) {
-    sys_semaphore_down(sem_id);
+/// -EDEADLK like `mutex_lock`.
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+/// -EAGAIN if no unit is available.
+pub fn semaphore_trydown(sem_id: usize) -> isize {
+    sys_semaphore_trydown(sem_id)
+}
+/// -ETIMEDOUT if no unit becomes available within `ms` milliseconds.
+pub fn semaphore_down_timeout(sem_id: usize, ms: usize) -> isize {
+    sys_semaphore_down_timeout(sem_id, ms)
+}
+/// -EBUSY while a thread waits for a unit.
+pub fn semaphore_destroy(sem_id: usize) -> isize {
+    sys_semaphore_destroy(sem_id)
+}
+/// Like `mutex_open`, a new semaphore gets `res_count` units.
+pub fn semaphore_open(name: &str, flags: NamedFlags, res_count: usize) -> isize {
+    sys_semaphore_open(name, flags.bits, res_count)
+}
+pub fn semaphore_unlink(name: &str) -> isize {
+    sys_semaphore_unlink(name)
+}
+/// Let go of an opened semaphore, whoever else waits on it.
+pub fn semaphore_close(sem_id: usize) -> isize {
+    sys_sync_close(SYNC_SEMAPHORE, sem_id)
 }
 pub fn condvar_create() -> isize {
     sys_condvar_create(0)
@@ -194,6 +479,123 @@ pub fn condvar_create() -> isize {
 pub fn condvar_signal(condvar_id: usize) {
     sys_condvar_signal(condvar_id);
 }
+/// Wake every waiter, they get the mutex one after another.
+pub fn condvar_broadcast(condvar_id: usize) {
+    sys_condvar_broadcast(condvar_id);
+}
 pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
     sys_condvar_wait(condvar_id, mutex_id);
 }
+/// -ETIMEDOUT if nobody signals within `ms` milliseconds, the mutex is
+/// held again either way.
+pub fn condvar_wait_timeout(condvar_id: usize, mutex_id: usize, ms: usize) -> isize {
+    sys_condvar_wait_timeout(condvar_id, mutex_id, ms)
+}
+/// -EBUSY while a thread waits on the condvar.
+pub fn condvar_destroy(condvar_id: usize) -> isize {
+    sys_condvar_destroy(condvar_id)
+}
+/// Prefers writers, readers wait while a writer does.
+pub fn rwlock_create() -> isize {
+    sys_rwlock_create()
+}
+pub fn rwlock_read(rwlock_id: usize) {
+    sys_rwlock_read(rwlock_id);
+}
+pub fn rwlock_write(rwlock_id: usize) {
+    sys_rwlock_write(rwlock_id);
+}
+/// Releases a write hold or one read hold, -EPERM if there is none.
+pub fn rwlock_unlock(rwlock_id: usize) -> isize {
+    sys_rwlock_unlock(rwlock_id)
+}
+/// Trade a read hold for the write hold once the other readers are gone.
+/// -EDEADLK if another reader is already waiting to do so.
+pub fn rwlock_upgrade(rwlock_id: usize) -> isize {
+    sys_rwlock_upgrade(rwlock_id)
+}
+/// Trade the write hold for a read hold without letting a writer in.
+pub fn rwlock_downgrade(rwlock_id: usize) -> isize {
+    sys_rwlock_downgrade(rwlock_id)
+}
+/// -EBUSY while the lock is held or waited for.
+pub fn rwlock_destroy(rwlock_id: usize) -> isize {
+    sys_rwlock_destroy(rwlock_id)
+}
+/// -EINVAL if `count` is 0.
+pub fn barrier_create(count: usize) -> isize {
+    sys_barrier_create(count)
+}
+/// Wait until `count` threads do, returns 1 in the last of them.
+pub fn barrier_wait(barrier_id: usize) -> isize {
+    sys_barrier_wait(barrier_id)
+}
+/// -EBUSY while threads wait at the barrier.
+pub fn barrier_destroy(barrier_id: usize) -> isize {
+    sys_barrier_destroy(barrier_id)
+}
+
+pub const SYNC_MUTEX: usize = 0;
+pub const SYNC_SEMAPHORE: usize = 1;
+pub const SYNC_CONDVAR: usize = 2;
+pub const SYNC_RWLOCK: usize = 3;
+pub const SYNC_BARRIER: usize = 4;
+/// Open an fd for sync object `id` of `kind`, one of the SYNC_* constants.
+/// Children inherit it like any fd, it stays valid across `destroy` and
+/// the object lives until the last fd and id referring to it are gone.
+pub fn sync_export(kind: usize, id: usize) -> isize {
+    sys_sync_export(kind, id)
+}
+/// The id, among the caller's objects of its kind, of the object `fd`
+/// refers to. -EBADF if `fd` is not open, -EINVAL if it is no sync handle.
+pub fn sync_import(fd: usize) -> isize {
+    sys_sync_import(fd)
+}
+/// Drop the caller's id of an object without the checks of destroy.
+pub fn sync_close(kind: usize, id: usize) -> isize {
+    sys_sync_close(kind, id)
+}
+
+bitflags! {
+    pub struct NamedFlags: u32 {
+        const CREATE = 1 << 0;
+        const EXCL = 1 << 1;
+    }
+}
+
+/// Sleep while `futex` holds `expected`, for at most `timeout_ms`
+/// milliseconds unless that is 0. -EAGAIN if it held something else.
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout_ms: usize) -> isize {
+    sys_futex(
+        futex as *const _ as usize,
+        FUTEX_WAIT,
+        expected as usize,
+        timeout_ms,
+    )
+}
+/// Wake up to `count` threads waiting on `futex`, returns how many woke.
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> isize {
+    sys_futex(futex as *const _ as usize, FUTEX_WAKE, count, 0)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EIO: isize = 5;
+pub const EBADF: isize = 9;
+pub const EAGAIN: isize = 11;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const EEXIST: isize = 17;
+pub const EINVAL: isize = 22;
+pub const EDEADLK: isize = 35;
+pub const EMSGSIZE: isize = 90;
+pub const ETIMEDOUT: isize = 110;
+
+/// Make mutex_lock and semaphore_down of this process fail instead of
+/// deadlocking.
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
@@ -0,0 +1,188 @@
+//! Synchronization built on futexes, in the shape of `std::sync`. An
+//! uncontended lock, notify or finished `Once` is a few atomic operations,
+//! only threads that have to sleep, and those waking them, enter the kernel.
+//! A lock holder that panics takes the process with it, so there is no
+//! poisoning.
+
+use crate::{futex_wait, futex_wake};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// locked, and someone may be sleeping on it
+const CONTENDED: u32 = 2;
+
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // whoever takes it from here on may leave sleepers behind, so
+            // it is taken as CONTENDED and unlock wakes one
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                futex_wait(&self.state, CONTENDED, 0);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+pub struct Condvar {
+    /// bumped by every notify, a waiter sleeps only while it is unchanged
+    seq: AtomicU32,
+    waiters: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Like `std::sync::Condvar::wait`, wakeups may be spurious.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        // read while the mutex is held, a notify after the unlock changes it
+        let seq = self.seq.load(Ordering::SeqCst);
+        drop(guard);
+        futex_wait(&self.seq, seq, 0);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        mutex.lock()
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            futex_wake(&self.seq, 1);
+        }
+    }
+
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            futex_wake(&self.seq, usize::MAX);
+        }
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+/// running, and someone is sleeping until it is done
+const RUNNING_WAITED: u32 = 2;
+const COMPLETE: u32 = 3;
+
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// Run `f` if no call has yet, otherwise wait for the one that did to
+    /// finish.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    f();
+                    if self.state.swap(COMPLETE, Ordering::Release) == RUNNING_WAITED {
+                        futex_wake(&self.state, usize::MAX);
+                    }
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(_) => {
+                    let _ = self.state.compare_exchange(
+                        RUNNING,
+                        RUNNING_WAITED,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    );
+                    futex_wait(&self.state, RUNNING_WAITED, 0);
+                }
+            }
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
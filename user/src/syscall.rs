@@ -1,3 +1,4 @@
+use super::RLimit;
 use core::arch::asm;
 
 const SYSCALL_DUP: usize = 24;
@@ -7,28 +8,77 @@ const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_GET_PRIORITY: usize = 141;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_ENABLE_DEADLOCK_DETECT: usize = 469;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_TRYLOCK: usize = 1013;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1014;
+const SYSCALL_MUTEX_DESTROY: usize = 1015;
+const SYSCALL_MUTEX_OPEN: usize = 1016;
+const SYSCALL_MUTEX_UNLINK: usize = 1017;
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_TRYDOWN: usize = 1023;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1024;
+const SYSCALL_SEMAPHORE_DESTROY: usize = 1025;
+const SYSCALL_SEMAPHORE_OPEN: usize = 1026;
+const SYSCALL_SEMAPHORE_UNLINK: usize = 1027;
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1033;
+const SYSCALL_CONDVAR_DESTROY: usize = 1034;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1035;
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+const SYSCALL_RWLOCK_UPGRADE: usize = 1044;
+const SYSCALL_RWLOCK_DOWNGRADE: usize = 1045;
+const SYSCALL_RWLOCK_DESTROY: usize = 1046;
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+const SYSCALL_BARRIER_DESTROY: usize = 1052;
+const SYSCALL_SYNC_EXPORT: usize = 1060;
+const SYSCALL_SYNC_IMPORT: usize = 1061;
+const SYSCALL_SYNC_CLOSE: usize = 1062;
+const SYSCALL_SHM_OPEN: usize = 1100;
+const SYSCALL_SHM_MAP: usize = 1101;
+const SYSCALL_SHM_UNMAP: usize = 1102;
+const SYSCALL_SHM_CLOSE: usize = 1103;
+const SYSCALL_STRACE: usize = 1110;
+const SYSCALL_STRACE_READ: usize = 1111;
+const SYSCALL_MQ_OPEN: usize = 1120;
+const SYSCALL_MQ_SEND: usize = 1121;
+const SYSCALL_MQ_RECEIVE: usize = 1122;
+const SYSCALL_MQ_UNLINK: usize = 1123;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
+    syscall6(id, [args[0], args[1], args[2], 0, 0, 0])
+}
+
+/// Arguments go in a0 to a5, as in the Linux ABI.
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
     let mut ret: isize;
     unsafe {
         asm!(
@@ -36,6 +86,9 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
             inlateout("x10") args[0] => ret,
             in("x11") args[1],
             in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
             in("x17") id
         );
     }
@@ -69,6 +122,10 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, timeout_ms: usize) -> isize {
+    syscall6(SYSCALL_FUTEX, [uaddr, op, val, timeout_ms, 0, 0])
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
@@ -78,6 +135,10 @@ pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
 
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    syscall6(SYSCALL_PTRACE, [request, pid, addr, data, 0, 0])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
@@ -86,6 +147,14 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
+pub fn sys_set_priority(prio: usize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [prio, 0, 0])
+}
+
+pub fn sys_get_priority() -> isize {
+    syscall(SYSCALL_GET_PRIORITY, [0, 0, 0])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
@@ -98,15 +167,35 @@ pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
 
-pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
+pub fn sys_exec(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
     syscall(
         SYSCALL_EXEC,
-        [path.as_ptr() as usize, args.as_ptr() as usize, 0],
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envs.as_ptr() as usize,
+        ],
     )
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlim as usize, 0])
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: u32) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot as usize])
+}
+
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, options])
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
 }
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
@@ -133,6 +222,29 @@ pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_trylock(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_TRYLOCK, [id, 0, 0])
+}
+
+pub fn sys_mutex_lock_timeout(id: usize, ms: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK_TIMEOUT, [id, ms, 0])
+}
+
+pub fn sys_mutex_destroy(mutex_id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_DESTROY, [mutex_id, 0, 0])
+}
+
+pub fn sys_mutex_open(name: &str, flags: u32) -> isize {
+    syscall(
+        SYSCALL_MUTEX_OPEN,
+        [name.as_ptr() as usize, flags as usize, 0],
+    )
+}
+
+pub fn sys_mutex_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MUTEX_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -145,6 +257,29 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_trydown(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_TRYDOWN, [sem_id, 0, 0])
+}
+
+pub fn sys_semaphore_down_timeout(sem_id: usize, ms: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN_TIMEOUT, [sem_id, ms, 0])
+}
+
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DESTROY, [sem_id, 0, 0])
+}
+
+pub fn sys_semaphore_open(name: &str, flags: u32, res_count: usize) -> isize {
+    syscall(
+        SYSCALL_SEMAPHORE_OPEN,
+        [name.as_ptr() as usize, flags as usize, res_count],
+    )
+}
+
+pub fn sys_semaphore_unlink(name: &str) -> isize {
+    syscall(SYSCALL_SEMAPHORE_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
 pub fn sys_condvar_create(_arg: usize) -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [_arg, 0, 0])
 }
@@ -156,3 +291,140 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, ms: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_WAIT_TIMEOUT, [condvar_id, mutex_id, ms])
+}
+
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_DESTROY, [condvar_id, 0, 0])
+}
+
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_BROADCAST, [condvar_id, 0, 0])
+}
+
+pub fn sys_rwlock_create() -> isize {
+    syscall(SYSCALL_RWLOCK_CREATE, [0, 0, 0])
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_READ, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_WRITE, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_UNLOCK, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_upgrade(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_UPGRADE, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_downgrade(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_DOWNGRADE, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_destroy(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_DESTROY, [rwlock_id, 0, 0])
+}
+
+pub fn sys_barrier_create(count: usize) -> isize {
+    syscall(SYSCALL_BARRIER_CREATE, [count, 0, 0])
+}
+
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    syscall(SYSCALL_BARRIER_WAIT, [barrier_id, 0, 0])
+}
+
+pub fn sys_barrier_destroy(barrier_id: usize) -> isize {
+    syscall(SYSCALL_BARRIER_DESTROY, [barrier_id, 0, 0])
+}
+
+pub fn sys_sync_export(kind: usize, id: usize) -> isize {
+    syscall(SYSCALL_SYNC_EXPORT, [kind, id, 0])
+}
+
+pub fn sys_sync_import(fd: usize) -> isize {
+    syscall(SYSCALL_SYNC_IMPORT, [fd, 0, 0])
+}
+
+pub fn sys_sync_close(kind: usize, id: usize) -> isize {
+    syscall(SYSCALL_SYNC_CLOSE, [kind, id, 0])
+}
+
+pub fn sys_shm_open(key: usize, size: usize, flags: u32) -> isize {
+    syscall(SYSCALL_SHM_OPEN, [key, size, flags as usize])
+}
+
+pub fn sys_shm_map(shm_id: usize) -> isize {
+    syscall(SYSCALL_SHM_MAP, [shm_id, 0, 0])
+}
+
+pub fn sys_shm_unmap(addr: usize) -> isize {
+    syscall(SYSCALL_SHM_UNMAP, [addr, 0, 0])
+}
+
+pub fn sys_shm_close(shm_id: usize) -> isize {
+    syscall(SYSCALL_SHM_CLOSE, [shm_id, 0, 0])
+}
+
+pub fn sys_strace(pid: usize, enable: usize) -> isize {
+    syscall(SYSCALL_STRACE, [pid, enable, 0])
+}
+
+pub fn sys_strace_read(seq: &mut usize, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_STRACE_READ,
+        [seq as *mut _ as usize, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_mq_open(name: &str, flags: u32, max_msgs: usize, msg_size: usize) -> isize {
+    syscall6(
+        SYSCALL_MQ_OPEN,
+        [
+            name.as_ptr() as usize,
+            flags as usize,
+            max_msgs,
+            msg_size,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_send(fd: usize, msg: &[u8], priority: usize, timeout_ms: usize) -> isize {
+    syscall6(
+        SYSCALL_MQ_SEND,
+        [
+            fd,
+            msg.as_ptr() as usize,
+            msg.len(),
+            priority,
+            timeout_ms,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_receive(fd: usize, buf: &mut [u8], priority: *mut usize, timeout_ms: usize) -> isize {
+    syscall6(
+        SYSCALL_MQ_RECEIVE,
+        [
+            fd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            priority as usize,
+            timeout_ms,
+            0,
+        ],
+    )
+}
+
+pub fn sys_mq_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MQ_UNLINK, [name.as_ptr() as usize, 0, 0])
+}